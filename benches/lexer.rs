@@ -0,0 +1,92 @@
+//! Times `Lexer::tokenize` on generated programs of growing size. The lexer
+//! reads each character once, so the time should grow in step with the
+//! input: the time per byte stays about the same from row to row.
+//!
+//! Run it with `cargo bench --bench lexer`. Sizes in KiB can follow `--`,
+//! as in `cargo bench --bench lexer -- 64 128 256`; the default runs from
+//! 1 MiB to 16 MiB.
+
+use delta::lexer::Lexer;
+use std::time::{Duration, Instant};
+
+const RUNS: usize = 5;
+
+/// A program of at least `size` bytes, made of copies of a block that has
+/// a bit of everything: comments, indentation, strings with characters
+/// outside ASCII, and the multi-word keywords the lexer backtracks over.
+fn program(size: usize) -> String {
+    let mut source = String::with_capacity(size + 1024);
+    let mut i = 0;
+    while source.len() < size {
+        source += &format!(
+            "# Step {i}
+define step_{i} with total, count
+    when count is greater than or equal 10 then
+        return total
+    let label be \"étape {i}: naïve → café\"  // not ASCII
+    /* a block
+       comment */
+    repeat for each item in [1, 2.5, 3]
+        let total be total + item * 2
+    when label is not equal \"\" and not (count is less than 0) then
+        show first of [label, \"x\"]
+    return length of [total, count]
+end
+let result_{i} be step_{i} {i}, 3
+
+"
+        );
+        i += 1;
+    }
+    source
+}
+
+/// The best of a few runs, and how many tokens it made.
+fn time(source: &str) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut count = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        let tokens = Lexer::new(source).tokenize().expect("the program lexes");
+        best = best.min(start.elapsed());
+        count = tokens.len();
+    }
+    (best, count)
+}
+
+fn main() {
+    // Cargo passes `--bench`; anything else is a size
+    let mut sizes: Vec<usize> = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .map(|arg| arg.parse().expect("sizes are whole numbers of KiB"))
+        .collect();
+    if sizes.is_empty() {
+        sizes = vec![1024, 2048, 4096, 8192, 16384];
+    }
+
+    println!(
+        "{:>10} {:>10} {:>12} {:>10} {:>10}",
+        "size", "tokens", "time", "ns/byte", "MiB/s"
+    );
+    let mut first_rate = None;
+    let mut last_rate = 0.0;
+    for kib in sizes {
+        let source = program(kib * 1024);
+        let (elapsed, tokens) = time(&source);
+        let rate = elapsed.as_nanos() as f64 / source.len() as f64;
+        let throughput = source.len() as f64 / (1024.0 * 1024.0) / elapsed.as_secs_f64();
+        println!(
+            "{:>7} KiB {:>10} {:>12.2?} {:>10.2} {:>10.1}",
+            kib, tokens, elapsed, rate, throughput
+        );
+        first_rate.get_or_insert(rate);
+        last_rate = rate;
+    }
+    if let Some(first_rate) = first_rate {
+        println!(
+            "time per byte on the largest input is {:.2}x that on the smallest",
+            last_rate / first_rate
+        );
+    }
+}
@@ -0,0 +1,74 @@
+#!/usr/bin/env python3
+"""Drives `delta lsp` through a scripted session.
+
+Each line of the session file is one JSON-RPC message without the
+"jsonrpc" field. Messages with an "id" are requests: the client waits for
+the response and prints it. Notifications the server sends, such as
+diagnostics, are printed as they arrive.
+
+The script fails if a response is an error, or if the server does not exit
+with status 0 after the session, which should end with shutdown and exit.
+
+    scripts/lsp-client.py scripts/lsp-session.jsonl [path/to/delta]
+"""
+
+import json
+import subprocess
+import sys
+
+
+def send(server, message):
+    message = dict(message, jsonrpc="2.0")
+    body = json.dumps(message).encode()
+    server.stdin.write(b"Content-Length: %d\r\n\r\n" % len(body) + body)
+    server.stdin.flush()
+
+
+def receive(server):
+    length = None
+    while True:
+        line = server.stdout.readline()
+        if not line:
+            raise SystemExit("the server closed its output")
+        line = line.strip()
+        if not line:
+            break
+        name, _, value = line.decode().partition(":")
+        if name.lower() == "content-length":
+            length = int(value)
+    return json.loads(server.stdout.read(length))
+
+
+def main():
+    if len(sys.argv) not in (2, 3):
+        raise SystemExit(__doc__.strip().splitlines()[-1].strip())
+    delta = sys.argv[2] if len(sys.argv) == 3 else "./target/debug/delta"
+    server = subprocess.Popen([delta, "lsp"], stdin=subprocess.PIPE, stdout=subprocess.PIPE)
+
+    failed = False
+    with open(sys.argv[1]) as session:
+        for line in session:
+            if not line.strip():
+                continue
+            message = json.loads(line)
+            send(server, message)
+            if "id" not in message:
+                continue
+            while True:
+                reply = receive(server)
+                print(json.dumps(reply, indent=2))
+                if reply.get("id") == message["id"]:
+                    break
+            if "error" in reply:
+                failed = True
+
+    server.stdin.close()
+    status = server.wait()
+    if status != 0:
+        print("delta lsp exited with status %d" % status, file=sys.stderr)
+        failed = True
+    sys.exit(1 if failed else 0)
+
+
+if __name__ == "__main__":
+    main()
@@ -1,20 +1,53 @@
+pub use delta::span::Span;
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Program {
     pub statements: Vec<Statement>,
 }
 
+/// A statement and the source it covers, including any nested block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Statement {
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq)]
-pub enum Statement {
+pub enum StatementKind {
     Let(LetStatement),
     Show(ShowStatement),
     When(WhenStatement),
     FunctionDef(FunctionDef),
+    Return(ReturnStatement),
+    RepeatWhile(RepeatWhileStatement),
+    RepeatRange(RepeatRangeStatement),
+    RepeatEach(RepeatEachStatement),
+    Add(AddStatement),
+    Remove(RemoveStatement),
+    SetField(SetFieldStatement),
+    Break,
+    Continue,
     Expression(Expression),
+    /// A statement that failed to parse. The error has already been
+    /// reported, so later passes skip it.
+    Error,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct LetStatement {
-    pub identifier: String,
+    // More than one identifier unpacks a multi-value return: `let a, b be f`
+    pub identifiers: Vec<String>,
+    /// Where each identifier is written, in the same order
+    pub name_spans: Vec<Span>,
+    pub value: Expression,
+}
+
+/// `let user.age be 26`. `object` is everything before the last dot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SetFieldStatement {
+    pub object: Expression,
+    pub field: String,
     pub value: Expression,
 }
 
@@ -30,20 +63,125 @@ pub struct WhenStatement {
     pub otherwise_block: Option<Vec<Statement>>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatWhileStatement {
+    pub condition: Expression,
+    pub body: Vec<Statement>,
+}
+
+/// `repeat for each <variable> from <start> to <end> by <step>`. Both ends
+/// are inclusive, the range counts down when `end` is below `start`, and only
+/// the size of `step` matters.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatRangeStatement {
+    pub variable: String,
+    pub variable_span: Span,
+    pub start: Expression,
+    pub end: Expression,
+    pub step: Option<Expression>,
+    pub body: Vec<Statement>,
+}
+
+/// `repeat for each <variable> in <list>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RepeatEachStatement {
+    pub variable: String,
+    pub variable_span: Span,
+    pub list: Expression,
+    pub body: Vec<Statement>,
+}
+
+/// `add <item> to <list>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddStatement {
+    pub item: Expression,
+    pub list: Expression,
+}
+
+/// `remove <item> from <list>` drops the first element equal to `item`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoveStatement {
+    pub item: Expression,
+    pub list: Expression,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct FunctionDef {
     pub name: String,
+    pub name_span: Span,
     pub parameters: Vec<String>,
+    pub parameter_spans: Vec<Span>,
     pub body: Vec<Statement>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Expression {
+pub struct ReturnStatement {
+    pub values: Vec<Expression>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub kind: ExpressionKind,
+    pub span: Span,
+}
+
+impl Expression {
+    pub fn new(kind: ExpressionKind, span: Span) -> Self {
+        Expression { kind, span }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExpressionKind {
     Number(f64),
     String(String),
+    Boolean(bool),
+    Nothing,
     Identifier(String),
     BinaryOp(BinaryOperation),
     FunctionCall(FunctionCall),
+    List(Vec<Expression>),
+    Index(IndexExpression),
+    ListQuery(ListQuery),
+    Object(Vec<ObjectField>),
+    Member(MemberAccess),
+    Not(Box<Expression>),
+}
+
+/// One `name: value` entry of an object literal.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectField {
+    pub name: String,
+    pub name_span: Span,
+    pub value: Expression,
+}
+
+/// `object.field`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberAccess {
+    pub object: Box<Expression>,
+    pub field: String,
+}
+
+/// `list[index]`, counting from zero.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexExpression {
+    pub list: Box<Expression>,
+    pub index: Box<Expression>,
+}
+
+/// `length of`, `first of` and `last of`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListQuery {
+    pub query: ListQueryKind,
+    pub list: Box<Expression>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListQueryKind {
+    Length,
+    First,
+    Last,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -65,6 +203,30 @@ pub enum BinaryOperator {
     Subtract,
     Multiply,
     Divide,
+    // `and` and `or` only evaluate their right side when it decides the result
+    And,
+    Or,
+}
+
+/// Spelled the way the operator is written in source.
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let spelling = match self {
+            BinaryOperator::GreaterThan => "is greater than",
+            BinaryOperator::LessThan => "is less than",
+            BinaryOperator::GreaterThanOrEqual => "is greater than or equal",
+            BinaryOperator::LessThanOrEqual => "is less than or equal",
+            BinaryOperator::Equal => "is equal",
+            BinaryOperator::NotEqual => "is not equal",
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::And => "and",
+            BinaryOperator::Or => "or",
+        };
+        write!(f, "{}", spelling)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -0,0 +1,496 @@
+//! Command line parsing for the `delta` binary:
+//!
+//! ```text
+//! delta run hello.de
+//! delta build -O2 -o hello hello.de
+//! delta check --message-format=json src/*.de
+//! delta emit --ir hello.de
+//! delta fmt --check src/*.de
+//! ```
+
+use crate::codegen::OptLevel;
+
+pub const USAGE: &str = "\
+Usage: delta <command> [options] <file.de>...
+       delta repl [--jit]
+       delta lsp
+
+Commands:
+  run      Run programs with the interpreter
+  build    Compile programs to native executables
+  check    Report errors without producing any output
+  repl     Start an interactive session (with --jit, entries are compiled)
+  fmt      Rewrite programs in the standard layout
+  lsp      Start a language server for editors on stdin and stdout
+  emit     Write one stage of compilation, chosen with one of:
+             --tokens  --ast  --ir  --bc  --asm  --obj
+  help     Show this message
+
+Options:
+  -o <path>                      Where to write the output (one input only,
+                                 '-' for stdout with fmt, --tokens, --ast and
+                                 --ir)
+  -O0, -O1, -O2, -O3             Optimisation level of compiled code
+                                 (default -O0)
+  -Os, -Oz                       Optimise for size, -Oz more aggressively
+  --emit <kind[=path],...>       With 'build': also keep intermediate files,
+                                 any of ir, bc, asm and obj, written next to
+                                 the executable unless a path is given
+  -v, --verbose                  Report each step on stderr
+  -g                             Include debug information for gdb and lldb
+  --dump-ir                      Also write the IR before and after optimisation
+                                 next to the source, as .unopt.ll and .opt.ll
+  --target <triple>              Target to compile for (default: this machine)
+  --jit                          With 'run' and 'repl': compile to machine code
+                                 and run that instead of interpreting
+  --check                        With 'fmt': only report files that are not
+                                 formatted, without changing them
+  --linker <program>             Program used to link (default: cc, or
+                                 link.exe for MSVC targets)
+  --message-format <human|json>  How to print errors and warnings
+  --version                      Show the compiler version
+
+Exit codes:
+  0  success
+  1  a program has errors, or with 'fmt --check', is not formatted
+  2  the command line is invalid
+  3  a file or tool could not be used";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Run,
+    Build,
+    Check,
+    Repl,
+    Lsp,
+    Fmt,
+    Emit(Emit),
+}
+
+/// The stage of compilation written by `delta emit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Ir,
+    Bc,
+    Asm,
+    Obj,
+}
+
+impl Emit {
+    /// The extension of the file written next to the source when no `-o` is
+    /// given. Tokens and the AST go to stdout instead.
+    pub fn extension(self) -> Option<&'static str> {
+        match self {
+            Emit::Tokens | Emit::Ast => None,
+            Emit::Ir => Some("ll"),
+            Emit::Bc => Some("bc"),
+            Emit::Asm => Some("s"),
+            Emit::Obj => Some("o"),
+        }
+    }
+}
+
+/// How diagnostics are printed, chosen with `--message-format`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageFormat {
+    /// Annotated source snippets for people
+    Human,
+    /// One JSON object per line for tools
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub inputs: Vec<String>,
+    pub output: Option<String>,
+    /// Intermediate files `build` keeps, each with its own path if given.
+    pub artifacts: Vec<(Emit, Option<String>)>,
+    pub verbose: bool,
+    pub opt_level: OptLevel,
+    pub dump_ir: bool,
+    pub debug_info: bool,
+    pub jit: bool,
+    /// With `fmt`: report unformatted files instead of rewriting them.
+    pub check: bool,
+    pub target: Option<String>,
+    /// The linker from `--linker`; the target's default when `None`.
+    pub linker: Option<String>,
+    pub message_format: MessageFormat,
+}
+
+/// What the command line asks for.
+#[derive(Debug, PartialEq)]
+pub enum Invocation {
+    Compile(Options),
+    Help,
+    Version,
+}
+
+/// Parses the arguments after the program name. The error is a message
+/// for the user.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Invocation, String> {
+    let mut args = args.into_iter();
+
+    let command = match args.next().as_deref() {
+        Some("run") => Command::Run,
+        Some("build") => Command::Build,
+        Some("check") => Command::Check,
+        Some("repl") => Command::Repl,
+        Some("lsp") => Command::Lsp,
+        Some("fmt") => Command::Fmt,
+        // The stage is filled in from its flag below
+        Some("emit") => Command::Emit(Emit::Ir),
+        Some("help" | "--help" | "-h") | None => return Ok(Invocation::Help),
+        Some("--version" | "-V") => return Ok(Invocation::Version),
+        Some(other) => return Err(format!("unknown command '{}'", other)),
+    };
+
+    let mut emit = None;
+    let mut inputs = Vec::new();
+    let mut output = None;
+    let mut artifacts = Vec::new();
+    let mut verbose = false;
+    let mut opt_level = OptLevel::O0;
+    let mut opt_flag = None;
+    let mut dump_ir = false;
+    let mut debug_info = false;
+    let mut jit = false;
+    let mut check = false;
+    let mut target = None;
+    let mut linker = None;
+    let mut message_format = MessageFormat::Human;
+
+    while let Some(arg) = args.next() {
+        // Flags taking a value accept both `--flag value` and `--flag=value`
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_string(), Some(value.to_string()))
+            }
+            _ => (arg.clone(), None),
+        };
+        let mut value = |name: &str| match inline_value.clone().or_else(|| args.next()) {
+            Some(value) => Ok(value),
+            None => Err(format!("'{}' needs a value", name)),
+        };
+
+        match flag.as_str() {
+            "--tokens" | "--ast" | "--ir" | "--bc" | "--asm" | "--obj" => {
+                if !matches!(command, Command::Emit(_)) {
+                    return Err(format!("'{}' can only be used with 'delta emit'", flag));
+                }
+                if emit.is_some() {
+                    return Err("'delta emit' writes one stage at a time".to_string());
+                }
+                emit = Some(match flag.as_str() {
+                    "--tokens" => Emit::Tokens,
+                    "--ast" => Emit::Ast,
+                    "--ir" => Emit::Ir,
+                    "--bc" => Emit::Bc,
+                    "--asm" => Emit::Asm,
+                    _ => Emit::Obj,
+                });
+            }
+            "-o" => output = Some(value("-o")?),
+            "-O0" | "-O1" | "-O2" | "-O3" | "-Os" | "-Oz" => {
+                opt_level = match flag.as_str() {
+                    "-O0" => OptLevel::O0,
+                    "-O1" => OptLevel::O1,
+                    "-O2" => OptLevel::O2,
+                    "-O3" => OptLevel::O3,
+                    "-Os" => OptLevel::Os,
+                    _ => OptLevel::Oz,
+                };
+                opt_flag = Some(flag);
+            }
+            "--dump-ir" => dump_ir = true,
+            "-g" => debug_info = true,
+            "-v" | "--verbose" => verbose = true,
+            "--emit" => {
+                if command != Command::Build {
+                    return Err("'--emit' can only be used with 'delta build'".to_string());
+                }
+                for artifact in value("--emit")?.split(',') {
+                    let (kind, path) = match artifact.split_once('=') {
+                        Some((kind, path)) => (kind, Some(path.to_string())),
+                        None => (artifact, None),
+                    };
+                    let emit = match kind {
+                        "ir" => Emit::Ir,
+                        "bc" => Emit::Bc,
+                        "asm" => Emit::Asm,
+                        "obj" => Emit::Obj,
+                        other => {
+                            return Err(format!(
+                                "unknown kind '{}' for '--emit': expected ir, bc, asm or obj",
+                                other
+                            ));
+                        }
+                    };
+                    artifacts.push((emit, path));
+                }
+            }
+            "--jit" => jit = true,
+            "--check" => {
+                if command != Command::Fmt {
+                    return Err("'--check' can only be used with 'delta fmt'".to_string());
+                }
+                check = true;
+            }
+            "--target" => target = Some(value("--target")?),
+            "--linker" => linker = Some(value("--linker")?),
+            "--message-format" => {
+                message_format = match value("--message-format")?.as_str() {
+                    "human" => MessageFormat::Human,
+                    "json" => MessageFormat::Json,
+                    other => {
+                        return Err(format!(
+                            "unknown message format '{}': expected 'human' or 'json'",
+                            other
+                        ));
+                    }
+                }
+            }
+            _ if arg.starts_with("-o") => output = Some(arg[2..].to_string()),
+            _ if arg.starts_with('-') && arg != "-" => {
+                return Err(format!("unknown option '{}'", arg));
+            }
+            _ => inputs.push(arg),
+        }
+    }
+
+    let command = match command {
+        Command::Emit(_) => match emit {
+            Some(emit) => Command::Emit(emit),
+            None => {
+                return Err(
+                    "'delta emit' needs one of --tokens, --ast, --ir, --bc, --asm or --obj"
+                        .to_string(),
+                );
+            }
+        },
+        command => command,
+    };
+
+    if matches!(command, Command::Repl | Command::Lsp) {
+        if !inputs.is_empty() {
+            let name = if command == Command::Repl {
+                "repl"
+            } else {
+                "lsp"
+            };
+            return Err(format!("'delta {}' does not take input files", name));
+        }
+    } else if inputs.is_empty() {
+        return Err("no input files".to_string());
+    }
+    if jit {
+        if !matches!(command, Command::Run | Command::Repl) {
+            return Err("'--jit' can only be used with 'delta run' and 'delta repl'".to_string());
+        }
+        if target.is_some() {
+            return Err("'--jit' runs on this machine and cannot take '--target'".to_string());
+        }
+    }
+    // The interpreter has nothing to optimise
+    if let Some(flag) = opt_flag
+        && matches!(command, Command::Run | Command::Repl)
+        && !jit
+    {
+        return Err(format!(
+            "'{}' only applies to compiled code: add '--jit' to compile the program",
+            flag
+        ));
+    }
+    if output.is_some() {
+        if !matches!(command, Command::Build | Command::Emit(_) | Command::Fmt) {
+            return Err(
+                "'-o' can only be used with 'delta build', 'delta emit' and 'delta fmt'"
+                    .to_string(),
+            );
+        }
+        if check {
+            return Err("'--check' writes nothing, so it cannot take '-o'".to_string());
+        }
+        if inputs.len() > 1 {
+            return Err("'-o' needs exactly one input file".to_string());
+        }
+    }
+
+    if inputs.len() > 1 && artifacts.iter().any(|(_, path)| path.is_some()) {
+        return Err("'--emit' paths need exactly one input file".to_string());
+    }
+
+    Ok(Invocation::Compile(Options {
+        command,
+        inputs,
+        output,
+        artifacts,
+        verbose,
+        opt_level,
+        dump_ir,
+        debug_info,
+        jit,
+        check,
+        target,
+        linker,
+        message_format,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_args(args: &[&str]) -> Result<Invocation, String> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn compile_options(args: &[&str]) -> Options {
+        match parse_args(args) {
+            Ok(Invocation::Compile(options)) => options,
+            other => panic!("{:?} gave {:?}", args, other),
+        }
+    }
+
+    #[test]
+    fn commands_and_flags_are_accepted() {
+        let cases: &[(&[&str], Command, &[&str])] = &[
+            (&["run", "a.de"], Command::Run, &["a.de"]),
+            (&["run", "--jit", "a.de"], Command::Run, &["a.de"]),
+            (&["run", "--jit", "-O3", "a.de"], Command::Run, &["a.de"]),
+            (
+                &["build", "-O2", "-o", "out", "a.de"],
+                Command::Build,
+                &["a.de"],
+            ),
+            (
+                &["check", "a.de", "b.de"],
+                Command::Check,
+                &["a.de", "b.de"],
+            ),
+            (
+                &["emit", "--tokens", "a.de"],
+                Command::Emit(Emit::Tokens),
+                &["a.de"],
+            ),
+            (
+                &["emit", "a.de", "--obj"],
+                Command::Emit(Emit::Obj),
+                &["a.de"],
+            ),
+            (
+                &["fmt", "--check", "a.de", "b.de"],
+                Command::Fmt,
+                &["a.de", "b.de"],
+            ),
+            (&["fmt", "-o", "-", "a.de"], Command::Fmt, &["a.de"]),
+            (&["repl", "--jit"], Command::Repl, &[]),
+            (&["lsp"], Command::Lsp, &[]),
+        ];
+        for (args, command, inputs) in cases {
+            let options = compile_options(args);
+            assert_eq!(options.command, *command, "{:?}", args);
+            assert_eq!(options.inputs, *inputs, "{:?}", args);
+        }
+
+        assert_eq!(parse_args(&[]), Ok(Invocation::Help));
+        assert_eq!(parse_args(&["--help"]), Ok(Invocation::Help));
+        assert_eq!(parse_args(&["--version"]), Ok(Invocation::Version));
+    }
+
+    #[test]
+    fn flag_values_are_read_either_way() {
+        let options = compile_options(&[
+            "build",
+            "-Oz",
+            "-oout",
+            "--target=aarch64-linux-gnu",
+            "a.de",
+        ]);
+        assert_eq!(options.opt_level, OptLevel::Oz);
+        assert_eq!(options.output.as_deref(), Some("out"));
+        assert_eq!(options.target.as_deref(), Some("aarch64-linux-gnu"));
+
+        let options = compile_options(&["check", "--message-format", "json", "-v", "-g", "a.de"]);
+        assert_eq!(options.message_format, MessageFormat::Json);
+        assert!(options.verbose && options.debug_info);
+
+        let options = compile_options(&["build", "--emit=ir,obj=keep.o", "--dump-ir", "a.de"]);
+        assert_eq!(
+            options.artifacts,
+            [(Emit::Ir, None), (Emit::Obj, Some("keep.o".to_string()))]
+        );
+        assert!(options.dump_ir);
+    }
+
+    #[test]
+    fn conflicting_or_misplaced_flags_are_rejected() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["frobnicate", "a.de"], "unknown command 'frobnicate'"),
+            (&["run"], "no input files"),
+            (
+                &["run", "--frobnicate", "a.de"],
+                "unknown option '--frobnicate'",
+            ),
+            (&["run", "a.de", "-o"], "'-o' needs a value"),
+            (&["repl", "a.de"], "'delta repl' does not take input files"),
+            (&["lsp", "a.de"], "'delta lsp' does not take input files"),
+            (&["emit", "a.de"], "needs one of --tokens"),
+            (&["emit", "--ir", "--asm", "a.de"], "one stage at a time"),
+            (
+                &["run", "--ir", "a.de"],
+                "'--ir' can only be used with 'delta emit'",
+            ),
+            (&["build", "--jit", "a.de"], "'--jit' can only be used with"),
+            (
+                &["run", "--jit", "--target", "x86_64-linux-gnu", "a.de"],
+                "cannot take '--target'",
+            ),
+            (
+                &["run", "--check", "a.de"],
+                "'--check' can only be used with 'delta fmt'",
+            ),
+            (
+                &["fmt", "--check", "-o", "out.de", "a.de"],
+                "cannot take '-o'",
+            ),
+            (&["run", "-o", "out", "a.de"], "'-o' can only be used with"),
+            (&["repl", "-o", "out"], "'-o' can only be used with"),
+            (&["lsp", "-o", "out"], "'-o' can only be used with"),
+            (
+                &["run", "-O2", "a.de"],
+                "'-O2' only applies to compiled code",
+            ),
+            (&["repl", "-Oz"], "'-Oz' only applies to compiled code"),
+            (
+                &["build", "-o", "out", "a.de", "b.de"],
+                "'-o' needs exactly one input file",
+            ),
+            (
+                &["run", "--emit", "ir", "a.de"],
+                "'--emit' can only be used with 'delta build'",
+            ),
+            (
+                &["build", "--emit", "exe", "a.de"],
+                "unknown kind 'exe' for '--emit'",
+            ),
+            (
+                &["build", "--emit=ir=a.ll", "a.de", "b.de"],
+                "'--emit' paths need exactly one",
+            ),
+            (
+                &["check", "--message-format=xml", "a.de"],
+                "unknown message format 'xml'",
+            ),
+        ];
+        for (args, message) in cases {
+            match parse_args(args) {
+                Err(error) => assert!(error.contains(message), "{:?} gave '{}'", args, error),
+                Ok(invocation) => panic!("{:?} was accepted as {:?}", args, invocation),
+            }
+        }
+    }
+}
@@ -1,37 +1,270 @@
 use crate::ast::*;
-use inkwell::FloatPredicate;
+use delta::diagnostics::{Code, Diagnostic, codes};
+use inkwell::basic_block::BasicBlock;
 use inkwell::builder::Builder;
 use inkwell::context::Context;
 use inkwell::module::Module;
-use inkwell::types::FloatType;
-use inkwell::values::{FloatValue, FunctionValue, PointerValue};
-use std::collections::HashMap;
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FloatType, PointerType};
+use inkwell::values::{
+    BasicMetadataValueEnum, BasicValueEnum, FloatValue, FunctionValue, IntValue, PointerValue,
+};
+use inkwell::{FloatPredicate, IntPredicate};
+use std::collections::{BTreeMap, HashMap};
 use std::error::Error;
 use std::fmt;
+use std::mem::{self, Discriminant};
+use std::path::Path;
 
-#[derive(Debug)]
-pub struct CodegenError {
-    message: String,
+mod debug;
+mod jit;
+mod native;
+mod optimize;
+mod runtime;
+mod uncalled;
+
+pub use jit::JitSession;
+pub use native::{NativeFormat, link, target_machine};
+pub use optimize::OptLevel;
+
+fn error(code: Code, message: impl Into<String>) -> Box<dyn Error> {
+    Box::new(Diagnostic::error(code, message))
+}
+
+/// Points an error at `span` unless it already points somewhere. LLVM's own
+/// errors become internal compiler errors.
+fn locate(error: Box<dyn Error>, span: Span) -> Box<dyn Error> {
+    match error.downcast::<Diagnostic>() {
+        Ok(diagnostic) => Box::new(diagnostic.locate(span)),
+        Err(error) => Box::new(
+            Diagnostic::error(codes::INTERNAL, error.to_string())
+                .locate(span)
+                .with_help("this is a bug in the compiler"),
+        ),
+    }
+}
+
+/// Whether `expr` makes a new list or object, which no other name can hold
+/// yet.
+fn is_fresh(expr: &Expression) -> bool {
+    matches!(
+        expr.kind,
+        ExpressionKind::List(_) | ExpressionKind::Object(_)
+    )
+}
+
+/// The static type of a compiled value.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum VariableType {
+    Float,
+    String,
+    /// An `i1`.
+    Boolean,
+    /// `nothing`, which is always a null pointer.
+    Nothing,
+    /// A list of elements of one type. The element type is `None` for an
+    /// empty literal until the first `add` fills it in.
+    List(Option<Box<VariableType>>),
+    /// An object with the given fields. Fields can only be added through the
+    /// variable the object was made in while no other name holds it, so no
+    /// copy of the type is left without them.
+    Object(BTreeMap<String, VariableType>),
+}
+
+impl VariableType {
+    fn list_of(element: VariableType) -> Self {
+        VariableType::List(Some(Box::new(element)))
+    }
+
+    /// The type's name as used in symbol names, without spaces.
+    fn mangled(&self) -> String {
+        match self {
+            VariableType::List(Some(element)) => format!("list_of_{}", element.mangled()),
+            VariableType::Object(fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, field_type)| format!("{}:{}", name, field_type.mangled()))
+                    .collect();
+                format!("object({})", fields.join(","))
+            }
+            other => other.to_string(),
+        }
+    }
+
+    /// The type a value has where paths that give it `self` and `other`
+    /// meet, or `None` when no one type describes both. A list whose items
+    /// are not known yet takes the item type from the other path, and an
+    /// object keeps the fields it has on both.
+    fn merge(&self, other: &VariableType) -> Option<VariableType> {
+        match (self, other) {
+            (VariableType::List(None), VariableType::List(items))
+            | (VariableType::List(items), VariableType::List(None)) => {
+                Some(VariableType::List(items.clone()))
+            }
+            (VariableType::List(Some(left)), VariableType::List(Some(right))) => {
+                left.merge(right).map(VariableType::list_of)
+            }
+            (VariableType::Object(left), VariableType::Object(right)) => {
+                let fields = left
+                    .iter()
+                    .filter_map(|(name, field_type)| {
+                        let merged = field_type.merge(right.get(name)?)?;
+                        Some((name.clone(), merged))
+                    })
+                    .collect();
+                Some(VariableType::Object(fields))
+            }
+            _ if self == other => Some(self.clone()),
+            _ => None,
+        }
+    }
+
+    /// `self` with the item types it does not know yet taken from `other`.
+    fn filled_from(&self, other: &VariableType) -> VariableType {
+        match (self, other) {
+            (VariableType::List(None), VariableType::List(Some(_))) => other.clone(),
+            (VariableType::List(Some(items)), VariableType::List(Some(other_items))) => {
+                VariableType::list_of(items.filled_from(other_items))
+            }
+            (VariableType::Object(fields), VariableType::Object(other_fields)) => {
+                VariableType::Object(
+                    fields
+                        .iter()
+                        .map(|(name, field_type)| {
+                            let filled = match other_fields.get(name) {
+                                Some(other_type) => field_type.filled_from(other_type),
+                                None => field_type.clone(),
+                            };
+                            (name.clone(), filled)
+                        })
+                        .collect(),
+                )
+            }
+            _ => self.clone(),
+        }
+    }
+
+    /// Whether some list in the type does not know its item type yet.
+    fn has_unknown_items(&self) -> bool {
+        match self {
+            VariableType::List(None) => true,
+            VariableType::List(Some(items)) => items.has_unknown_items(),
+            VariableType::Object(fields) => fields.values().any(VariableType::has_unknown_items),
+            _ => false,
+        }
+    }
 }
 
-impl fmt::Display for CodegenError {
+impl fmt::Display for VariableType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.message)
+        match self {
+            VariableType::Float => write!(f, "number"),
+            VariableType::String => write!(f, "string"),
+            VariableType::Boolean => write!(f, "boolean"),
+            VariableType::Nothing => write!(f, "nothing"),
+            VariableType::List(Some(element)) => write!(f, "list of {}", element),
+            VariableType::List(None) => write!(f, "list"),
+            VariableType::Object(fields) => {
+                write!(f, "object {{")?;
+                for (index, (name, field_type)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, " {}: {}", name, field_type)?;
+                }
+                write!(f, " }}")
+            }
+        }
     }
 }
 
-impl Error for CodegenError {}
+/// A stack slot holding a local variable.
+#[derive(Clone)]
+struct Variable<'ctx> {
+    pointer: PointerValue<'ctx>,
+    var_type: VariableType,
+    /// Whether other names may hold the same list or object. Its static type
+    /// can then no longer be refined through this one, as theirs would be
+    /// left behind.
+    shared: bool,
+}
+
+/// The variables visible at some point of the program, by name.
+type Scope<'ctx> = HashMap<String, Variable<'ctx>>;
+
+/// The result of compiling an expression, together with its static type.
+struct TypedValue<'ctx> {
+    value: BasicValueEnum<'ctx>,
+    var_type: VariableType,
+}
+
+/// Identifies one compiled version of a function: its name and the types of
+/// the arguments it was called with.
+type InstanceKey = (String, Vec<VariableType>);
+
+/// A compiled function. `return_types` is empty for functions that return
+/// nothing and has several entries for `return a, b`, which is lowered to a
+/// struct return.
+#[derive(Clone)]
+struct FunctionInstance<'ctx> {
+    function: FunctionValue<'ctx>,
+    return_types: Vec<VariableType>,
+}
+
+/// How `return` statements are lowered in the body being compiled.
+enum ReturnMode {
+    /// A first pass over the body that only records the returned types.
+    Infer,
+    /// The return types are known and `return` emits a real `ret`.
+    Emit(Vec<VariableType>),
+}
+
+/// Where `continue` and `break` jump to inside a loop, and the variables as
+/// they were at each jump seen so far.
+struct LoopTargets<'ctx> {
+    continue_block: BasicBlock<'ctx>,
+    break_block: BasicBlock<'ctx>,
+    continue_states: Vec<Scope<'ctx>>,
+    break_states: Vec<Scope<'ctx>>,
+}
 
-enum VariableType<'ctx> {
-    Float(PointerValue<'ctx>),
-    String(PointerValue<'ctx>),
+/// The function whose body is currently being compiled.
+struct FunctionFrame {
+    key: InstanceKey,
+    mode: ReturnMode,
 }
 
 pub struct CodeGenerator<'ctx> {
     context: &'ctx Context,
     module: Module<'ctx>,
     builder: Builder<'ctx>,
-    variables: HashMap<String, VariableType<'ctx>>,
+    // Locals visible at the point being compiled. Each function gets a
+    // fresh map so bodies cannot see the caller's variables, and each block
+    // gets a copy that is dropped at its end.
+    variables: Scope<'ctx>,
+    // Stack slots of the function being compiled, one per variable name and
+    // kind of value, so that every path giving a variable the same kind of
+    // value stores it in the same place.
+    slots: HashMap<(String, Discriminant<VariableType>), PointerValue<'ctx>>,
+    // Every `define` seen so far.
+    functions: HashMap<String, FunctionDef>,
+    // Compiled functions, one per distinct list of argument types.
+    instances: HashMap<InstanceKey, FunctionInstance<'ctx>>,
+    // Functions whose return types are still being inferred, with the types
+    // returned by the first `return` seen so far.
+    inferring: HashMap<InstanceKey, Option<Vec<VariableType>>>,
+    // `None` while compiling the top level of the program.
+    frame: Option<FunctionFrame>,
+    // Innermost loop last.
+    loops: Vec<LoopTargets<'ctx>>,
+    // Problems that do not stop compilation, in the order they were found.
+    warnings: Vec<Diagnostic>,
+    // Set by `enable_debug_info`.
+    debug: Option<debug::DebugInfo<'ctx>>,
+    // Set by `record_types`: the type of each expression and binding
+    // compiled, described for people.
+    types: Option<Vec<(Span, String)>>,
+    // Set for the REPL's JIT: what is kept from one entry to the next.
+    session: Option<jit::SessionState<'ctx>>,
 }
 
 impl<'ctx> CodeGenerator<'ctx> {
@@ -44,465 +277,2203 @@ impl<'ctx> CodeGenerator<'ctx> {
             module,
             builder,
             variables: HashMap::new(),
+            slots: HashMap::new(),
+            functions: HashMap::new(),
+            instances: HashMap::new(),
+            inferring: HashMap::new(),
+            frame: None,
+            loops: Vec::new(),
+            warnings: Vec::new(),
+            debug: None,
+            types: None,
+            session: None,
         })
     }
 
+    /// Returns the warnings found so far, leaving none behind.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Makes `compile` note the type of every expression, variable and
+    /// parameter it compiles, and the signature of every function instance,
+    /// for `take_types`. Must come before `compile`.
+    pub fn record_types(&mut self) {
+        self.types = Some(Vec::new());
+    }
+
+    /// Returns the types noted so far, by the span they belong to. A span
+    /// can appear more than once, as function bodies are compiled once per
+    /// combination of argument types.
+    pub fn take_types(&mut self) -> Vec<(Span, String)> {
+        self.types.take().unwrap_or_default()
+    }
+
+    fn note_type(&mut self, span: Span, description: impl FnOnce() -> String) {
+        if let Some(types) = &mut self.types {
+            types.push((span, description()));
+        }
+    }
+
+    /// Records a warning. Function bodies can be compiled several times, so
+    /// repeats are dropped.
+    fn warn(&mut self, warning: Diagnostic) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
     fn get_float_type(&self) -> FloatType<'ctx> {
         self.context.f64_type()
     }
 
+    fn get_string_type(&self) -> PointerType<'ctx> {
+        self.context
+            .i8_type()
+            .ptr_type(inkwell::AddressSpace::default())
+    }
+
+    fn llvm_type(&self, var_type: &VariableType) -> BasicTypeEnum<'ctx> {
+        match var_type {
+            VariableType::Float => self.get_float_type().into(),
+            VariableType::Boolean => self.context.bool_type().into(),
+            VariableType::String
+            | VariableType::Nothing
+            | VariableType::List(_)
+            | VariableType::Object(_) => self.get_string_type().into(),
+        }
+    }
+
+    fn current_function(&self) -> FunctionValue<'ctx> {
+        self.builder
+            .get_insert_block()
+            .unwrap()
+            .get_parent()
+            .unwrap()
+    }
+
     pub fn compile(&mut self, program: &Program) -> Result<(), Box<dyn Error>> {
         // Create main function
         let main_type = self.context.i32_type().fn_type(&[], false);
         let main_fn = self.module.add_function("main", main_type, None);
         let basic_block = self.context.append_basic_block(main_fn, "entry");
         self.builder.position_at_end(basic_block);
+        self.debug_enter_function(main_fn, "main", Some(Span::default()));
 
         // Add printf declaration for output
         self.add_printf_declaration();
+        self.add_exit_declaration();
+
+        // Functions may be called before the line that defines them
+        self.register_functions(&program.statements)?;
 
         // Compile all statements
-        for statement in &program.statements {
-            self.compile_statement(statement)?;
-        }
+        self.compile_statements(&program.statements)?;
 
         // Return 0 from main
         let return_value = self.context.i32_type().const_int(0, false);
         self.builder.build_return(Some(&return_value))?;
 
+        // Functions that are never called are still checked and emitted,
+        // with parameter types guessed from their bodies
+        let mut uncalled: Vec<FunctionDef> = self
+            .functions
+            .values()
+            .filter(|def| !self.instances.keys().any(|(name, _)| *name == def.name))
+            .cloned()
+            .collect();
+        uncalled.sort_by(|a, b| a.name.cmp(&b.name));
+        for func_def in &uncalled {
+            let param_types = uncalled::guess_parameter_types(func_def);
+            self.instantiate_function(func_def, &param_types)?;
+        }
+
+        self.debug_leave_function();
+        self.finish_debug_info();
+
         // Verify the module
         if let Err(errors) = self.module.verify() {
-            return Err(Box::new(CodegenError {
-                message: format!("Module verification failed: {}", errors),
-            }));
+            return Err(Box::new(
+                Diagnostic::error(
+                    codes::INTERNAL,
+                    format!("Module verification failed: {}", errors),
+                )
+                .with_help("this is a bug in the compiler"),
+            ));
         }
 
-        println!("LLVM IR generated successfully!");
-        self.module.print_to_stderr();
+        Ok(())
+    }
 
+    /// Records the top-level `define` blocks of a program so that calls can
+    /// be resolved regardless of definition order.
+    fn register_functions(&mut self, statements: &[Statement]) -> Result<(), Box<dyn Error>> {
+        for statement in statements {
+            if let StatementKind::FunctionDef(func_def) = &statement.kind {
+                if let Some(first) = self.functions.get(&func_def.name) {
+                    return Err(Box::new(
+                        Diagnostic::error(
+                            codes::DUPLICATE_FUNCTION,
+                            format!("Function '{}' is defined more than once", func_def.name),
+                        )
+                        .with_primary(func_def.name_span, "defined again here")
+                        .with_secondary(first.name_span, "first defined here"),
+                    ));
+                }
+                self.functions
+                    .insert(func_def.name.clone(), func_def.clone());
+            }
+        }
         Ok(())
     }
 
     fn add_printf_declaration(&mut self) {
-        let i8_type = self.context.i8_type();
-        let i8_ptr_type = i8_type.ptr_type(inkwell::AddressSpace::default());
-        let printf_type = self.context.i32_type().fn_type(&[i8_ptr_type.into()], true);
+        let printf_type = self
+            .context
+            .i32_type()
+            .fn_type(&[self.get_string_type().into()], true);
         self.module.add_function("printf", printf_type, None);
     }
 
-    fn compile_statement(&mut self, statement: &Statement) -> Result<(), Box<dyn Error>> {
-        match statement {
-            Statement::Let(let_stmt) => {
-                match &let_stmt.value {
-                    Expression::String(s) => {
-                        // Handle string variables
-                        let global_str = self
-                            .builder
-                            .build_global_string_ptr(s, &format!("str_{}", let_stmt.identifier))?;
-                        let str_ptr = self.builder.build_alloca(
-                            self.context
-                                .i8_type()
-                                .ptr_type(inkwell::AddressSpace::default()),
-                            &let_stmt.identifier,
-                        )?;
-                        self.builder
-                            .build_store(str_ptr, global_str.as_pointer_value())?;
-                        self.variables
-                            .insert(let_stmt.identifier.clone(), VariableType::String(str_ptr));
-                    }
-                    _ => {
-                        // Handle numeric variables
-                        let value = self.compile_expression(&let_stmt.value)?;
-                        let alloca = self
-                            .builder
-                            .build_alloca(self.get_float_type(), &let_stmt.identifier)?;
-                        self.builder.build_store(alloca, value)?;
-                        self.variables
-                            .insert(let_stmt.identifier.clone(), VariableType::Float(alloca));
-                    }
-                }
+    fn add_exit_declaration(&mut self) {
+        let exit_type = self
+            .context
+            .void_type()
+            .fn_type(&[self.context.i32_type().into()], false);
+        self.module.add_function("exit", exit_type, None);
+    }
+
+    /// Ends the current block with code that reports `message` on stderr
+    /// and exits the program with status 1.
+    fn build_runtime_error(&mut self, message: &str) -> Result<(), Box<dyn Error>> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.get_string_type();
+        let fflush_fn = self.libc_function("fflush", i32_type.fn_type(&[ptr_type.into()], false));
+        let write_fn = self.libc_function(
+            "write",
+            i64_type.fn_type(&[i32_type.into(), ptr_type.into(), i64_type.into()], false),
+        );
+        let exit_fn = self.module.get_function("exit").unwrap();
+
+        // Output printed so far comes first, as it does in the interpreter
+        let stdio = ptr_type.const_null();
+        self.builder.build_call(fflush_fn, &[stdio.into()], "")?;
+        let text = format!("Runtime error: {}\n", message);
+        let pointer = self.builder.build_global_string_ptr(&text, "error")?;
+        let stderr = i32_type.const_int(2, false);
+        let length = i64_type.const_int(text.len() as u64, false);
+        self.builder.build_call(
+            write_fn,
+            &[
+                stderr.into(),
+                pointer.as_pointer_value().into(),
+                length.into(),
+            ],
+            "",
+        )?;
+        let status = i32_type.const_int(1, false);
+        self.builder.build_call(exit_fn, &[status.into()], "")?;
+        self.builder.build_unreachable()?;
+        Ok(())
+    }
+
+    /// Allocates a stack slot in the entry block of the current function, so
+    /// that every local lives in a single alloca regardless of where it is
+    /// first assigned.
+    fn create_entry_block_alloca(
+        &self,
+        var_type: &VariableType,
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, Box<dyn Error>> {
+        let entry = self.current_function().get_first_basic_block().unwrap();
+        let entry_builder = self.context.create_builder();
+        match entry.get_first_instruction() {
+            Some(instruction) => entry_builder.position_before(&instruction),
+            None => entry_builder.position_at_end(entry),
+        }
+        Ok(entry_builder.build_alloca(self.llvm_type(var_type), name)?)
+    }
+
+    /// Binds `name` to `value`, reusing the variable's slot when it keeps
+    /// the same kind of value and switching to the slot for the new kind
+    /// otherwise.
+    fn store_variable(
+        &mut self,
+        name: &str,
+        value: TypedValue<'ctx>,
+        shared: bool,
+    ) -> Result<(), Box<dyn Error>> {
+        let kind = mem::discriminant(&value.var_type);
+        let pointer = match self.variables.get_mut(name) {
+            Some(variable) if mem::discriminant(&variable.var_type) == kind => {
+                variable.var_type = value.var_type;
+                variable.shared = shared;
+                variable.pointer
             }
-            Statement::Show(show_stmt) => {
-                match &show_stmt.value {
-                    Expression::String(_) => {
-                        self.compile_expression(&show_stmt.value)?;
-                        // The string expression will handle its own printing
-                    }
-                    Expression::Identifier(name) => {
-                        if let Some(var_type) = self.variables.get(name) {
-                            match var_type {
-                                VariableType::String(ptr) => {
-                                    let loaded = self.builder.build_load(
-                                        self.context
-                                            .i8_type()
-                                            .ptr_type(inkwell::AddressSpace::default()),
-                                        *ptr,
-                                        name,
-                                    )?;
-
-                                    // Create format string for printing with newline
-                                    let format_str =
-                                        self.builder.build_global_string_ptr("%s\n", "fmt_str")?;
-
-                                    let printf_fn = self.module.get_function("printf").unwrap();
-                                    self.builder.build_call(
-                                        printf_fn,
-                                        &[format_str.as_pointer_value().into(), loaded.into()],
-                                        "printf_str_var",
-                                    )?;
-                                }
-                                VariableType::Float(ptr) => {
-                                    let loaded = self.builder.build_load(
-                                        self.get_float_type(),
-                                        *ptr,
-                                        name,
-                                    )?;
-                                    self.generate_print_call(loaded.into_float_value())?;
-                                }
+            _ => {
+                let pointer = match self.slots.get(&(name.to_string(), kind)) {
+                    Some(pointer) => *pointer,
+                    None => {
+                        let pointer = match self.session_global(&value.var_type, name) {
+                            Some(global) => global,
+                            None => {
+                                let pointer =
+                                    self.create_entry_block_alloca(&value.var_type, name)?;
+                                self.debug_variable(name, pointer, &value.var_type);
+                                pointer
                             }
-                        } else {
-                            return Err(Box::new(CodegenError {
-                                message: format!("Undefined variable: {}", name),
-                            }));
-                        }
-                    }
-                    _ => {
-                        let value = self.compile_expression(&show_stmt.value)?;
-                        self.generate_print_call(value)?;
+                        };
+                        self.slots.insert((name.to_string(), kind), pointer);
+                        pointer
                     }
+                };
+                self.variables.insert(
+                    name.to_string(),
+                    Variable {
+                        pointer,
+                        var_type: value.var_type,
+                        shared,
+                    },
+                );
+                pointer
+            }
+        };
+        self.builder.build_store(pointer, value.value)?;
+        Ok(())
+    }
+
+    /// Notes that the value of `expr` is being copied somewhere else, such
+    /// as into another variable or a list, so that the variable it comes from
+    /// is shared from now on.
+    fn share(&mut self, expr: &Expression) {
+        if let ExpressionKind::Identifier(name) = &expr.kind
+            && let Some(variable) = self.variables.get_mut(name)
+        {
+            variable.shared = true;
+        }
+    }
+
+    /// Returns true once the current block already ends in a terminator, in
+    /// which case no further instructions may be added to it.
+    fn block_is_terminated(&self) -> bool {
+        self.builder
+            .get_insert_block()
+            .and_then(|block| block.get_terminator())
+            .is_some()
+    }
+
+    /// Compiles a sequence of statements, stopping once control cannot reach
+    /// the next one (after a `return`, for instance).
+    fn compile_statements(&mut self, statements: &[Statement]) -> Result<(), Box<dyn Error>> {
+        for (index, stmt) in statements.iter().enumerate() {
+            if self.block_is_terminated() {
+                let unreachable = &statements[index..];
+                let span = stmt.span.to(unreachable[unreachable.len() - 1].span);
+                self.warn(
+                    Diagnostic::warning(codes::UNREACHABLE_CODE, "Unreachable code")
+                        .with_primary(span, "this can never run")
+                        .with_secondary(statements[index - 1].span, "control never gets past this"),
+                );
+                break;
+            }
+            self.compile_statement(stmt)?;
+        }
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<(), Box<dyn Error>> {
+        self.debug_location(statement.span);
+        self.lower_statement(statement)
+            .map_err(|error| locate(error, statement.span))
+    }
+
+    fn lower_statement(&mut self, statement: &Statement) -> Result<(), Box<dyn Error>> {
+        match &statement.kind {
+            StatementKind::Let(let_stmt) => {
+                self.share(&let_stmt.value);
+                let values = self.compile_values(&let_stmt.value)?;
+                if values.len() != let_stmt.identifiers.len() {
+                    return Err(error(
+                        codes::VALUE_COUNT,
+                        format!(
+                            "Expected {} value(s) to assign to {}, found {}",
+                            let_stmt.identifiers.len(),
+                            let_stmt.identifiers.join(", "),
+                            values.len()
+                        ),
+                    ));
+                }
+                for ((identifier, span), value) in let_stmt
+                    .identifiers
+                    .iter()
+                    .zip(&let_stmt.name_spans)
+                    .zip(values)
+                {
+                    self.note_type(*span, || value.var_type.to_string());
+                    self.store_variable(identifier, value, !is_fresh(&let_stmt.value))?;
                 }
             }
-            Statement::When(when_stmt) => {
+            StatementKind::Show(show_stmt) => {
+                let value = self.compile_expression(&show_stmt.value)?;
+                self.generate_print_call(value)?;
+            }
+            StatementKind::When(when_stmt) => {
                 self.compile_when_statement(when_stmt)?;
             }
-            Statement::FunctionDef(_) => {
-                // TODO: Implement function definitions
-                println!("Function definitions not yet implemented in compiler");
+            StatementKind::FunctionDef(func_def) => {
+                // Nested definitions become visible once they are reached
+                self.functions
+                    .entry(func_def.name.clone())
+                    .or_insert_with(|| func_def.clone());
+            }
+            StatementKind::Return(return_stmt) => {
+                self.compile_return_statement(return_stmt)?;
+            }
+            StatementKind::RepeatWhile(_)
+            | StatementKind::RepeatRange(_)
+            | StatementKind::RepeatEach(_) => {
+                if self
+                    .variables
+                    .values()
+                    .any(|variable| variable.var_type.has_unknown_items())
+                {
+                    self.settle_loop_types(statement)?;
+                }
+                self.compile_loop(statement)?;
+            }
+            StatementKind::Add(add_stmt) => {
+                self.compile_add_statement(add_stmt)?;
+            }
+            StatementKind::Remove(remove_stmt) => {
+                self.compile_remove_statement(remove_stmt)?;
+            }
+            StatementKind::SetField(set_stmt) => {
+                self.compile_set_field_statement(set_stmt)?;
+            }
+            StatementKind::Break => {
+                let state = self.variables.clone();
+                let targets = self.innermost_loop();
+                targets.break_states.push(state);
+                let target = targets.break_block;
+                self.builder.build_unconditional_branch(target)?;
             }
-            Statement::Expression(expr) => {
-                self.compile_expression(expr)?;
+            StatementKind::Continue => {
+                let state = self.variables.clone();
+                let targets = self.innermost_loop();
+                targets.continue_states.push(state);
+                let target = targets.continue_block;
+                self.builder.build_unconditional_branch(target)?;
             }
+            StatementKind::Expression(expr) => {
+                // Calls are allowed to produce any number of values here
+                self.compile_values(expr)?;
+            }
+            // Already reported by the parser, which stops the compile
+            StatementKind::Error => {}
         }
         Ok(())
     }
 
-    fn compile_when_statement(&mut self, when_stmt: &WhenStatement) -> Result<(), Box<dyn Error>> {
-        let condition = self.compile_expression(&when_stmt.condition)?;
+    fn compile_return_statement(
+        &mut self,
+        return_stmt: &ReturnStatement,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut values = Vec::with_capacity(return_stmt.values.len());
+        for value in &return_stmt.values {
+            self.share(value);
+            values.push(self.compile_expression(value)?);
+        }
+        let types: Vec<VariableType> = values.iter().map(|value| value.var_type.clone()).collect();
 
-        let current_fn = self
-            .builder
-            .get_insert_block()
-            .unwrap()
-            .get_parent()
-            .unwrap();
-        let then_block = self.context.append_basic_block(current_fn, "then");
-        let else_block = self.context.append_basic_block(current_fn, "else");
-        let merge_block = self.context.append_basic_block(current_fn, "merge");
+        let frame = self
+            .frame
+            .as_ref()
+            .expect("the parser rejects 'return' outside of functions");
+        let name = frame.key.0.clone();
 
-        // Convert the condition to a boolean (assume it's a float comparison result)
-        let zero = self.get_float_type().const_float(0.0);
-        let cond_bool = self.builder.build_float_compare(
-            FloatPredicate::ONE, // not equal to zero (true)
-            condition,
-            zero,
-            "cond",
-        )?;
+        match &frame.mode {
+            ReturnMode::Infer => {
+                let seen = self
+                    .inferring
+                    .get_mut(&frame.key)
+                    .expect("functions being inferred are tracked");
+                match seen {
+                    Some(expected) if *expected != types => {
+                        return Err(Self::inconsistent_returns(&name, expected, &types));
+                    }
+                    Some(_) => {}
+                    None => *seen = Some(types),
+                }
+                // The inference pass is thrown away, so any terminator will do
+                self.builder.build_return(None)?;
+            }
+            ReturnMode::Emit(expected) => {
+                if *expected != types {
+                    return Err(Self::inconsistent_returns(&name, expected, &types));
+                }
+                match values.len() {
+                    0 => self.builder.build_return(None)?,
+                    1 => self.builder.build_return(Some(&values[0].value))?,
+                    _ => {
+                        let fields: Vec<BasicValueEnum> =
+                            values.iter().map(|value| value.value).collect();
+                        self.builder.build_aggregate_return(&fields)?
+                    }
+                };
+            }
+        }
 
-        self.builder
-            .build_conditional_branch(cond_bool, then_block, else_block)?;
+        Ok(())
+    }
 
-        // Compile then block
-        self.builder.position_at_end(then_block);
-        for stmt in &when_stmt.then_block {
-            self.compile_statement(stmt)?;
+    fn inconsistent_returns(
+        name: &str,
+        expected: &[VariableType],
+        found: &[VariableType],
+    ) -> Box<dyn Error> {
+        error(
+            codes::RETURN_MISMATCH,
+            format!(
+                "Function '{}' returns ({}) in one place and ({}) in another",
+                name,
+                Self::describe_types(expected),
+                Self::describe_types(found)
+            ),
+        )
+    }
+
+    fn describe_types(types: &[VariableType]) -> String {
+        if types.is_empty() {
+            return "nothing".to_string();
         }
-        self.builder.build_unconditional_branch(merge_block)?;
+        types
+            .iter()
+            .map(|var_type| var_type.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
 
-        // Compile else block
-        self.builder.position_at_end(else_block);
-        if let Some(otherwise_block) = &when_stmt.otherwise_block {
-            for stmt in otherwise_block {
-                self.compile_statement(stmt)?;
+    /// Compiles an expression that may produce any number of values. Only
+    /// calls to functions with `return a, b` produce more than one, and only
+    /// calls to functions without a `return` value produce none.
+    fn compile_values(
+        &mut self,
+        expr: &Expression,
+    ) -> Result<Vec<TypedValue<'ctx>>, Box<dyn Error>> {
+        let values = match &expr.kind {
+            ExpressionKind::FunctionCall(call) => self.compile_call(&call.name, &call.arguments),
+            ExpressionKind::Identifier(name) if self.names_function(name) => {
+                self.compile_call(name, &[])
+            }
+            _ => return Ok(vec![self.compile_expression(expr)?]),
+        };
+        values.map_err(|error| locate(error, expr.span))
+    }
+
+    /// A bare identifier that is not a variable but a function is a call
+    /// without arguments.
+    fn names_function(&self, name: &str) -> bool {
+        !self.variables.contains_key(name) && self.functions.contains_key(name)
+    }
+
+    /// Compiles a call to a `define`d function, returning one value per
+    /// value in its `return` statements.
+    fn compile_call(
+        &mut self,
+        name: &str,
+        arguments: &[Expression],
+    ) -> Result<Vec<TypedValue<'ctx>>, Box<dyn Error>> {
+        let func_def = match self.functions.get(name) {
+            Some(func_def) => func_def.clone(),
+            None => {
+                return Err(error(
+                    codes::UNDEFINED_FUNCTION,
+                    format!("Undefined function: {}", name),
+                ));
             }
+        };
+
+        if func_def.parameters.len() != arguments.len() {
+            return Err(Box::new(
+                Diagnostic::error(
+                    codes::ARGUMENT_COUNT,
+                    format!(
+                        "Function '{}' expects {} argument(s), but {} were given",
+                        name,
+                        func_def.parameters.len(),
+                        arguments.len()
+                    ),
+                )
+                .with_secondary(func_def.name_span, "defined here"),
+            ));
         }
-        self.builder.build_unconditional_branch(merge_block)?;
 
-        // Continue from merge block
-        self.builder.position_at_end(merge_block);
+        let mut values = Vec::with_capacity(arguments.len());
+        for argument in arguments {
+            self.share(argument);
+            values.push(self.compile_expression(argument)?);
+        }
 
-        Ok(())
+        let param_types: Vec<VariableType> =
+            values.iter().map(|value| value.var_type.clone()).collect();
+        let (function, return_types) = self.instantiate_function(&func_def, &param_types)?;
+
+        let args: Vec<BasicMetadataValueEnum> =
+            values.iter().map(|value| value.value.into()).collect();
+        let call = self.builder.build_call(function, &args, "")?;
+
+        let result = match call.try_as_basic_value().left() {
+            Some(result) => result,
+            None => return Ok(Vec::new()),
+        };
+        if return_types.len() == 1 {
+            return Ok(vec![TypedValue {
+                value: result,
+                var_type: return_types[0].clone(),
+            }]);
+        }
+
+        let mut results = Vec::with_capacity(return_types.len());
+        for (index, var_type) in return_types.into_iter().enumerate() {
+            let value = self.builder.build_extract_value(
+                result.into_struct_value(),
+                index as u32,
+                "ret",
+            )?;
+            results.push(TypedValue { value, var_type });
+        }
+        Ok(results)
     }
 
-    fn compile_expression(
+    /// Returns the compiled version of `func_def` for the given argument
+    /// types, compiling it on first use. Each combination of argument types
+    /// gets its own LLVM function, so parameters always have a concrete type.
+    ///
+    /// The return types are found by compiling the body once into a scratch
+    /// function that only records what each `return` produces. A recursive
+    /// call made during that pass commits to the types returned so far, so
+    /// the base case has to come before the recursion.
+    fn instantiate_function(
         &mut self,
-        expr: &Expression,
-    ) -> Result<FloatValue<'ctx>, Box<dyn Error>> {
-        match expr {
-            Expression::Number(n) => Ok(self.get_float_type().const_float(*n)),
-            Expression::String(s) => {
-                // For strings, we need special handling for output
-                // For now, we'll just print the string directly
-                let printf_fn = self.module.get_function("printf").unwrap();
-                let string_ptr = self
-                    .builder
-                    .build_global_string_ptr(&format!("{}\n", s), "str")?;
-                self.builder.build_call(
-                    printf_fn,
-                    &[string_ptr.as_pointer_value().into()],
-                    "printf_string",
-                )?;
+        func_def: &FunctionDef,
+        param_types: &[VariableType],
+    ) -> Result<(FunctionValue<'ctx>, Vec<VariableType>), Box<dyn Error>> {
+        let key = (func_def.name.clone(), param_types.to_vec());
+        if let Some(instance) = self.instances.get(&key) {
+            return Ok((instance.function, instance.return_types.clone()));
+        }
 
-                // Return 0 as the numeric value
-                Ok(self.get_float_type().const_float(0.0))
-            }
-            Expression::Identifier(name) => {
-                if let Some(var_type) = self.variables.get(name) {
-                    match var_type {
-                        VariableType::Float(ptr) => {
-                            let loaded =
-                                self.builder.build_load(self.get_float_type(), *ptr, name)?;
-                            Ok(loaded.into_float_value())
-                        }
-                        VariableType::String(_) => {
-                            // String identifiers used in expressions evaluate to 0
-                            Ok(self.get_float_type().const_float(0.0))
-                        }
-                    }
-                } else {
-                    Err(Box::new(CodegenError {
-                        message: format!("Undefined variable: {}", name),
-                    }))
-                }
-            }
-            Expression::BinaryOp(binop) => {
-                let left = self.compile_expression(&binop.left)?;
-                let right = self.compile_expression(&binop.right)?;
+        if let Some(seen) = self.inferring.get(&key) {
+            let return_types = seen.clone().unwrap_or_default();
+            let function = self.declare_function(func_def, param_types, &return_types, "");
+            self.instances.insert(
+                key,
+                FunctionInstance {
+                    function,
+                    return_types: return_types.clone(),
+                },
+            );
+            return Ok((function, return_types));
+        }
 
-                match binop.operator {
-                    BinaryOperator::Add => Ok(self.builder.build_float_add(left, right, "add")?),
-                    BinaryOperator::Subtract => {
-                        Ok(self.builder.build_float_sub(left, right, "sub")?)
-                    }
-                    BinaryOperator::Multiply => {
-                        Ok(self.builder.build_float_mul(left, right, "mul")?)
-                    }
-                    BinaryOperator::Divide => {
-                        Ok(self.builder.build_float_div(left, right, "div")?)
-                    }
-                    BinaryOperator::GreaterThan => {
-                        let cmp = self.builder.build_float_compare(
-                            FloatPredicate::OGT,
-                            left,
-                            right,
-                            "gt",
-                        )?;
-                        let result = self.builder.build_unsigned_int_to_float(
-                            cmp,
-                            self.get_float_type(),
-                            "gt_float",
-                        )?;
-                        Ok(result)
-                    }
-                    BinaryOperator::LessThan => {
-                        let cmp = self.builder.build_float_compare(
-                            FloatPredicate::OLT,
-                            left,
-                            right,
-                            "lt",
-                        )?;
-                        let result = self.builder.build_unsigned_int_to_float(
-                            cmp,
-                            self.get_float_type(),
-                            "lt_float",
-                        )?;
-                        Ok(result)
-                    }
-                    BinaryOperator::GreaterThanOrEqual => {
-                        let cmp = self.builder.build_float_compare(
-                            FloatPredicate::OGE,
-                            left,
-                            right,
-                            "gte",
-                        )?;
-                        let result = self.builder.build_unsigned_int_to_float(
-                            cmp,
-                            self.get_float_type(),
-                            "gte_float",
-                        )?;
-                        Ok(result)
-                    }
-                    BinaryOperator::LessThanOrEqual => {
-                        let cmp = self.builder.build_float_compare(
-                            FloatPredicate::OLE,
-                            left,
-                            right,
-                            "lte",
-                        )?;
-                        let result = self.builder.build_unsigned_int_to_float(
-                            cmp,
-                            self.get_float_type(),
-                            "lte_float",
-                        )?;
-                        Ok(result)
-                    }
-                    BinaryOperator::Equal => {
-                        let cmp = self.builder.build_float_compare(
-                            FloatPredicate::OEQ,
-                            left,
-                            right,
-                            "eq",
-                        )?;
-                        let result = self.builder.build_unsigned_int_to_float(
-                            cmp,
-                            self.get_float_type(),
-                            "eq_float",
-                        )?;
-                        Ok(result)
-                    }
-                    BinaryOperator::NotEqual => {
-                        let cmp = self.builder.build_float_compare(
-                            FloatPredicate::ONE,
-                            left,
-                            right,
-                            "ne",
-                        )?;
-                        let result = self.builder.build_unsigned_int_to_float(
-                            cmp,
-                            self.get_float_type(),
-                            "ne_float",
-                        )?;
-                        Ok(result)
-                    }
-                }
+        self.inferring.insert(key.clone(), None);
+        let scratch = self.declare_function(func_def, param_types, &[], ".infer");
+        let inferred = self.compile_function(scratch, &key, func_def, ReturnMode::Infer);
+        // SAFETY: nothing refers to the scratch function once its body is done
+        unsafe { scratch.delete() };
+        let seen = self.inferring.remove(&key).unwrap_or_default();
+        inferred?;
+        let return_types = seen.unwrap_or_default();
+
+        let function = match self.instances.get(&key) {
+            Some(instance) if instance.return_types != return_types => {
+                return Err(Box::new(
+                    Diagnostic::error(
+                        codes::RECURSIVE_RETURN_TYPE,
+                        format!(
+                            "Cannot work out what '{}' returns: it calls itself before reaching a 'return'",
+                            func_def.name
+                        ),
+                    )
+                    .with_help("return a value before the recursive call, as in a base case"),
+                ));
             }
-            Expression::FunctionCall(_) => {
-                // TODO: Implement function calls
-                Ok(self.get_float_type().const_float(0.0))
+            Some(instance) => instance.function,
+            None => {
+                let function = self.declare_function(func_def, param_types, &return_types, "");
+                self.instances.insert(
+                    key.clone(),
+                    FunctionInstance {
+                        function,
+                        return_types: return_types.clone(),
+                    },
+                );
+                function
+            }
+        };
+
+        self.compile_function(
+            function,
+            &key,
+            func_def,
+            ReturnMode::Emit(return_types.clone()),
+        )?;
+        self.note_type(func_def.name_span, || {
+            Self::describe_instance(func_def, param_types, &return_types)
+        });
+        Ok((function, return_types))
+    }
+
+    /// A compiled function's signature, as in `area with width: number,
+    /// height: number returns number`.
+    fn describe_instance(
+        func_def: &FunctionDef,
+        param_types: &[VariableType],
+        return_types: &[VariableType],
+    ) -> String {
+        let mut description = func_def.name.clone();
+        if !param_types.is_empty() {
+            let parameters: Vec<String> = func_def
+                .parameters
+                .iter()
+                .zip(param_types)
+                .map(|(name, param_type)| format!("{}: {}", name, param_type))
+                .collect();
+            description += &format!(" with {}", parameters.join(", "));
+        }
+        format!(
+            "{} returns {}",
+            description,
+            Self::describe_types(return_types)
+        )
+    }
+
+    fn declare_function(
+        &self,
+        func_def: &FunctionDef,
+        param_types: &[VariableType],
+        return_types: &[VariableType],
+        suffix: &str,
+    ) -> FunctionValue<'ctx> {
+        let llvm_params: Vec<BasicMetadataTypeEnum> = param_types
+            .iter()
+            .map(|param_type| self.llvm_type(param_type).into())
+            .collect();
+        let fn_type = match return_types {
+            [] => self.context.void_type().fn_type(&llvm_params, false),
+            [single] => self.llvm_type(single).fn_type(&llvm_params, false),
+            several => {
+                let fields: Vec<BasicTypeEnum> = several
+                    .iter()
+                    .map(|var_type| self.llvm_type(var_type))
+                    .collect();
+                self.context
+                    .struct_type(&fields, false)
+                    .fn_type(&llvm_params, false)
             }
+        };
+
+        // `delta.greet.string`: the dot keeps Delta names apart from C symbols
+        let mut symbol = format!("delta.{}", func_def.name);
+        for param_type in param_types {
+            symbol.push('.');
+            symbol.push_str(&param_type.mangled());
         }
+        // A REPL entry may define a function again, so its symbols are its own
+        if let Some(session) = &self.session {
+            symbol.push_str(&format!(".{}", session.entry));
+        }
+        symbol.push_str(suffix);
+        self.module.add_function(&symbol, fn_type, None)
     }
 
-    fn generate_print_call(&mut self, value: FloatValue<'ctx>) -> Result<(), Box<dyn Error>> {
-        let printf_fn = self.module.get_function("printf").unwrap();
+    /// Lowers a `define` block into `function`. The body is compiled against
+    /// a fresh variable scope with the builder position restored afterwards.
+    fn compile_function(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        key: &InstanceKey,
+        func_def: &FunctionDef,
+        mode: ReturnMode,
+    ) -> Result<(), Box<dyn Error>> {
+        let saved_block = self.builder.get_insert_block();
+        let saved_location = self.debug_current_location();
+        let saved_variables = std::mem::take(&mut self.variables);
+        let saved_slots = std::mem::take(&mut self.slots);
+        // Scratch bodies are thrown away, so they get no debug information
+        let debug_span = matches!(mode, ReturnMode::Emit(_)).then_some(func_def.name_span);
+        self.debug_enter_function(function, &func_def.name, debug_span);
+        let saved_frame = self.frame.replace(FunctionFrame {
+            key: key.clone(),
+            mode,
+        });
+        let saved_loops = std::mem::take(&mut self.loops);
 
-        // Create format string for printing floats
-        let format_str = self.builder.build_global_string_ptr("%.16g\n", "fmt")?;
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
 
-        self.builder.build_call(
-            printf_fn,
-            &[format_str.as_pointer_value().into(), value.into()],
-            "printf_call",
-        )?;
+        let result = self.compile_function_body(function, func_def, &key.1);
 
-        Ok(())
-    }
+        self.debug_leave_function();
+        self.debug_set_location(saved_location);
+        self.variables = saved_variables;
+        self.slots = saved_slots;
+        self.frame = saved_frame;
+        self.loops = saved_loops;
+        if let Some(block) = saved_block {
+            self.builder.position_at_end(block);
+        }
+        result?;
+
+        // A subprogram stays incomplete until the debug info is finalized, so
+        // functions that have one are left to the module-wide check
+        let described = self.debug.is_some() && debug_span.is_some();
+        if !described && !function.verify(false) {
+            return Err(Box::new(
+                Diagnostic::error(
+                    codes::INTERNAL,
+                    format!("Invalid code generated for function '{}'", func_def.name),
+                )
+                .with_primary(func_def.name_span, "")
+                .with_help("this is a bug in the compiler"),
+            ));
+        }
 
-    pub fn save_to_file(&self, filename: &str) -> Result<(), Box<dyn Error>> {
-        self.module.print_to_file(filename)?;
         Ok(())
     }
 
-    // Keep the interpreter for comparison
-    pub fn interpret(&mut self, program: &Program) -> Result<(), String> {
-        let mut interpreter_vars: HashMap<String, String> = HashMap::new();
+    fn compile_function_body(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        func_def: &FunctionDef,
+        param_types: &[VariableType],
+    ) -> Result<(), Box<dyn Error>> {
+        for (((name, span), param_type), param) in func_def
+            .parameters
+            .iter()
+            .zip(&func_def.parameter_spans)
+            .zip(param_types)
+            .zip(function.get_param_iter())
+        {
+            param.set_name(name);
+            self.note_type(*span, || param_type.to_string());
+            // The caller keeps its own copy of the argument
+            self.store_variable(
+                name,
+                TypedValue {
+                    value: param,
+                    var_type: param_type.clone(),
+                },
+                true,
+            )?;
+        }
+
+        self.compile_statements(&func_def.body)?;
 
-        for statement in &program.statements {
-            self.interpret_statement(statement, &mut interpreter_vars)?;
+        if !self.block_is_terminated() {
+            let returns_values = matches!(
+                self.frame.as_ref().map(|frame| &frame.mode),
+                Some(ReturnMode::Emit(types)) if !types.is_empty()
+            );
+            if returns_values {
+                return Err(Box::new(
+                    Diagnostic::error(
+                        codes::RETURN_MISMATCH,
+                        format!(
+                            "Function '{}' can reach its end without returning a value",
+                            func_def.name
+                        ),
+                    )
+                    .with_primary(func_def.name_span, "")
+                    .with_help("add a 'return' at the end of the function"),
+                ));
+            }
+            self.builder.build_return(None)?;
         }
+
         Ok(())
     }
 
-    fn interpret_statement(
-        &self,
-        statement: &Statement,
-        variables: &mut HashMap<String, String>,
-    ) -> Result<(), String> {
-        match statement {
-            Statement::Show(show) => {
-                let value = self.evaluate_expression(&show.value, variables)?;
-                println!("{}", value);
-            }
-            Statement::Let(let_stmt) => {
-                let value = self.evaluate_expression(&let_stmt.value, variables)?;
-                variables.insert(let_stmt.identifier.clone(), value);
-            }
-            Statement::When(when_stmt) => {
-                let condition_result = self.evaluate_expression(&when_stmt.condition, variables)?;
-                if condition_result.contains("true") || condition_result.contains("True") {
-                    for stmt in &when_stmt.then_block {
-                        self.interpret_statement(stmt, variables)?;
-                    }
-                } else if let Some(otherwise_block) = &when_stmt.otherwise_block {
-                    for stmt in otherwise_block {
-                        self.interpret_statement(stmt, variables)?;
-                    }
-                }
-            }
-            Statement::FunctionDef(func_def) => {
-                println!("Defined function: {}", func_def.name);
+    /// Compiles a condition down to an `i1`. `and`, `or` and `not` are
+    /// lowered to branches here rather than computed on numbers.
+    fn compile_condition(&mut self, expr: &Expression) -> Result<IntValue<'ctx>, Box<dyn Error>> {
+        match &expr.kind {
+            ExpressionKind::BinaryOp(binop)
+                if matches!(binop.operator, BinaryOperator::And | BinaryOperator::Or) =>
+            {
+                return self.compile_short_circuit(binop);
             }
-            Statement::Expression(expr) => {
-                let _value = self.evaluate_expression(expr, variables)?;
+            ExpressionKind::Not(operand) => {
+                let operand = self.compile_condition(operand)?;
+                return Ok(self.builder.build_not(operand, "not")?);
             }
+            _ => {}
         }
-        Ok(())
+
+        let condition = self.compile_expression(expr)?;
+        self.build_truthiness(condition)
     }
 
-    fn evaluate_expression(
-        &self,
-        expression: &Expression,
-        variables: &HashMap<String, String>,
-    ) -> Result<String, String> {
-        match expression {
-            Expression::Number(n) => Ok(n.to_string()),
-            Expression::String(s) => Ok(s.clone()),
-            Expression::Identifier(name) => {
-                if let Some(value) = variables.get(name) {
-                    Ok(value.clone())
-                } else {
-                    Ok(format!("<undefined: {}>", name))
+    /// Decides whether a value counts as true in a condition: `false`,
+    /// `nothing`, zero, the empty string and the empty list do not; every
+    /// other value, including every object, does.
+    fn build_truthiness(
+        &mut self,
+        value: TypedValue<'ctx>,
+    ) -> Result<IntValue<'ctx>, Box<dyn Error>> {
+        let bool_type = self.context.bool_type();
+        let truthy = match value.var_type {
+            VariableType::Boolean => value.value.into_int_value(),
+            VariableType::Nothing => bool_type.const_zero(),
+            VariableType::Object(_) => bool_type.const_all_ones(),
+            // Unordered so that NaN, which is not equal to zero, is true
+            VariableType::Float => self.builder.build_float_compare(
+                FloatPredicate::UNE,
+                value.value.into_float_value(),
+                self.get_float_type().const_zero(),
+                "truthy",
+            )?,
+            VariableType::String => {
+                let i8_type = self.context.i8_type();
+                let first = self
+                    .builder
+                    .build_load(i8_type, value.value.into_pointer_value(), "first")?
+                    .into_int_value();
+                self.builder.build_int_compare(
+                    IntPredicate::NE,
+                    first,
+                    i8_type.const_zero(),
+                    "truthy",
+                )?
+            }
+            VariableType::List(_) => {
+                let length = self.build_list_length(value.value.into_pointer_value())?;
+                self.builder.build_int_compare(
+                    IntPredicate::NE,
+                    length,
+                    self.context.i64_type().const_zero(),
+                    "truthy",
+                )?
+            }
+        };
+        Ok(truthy)
+    }
+
+    /// Lowers `a and b` / `a or b` so that `b` only runs when `a` does not
+    /// already decide the result, joining both paths with a phi.
+    fn compile_short_circuit(
+        &mut self,
+        binop: &BinaryOperation,
+    ) -> Result<IntValue<'ctx>, Box<dyn Error>> {
+        let is_and = matches!(binop.operator, BinaryOperator::And);
+        let bool_type = self.context.bool_type();
+
+        let left = self.compile_condition(&binop.left)?;
+        // The left side may have added blocks of its own
+        let left_block = self.builder.get_insert_block().unwrap();
+        let current_fn = self.current_function();
+        let right_block = self.context.append_basic_block(current_fn, "logic.rhs");
+        let merge_block = self.context.append_basic_block(current_fn, "logic.merge");
+        if is_and {
+            self.builder
+                .build_conditional_branch(left, right_block, merge_block)?;
+        } else {
+            self.builder
+                .build_conditional_branch(left, merge_block, right_block)?;
+        }
+
+        self.builder.position_at_end(right_block);
+        let right = self.compile_condition(&binop.right)?;
+        let right_end_block = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(merge_block)?;
+
+        // Skipping the right side means `and` was false or `or` was true
+        self.builder.position_at_end(merge_block);
+        let result = self.builder.build_phi(bool_type, "logic")?;
+        let decided = bool_type.const_int(u64::from(!is_and), false);
+        result.add_incoming(&[(&decided, left_block), (&right, right_end_block)]);
+        Ok(result.as_basic_value().into_int_value())
+    }
+
+    fn compile_when_statement(&mut self, when_stmt: &WhenStatement) -> Result<(), Box<dyn Error>> {
+        let cond_bool = self.compile_condition(&when_stmt.condition)?;
+
+        let current_fn = self.current_function();
+        let then_block = self.context.append_basic_block(current_fn, "then");
+        let else_block = self.context.append_basic_block(current_fn, "else");
+        let merge_block = self.context.append_basic_block(current_fn, "merge");
+
+        self.builder
+            .build_conditional_branch(cond_bool, then_block, else_block)?;
+
+        // Compile then block
+        let then_state = self.compile_block(&when_stmt.then_block, then_block, merge_block)?;
+
+        // Compile else block
+        let otherwise_block = when_stmt.otherwise_block.as_deref().unwrap_or(&[]);
+        let else_state = self.compile_block(otherwise_block, else_block, merge_block)?;
+
+        // Continue from merge block
+        self.builder.position_at_end(merge_block);
+        let states: Vec<Scope<'ctx>> = then_state.into_iter().chain(else_state).collect();
+        if states.is_empty() {
+            // Both branches returned, so nothing after the `when` can run
+            self.builder.build_unreachable()?;
+        } else {
+            self.variables = self.merge_scopes(&states)?;
+        }
+
+        Ok(())
+    }
+
+    /// Lowers `repeat while` to a header block that tests the condition, a
+    /// body that jumps back to the header, and an exit block.
+    fn compile_repeat_while_statement(
+        &mut self,
+        repeat_stmt: &RepeatWhileStatement,
+    ) -> Result<(), Box<dyn Error>> {
+        let current_fn = self.current_function();
+        let header_block = self.context.append_basic_block(current_fn, "loop.header");
+        let body_block = self.context.append_basic_block(current_fn, "loop.body");
+        let exit_block = self.context.append_basic_block(current_fn, "loop.exit");
+
+        self.builder.build_unconditional_branch(header_block)?;
+
+        self.builder.position_at_end(header_block);
+        let cond_bool = self.compile_condition(&repeat_stmt.condition)?;
+        self.builder
+            .build_conditional_branch(cond_bool, body_block, exit_block)?;
+
+        self.compile_loop_body(
+            &repeat_stmt.body,
+            body_block,
+            header_block,
+            exit_block,
+            None,
+        )?;
+
+        self.builder.position_at_end(exit_block);
+        Ok(())
+    }
+
+    /// Lowers `repeat for each n from A to B by S` to a loop over an integer
+    /// induction variable running from 0 to the trip count, which LLVM's loop
+    /// passes understand. The loop variable is recomputed from it as
+    /// `A + index * step` at the top of each iteration.
+    fn compile_repeat_range_statement(
+        &mut self,
+        range_stmt: &RepeatRangeStatement,
+    ) -> Result<(), Box<dyn Error>> {
+        let float_type = self.get_float_type();
+        let i64_type = self.context.i64_type();
+        let zero = float_type.const_float(0.0);
+
+        let start = self.compile_expression(&range_stmt.start)?;
+        let start = self.expect_float(start, "the start of a range")?;
+        let end = self.compile_expression(&range_stmt.end)?;
+        let end = self.expect_float(end, "the end of a range")?;
+        let step = match &range_stmt.step {
+            Some(step) => {
+                let step = self.compile_expression(step)?;
+                self.expect_float(step, "the step of a range")?
+            }
+            None => float_type.const_float(1.0),
+        };
+
+        let current_fn = self.current_function();
+        let zero_step_block = self
+            .context
+            .append_basic_block(current_fn, "range.zero_step");
+        let preheader_block = self
+            .context
+            .append_basic_block(current_fn, "range.preheader");
+        let too_long_block = self
+            .context
+            .append_basic_block(current_fn, "range.too_long");
+        let count_block = self.context.append_basic_block(current_fn, "range.count");
+        let header_block = self.context.append_basic_block(current_fn, "range.header");
+        let body_block = self.context.append_basic_block(current_fn, "range.body");
+        let latch_block = self.context.append_basic_block(current_fn, "range.latch");
+        let exit_block = self.context.append_basic_block(current_fn, "range.exit");
+
+        // Only the size of the step matters, the direction comes from the ends
+        let step_is_negative =
+            self.builder
+                .build_float_compare(FloatPredicate::OLT, step, zero, "step.negative")?;
+        let negated_step = self.builder.build_float_neg(step, "step.neg")?;
+        let magnitude = self
+            .builder
+            .build_select(step_is_negative, negated_step, step, "step.abs")?
+            .into_float_value();
+        let step_is_zero =
+            self.builder
+                .build_float_compare(FloatPredicate::OEQ, magnitude, zero, "step.zero")?;
+        self.builder
+            .build_conditional_branch(step_is_zero, zero_step_block, preheader_block)?;
+
+        self.builder.position_at_end(zero_step_block);
+        self.build_runtime_error("the step of a range cannot be zero")?;
+
+        // trip count = floor(|end - start| / |step|) + 1, both ends included
+        self.builder.position_at_end(preheader_block);
+        let ascending =
+            self.builder
+                .build_float_compare(FloatPredicate::OGE, end, start, "range.ascending")?;
+        let negated_magnitude = self.builder.build_float_neg(magnitude, "range.down")?;
+        let delta = self
+            .builder
+            .build_select(ascending, magnitude, negated_magnitude, "range.delta")?
+            .into_float_value();
+        let span = self.builder.build_float_sub(end, start, "range.span")?;
+        let negated_span = self.builder.build_float_neg(span, "range.span.neg")?;
+        let distance = self
+            .builder
+            .build_select(ascending, span, negated_span, "range.distance")?
+            .into_float_value();
+        let steps = self
+            .builder
+            .build_float_div(distance, magnitude, "range.steps")?;
+        // Ordered, so NaN from an end that is not finite fails it too
+        let limit = float_type.const_float(i64::MAX as f64);
+        let countable = self.builder.build_float_compare(
+            FloatPredicate::OLT,
+            steps,
+            limit,
+            "range.countable",
+        )?;
+        self.builder
+            .build_conditional_branch(countable, count_block, too_long_block)?;
+
+        self.builder.position_at_end(too_long_block);
+        self.build_runtime_error("the range has too many steps to count")?;
+
+        self.builder.position_at_end(count_block);
+        let whole_steps = self
+            .builder
+            .build_float_to_signed_int(steps, i64_type, "range.whole")?;
+        let trip_count =
+            self.builder
+                .build_int_add(whole_steps, i64_type.const_int(1, false), "range.trips")?;
+        self.builder.build_unconditional_branch(header_block)?;
+
+        self.builder.position_at_end(header_block);
+        let index = self.builder.build_phi(i64_type, "range.index")?;
+        index.add_incoming(&[(&i64_type.const_zero(), count_block)]);
+        let index_value = index.as_basic_value().into_int_value();
+        let keep_going = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            index_value,
+            trip_count,
+            "range.cond",
+        )?;
+        self.builder
+            .build_conditional_branch(keep_going, body_block, exit_block)?;
+
+        // The loop variable only exists inside the body
+        self.builder.position_at_end(body_block);
+        let offset =
+            self.builder
+                .build_signed_int_to_float(index_value, float_type, "range.offset")?;
+        let scaled = self
+            .builder
+            .build_float_mul(offset, delta, "range.scaled")?;
+        let current = self.builder.build_float_add(start, scaled, "range.value")?;
+        self.note_type(range_stmt.variable_span, || VariableType::Float.to_string());
+        let slot = self.create_entry_block_alloca(&VariableType::Float, &range_stmt.variable)?;
+        self.debug_variable(&range_stmt.variable, slot, &VariableType::Float);
+        self.builder.build_store(slot, current)?;
+        let binding = Variable {
+            pointer: slot,
+            var_type: VariableType::Float,
+            shared: false,
+        };
+
+        self.compile_loop_body(
+            &range_stmt.body,
+            body_block,
+            latch_block,
+            exit_block,
+            Some((&range_stmt.variable, binding)),
+        )?;
+
+        self.builder.position_at_end(latch_block);
+        let next =
+            self.builder
+                .build_int_add(index_value, i64_type.const_int(1, false), "range.next")?;
+        index.add_incoming(&[(&next, latch_block)]);
+        self.builder.build_unconditional_branch(header_block)?;
+
+        self.builder.position_at_end(exit_block);
+        Ok(())
+    }
+
+    /// Lowers `repeat for each item in list`. The length is read again before
+    /// every iteration, so items added by the body are visited too.
+    fn compile_repeat_each_statement(
+        &mut self,
+        each_stmt: &RepeatEachStatement,
+    ) -> Result<(), Box<dyn Error>> {
+        let i64_type = self.context.i64_type();
+        let list = self.compile_expression(&each_stmt.list)?;
+        let (list, element_type) = self.expect_list(list, "a 'repeat for each' loop")?;
+        let element_type = Self::known_element_type(element_type)?;
+
+        let current_fn = self.current_function();
+        let preheader_block = self.builder.get_insert_block().unwrap();
+        let header_block = self.context.append_basic_block(current_fn, "each.header");
+        let body_block = self.context.append_basic_block(current_fn, "each.body");
+        let latch_block = self.context.append_basic_block(current_fn, "each.latch");
+        let exit_block = self.context.append_basic_block(current_fn, "each.exit");
+        self.builder.build_unconditional_branch(header_block)?;
+
+        self.builder.position_at_end(header_block);
+        let index = self.builder.build_phi(i64_type, "each.index")?;
+        index.add_incoming(&[(&i64_type.const_zero(), preheader_block)]);
+        let index_value = index.as_basic_value().into_int_value();
+        let length = self.build_list_length(list)?;
+        let keep_going =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, index_value, length, "each.cond")?;
+        self.builder
+            .build_conditional_branch(keep_going, body_block, exit_block)?;
+
+        // The loop variable only exists inside the body
+        self.builder.position_at_end(body_block);
+        let element_slot = self.build_list_slot(list, index_value)?;
+        let element = self.builder.build_load(
+            self.llvm_type(&element_type),
+            element_slot,
+            &each_stmt.variable,
+        )?;
+        self.note_type(each_stmt.variable_span, || element_type.to_string());
+        let slot = self.create_entry_block_alloca(&element_type, &each_stmt.variable)?;
+        self.debug_variable(&each_stmt.variable, slot, &element_type);
+        self.builder.build_store(slot, element)?;
+        // The list keeps its own copy of the item
+        let binding = Variable {
+            pointer: slot,
+            var_type: element_type,
+            shared: true,
+        };
+
+        self.compile_loop_body(
+            &each_stmt.body,
+            body_block,
+            latch_block,
+            exit_block,
+            Some((&each_stmt.variable, binding)),
+        )?;
+
+        self.builder.position_at_end(latch_block);
+        let next =
+            self.builder
+                .build_int_add(index_value, i64_type.const_int(1, false), "each.next")?;
+        index.add_incoming(&[(&next, latch_block)]);
+        self.builder.build_unconditional_branch(header_block)?;
+
+        self.builder.position_at_end(exit_block);
+        Ok(())
+    }
+
+    fn compile_add_statement(&mut self, add_stmt: &AddStatement) -> Result<(), Box<dyn Error>> {
+        self.share(&add_stmt.item);
+        let item = self.compile_expression(&add_stmt.item)?;
+        let list = self.compile_expression(&add_stmt.list)?;
+        let (list, element_type) = self.expect_list(list, "'add ... to'")?;
+
+        match element_type {
+            Some(element_type) if element_type != item.var_type => {
+                return Err(error(
+                    codes::TYPE_MISMATCH,
+                    format!(
+                        "Cannot add a {} to a list of {}",
+                        item.var_type, element_type
+                    ),
+                ));
+            }
+            Some(_) => {}
+            None => {
+                // `let items be []` takes its item type from the first `add`,
+                // which only the variable holding the list gets to see
+                let variable = match &add_stmt.list.kind {
+                    ExpressionKind::Identifier(name) => self.variables.get_mut(name),
+                    _ => None,
+                };
+                let reason = match variable {
+                    Some(variable) if !variable.shared => {
+                        variable.var_type = VariableType::list_of(item.var_type.clone());
+                        None
+                    }
+                    Some(_) => Some("other names hold this list"),
+                    None => Some("not a variable"),
+                };
+                if let Some(reason) = reason {
+                    return Err(Box::new(
+                        Diagnostic::error(
+                            codes::UNKNOWN_ITEM_TYPE,
+                            "Cannot add the first item to this list here",
+                        )
+                        .with_primary(add_stmt.list.span, reason)
+                        .with_note("an empty list learns its item type from the first `add` to its variable")
+                        .with_help("add the first item before passing the list on"),
+                    ));
                 }
             }
-            Expression::BinaryOp(binop) => {
-                let left_val = self.evaluate_expression(&binop.left, variables)?;
-                let right_val = self.evaluate_expression(&binop.right, variables)?;
+        }
 
-                if let (Ok(left_num), Ok(right_num)) =
-                    (left_val.parse::<f64>(), right_val.parse::<f64>())
-                {
-                    let result = match binop.operator {
-                        BinaryOperator::GreaterThan => {
-                            return Ok((left_num > right_num).to_string());
-                        }
-                        BinaryOperator::LessThan => return Ok((left_num < right_num).to_string()),
-                        BinaryOperator::GreaterThanOrEqual => {
-                            return Ok((left_num >= right_num).to_string());
-                        }
-                        BinaryOperator::LessThanOrEqual => {
-                            return Ok((left_num <= right_num).to_string());
-                        }
-                        BinaryOperator::Equal => {
-                            return Ok(((left_num - right_num).abs() < f64::EPSILON).to_string());
-                        }
-                        BinaryOperator::NotEqual => {
-                            return Ok(((left_num - right_num).abs() >= f64::EPSILON).to_string());
-                        }
-                        BinaryOperator::Add => left_num + right_num,
-                        BinaryOperator::Subtract => left_num - right_num,
-                        BinaryOperator::Multiply => left_num * right_num,
-                        BinaryOperator::Divide => {
-                            if right_num == 0.0 {
-                                return Err("Division by zero".to_string());
-                            }
-                            left_num / right_num
-                        }
-                    };
-                    Ok(result.to_string())
+        let slot = self.build_list_push_slot(list)?;
+        self.builder.build_store(slot, item.value)?;
+        Ok(())
+    }
+
+    fn compile_set_field_statement(
+        &mut self,
+        set_stmt: &SetFieldStatement,
+    ) -> Result<(), Box<dyn Error>> {
+        self.share(&set_stmt.value);
+        let value = self.compile_expression(&set_stmt.value)?;
+        let object = self.compile_expression(&set_stmt.object)?;
+        let (object, fields) = self.expect_object(object, "a field assignment")?;
+
+        match fields.get(&set_stmt.field) {
+            Some(field_type) if *field_type != value.var_type => {
+                return Err(error(
+                    codes::TYPE_MISMATCH,
+                    format!(
+                        "Field '{}' holds a {}, so it cannot be set to a {}",
+                        set_stmt.field, field_type, value.var_type
+                    ),
+                ));
+            }
+            Some(_) => {}
+            None => {
+                let variable = match &set_stmt.object.kind {
+                    ExpressionKind::Identifier(name) => self.variables.get_mut(name),
+                    _ => None,
+                };
+                let reason = match variable {
+                    Some(variable) if !variable.shared => {
+                        let mut fields = fields;
+                        fields.insert(set_stmt.field.clone(), value.var_type.clone());
+                        variable.var_type = VariableType::Object(fields);
+                        None
+                    }
+                    Some(_) => Some("other names hold this object"),
+                    None => Some("not a variable"),
+                };
+                if let Some(reason) = reason {
+                    return Err(Box::new(
+                        Diagnostic::error(
+                            codes::FIELD_NOT_ADDABLE,
+                            format!("Cannot add field '{}' here", set_stmt.field),
+                        )
+                        .with_primary(set_stmt.object.span, reason)
+                        .with_note(
+                            "new fields can only be added through the variable the object was made in",
+                        )
+                        .with_help("add the field before giving the object another name"),
+                    ));
+                }
+            }
+        }
+
+        let slot = self.build_object_slot(object, &set_stmt.field)?;
+        self.builder.build_store(slot, value.value)?;
+        Ok(())
+    }
+
+    /// Lowers `remove item from list` to a search for the first equal item,
+    /// which is then removed. Nothing happens when there is no such item.
+    fn compile_remove_statement(
+        &mut self,
+        remove_stmt: &RemoveStatement,
+    ) -> Result<(), Box<dyn Error>> {
+        let i64_type = self.context.i64_type();
+        let item = self.compile_expression(&remove_stmt.item)?;
+        let list = self.compile_expression(&remove_stmt.list)?;
+        let (list, element_type) = self.expect_list(list, "'remove ... from'")?;
+        let element_type = Self::known_element_type(element_type)?;
+        if element_type != item.var_type {
+            return Err(error(
+                codes::TYPE_MISMATCH,
+                format!(
+                    "Cannot remove a {} from a list of {}",
+                    item.var_type, element_type
+                ),
+            ));
+        }
+
+        let current_fn = self.current_function();
+        let preheader_block = self.builder.get_insert_block().unwrap();
+        let header_block = self.context.append_basic_block(current_fn, "remove.header");
+        let body_block = self.context.append_basic_block(current_fn, "remove.body");
+        let found_block = self.context.append_basic_block(current_fn, "remove.found");
+        let latch_block = self.context.append_basic_block(current_fn, "remove.latch");
+        let exit_block = self.context.append_basic_block(current_fn, "remove.exit");
+        self.builder.build_unconditional_branch(header_block)?;
+
+        self.builder.position_at_end(header_block);
+        let index = self.builder.build_phi(i64_type, "remove.index")?;
+        index.add_incoming(&[(&i64_type.const_zero(), preheader_block)]);
+        let index_value = index.as_basic_value().into_int_value();
+        let length = self.build_list_length(list)?;
+        let keep_going = self.builder.build_int_compare(
+            IntPredicate::SLT,
+            index_value,
+            length,
+            "remove.cond",
+        )?;
+        self.builder
+            .build_conditional_branch(keep_going, body_block, exit_block)?;
+
+        self.builder.position_at_end(body_block);
+        let slot = self.build_list_slot(list, index_value)?;
+        let element = self
+            .builder
+            .build_load(self.llvm_type(&element_type), slot, "element")?;
+        let equal = self.build_values_equal(&element_type, element, item.value)?;
+        self.builder
+            .build_conditional_branch(equal, found_block, latch_block)?;
+
+        self.builder.position_at_end(found_block);
+        self.build_list_remove_at(list, index_value)?;
+        self.builder.build_unconditional_branch(exit_block)?;
+
+        self.builder.position_at_end(latch_block);
+        let next =
+            self.builder
+                .build_int_add(index_value, i64_type.const_int(1, false), "remove.next")?;
+        index.add_incoming(&[(&next, latch_block)]);
+        self.builder.build_unconditional_branch(header_block)?;
+
+        self.builder.position_at_end(exit_block);
+        Ok(())
+    }
+
+    /// Compares two values of type `var_type`. Strings compare by content,
+    /// lists and objects by identity.
+    fn build_values_equal(
+        &mut self,
+        var_type: &VariableType,
+        left: BasicValueEnum<'ctx>,
+        right: BasicValueEnum<'ctx>,
+    ) -> Result<IntValue<'ctx>, Box<dyn Error>> {
+        let equal = match var_type {
+            VariableType::Float => self.builder.build_float_compare(
+                FloatPredicate::OEQ,
+                left.into_float_value(),
+                right.into_float_value(),
+                "equal",
+            )?,
+            VariableType::String => {
+                let i32_type = self.context.i32_type();
+                let strcmp_fn = self.strcmp_function();
+                let order = self
+                    .builder
+                    .build_call(strcmp_fn, &[left.into(), right.into()], "order")?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_int_value();
+                self.builder.build_int_compare(
+                    IntPredicate::EQ,
+                    order,
+                    i32_type.const_zero(),
+                    "equal",
+                )?
+            }
+            VariableType::Boolean => self.builder.build_int_compare(
+                IntPredicate::EQ,
+                left.into_int_value(),
+                right.into_int_value(),
+                "equal",
+            )?,
+            VariableType::Nothing => self.context.bool_type().const_all_ones(),
+            VariableType::List(_) | VariableType::Object(_) => self.builder.build_int_compare(
+                IntPredicate::EQ,
+                left.into_pointer_value(),
+                right.into_pointer_value(),
+                "equal",
+            )?,
+        };
+        Ok(equal)
+    }
+
+    fn compile_loop(&mut self, statement: &Statement) -> Result<(), Box<dyn Error>> {
+        match &statement.kind {
+            StatementKind::RepeatWhile(repeat_stmt) => {
+                self.compile_repeat_while_statement(repeat_stmt)
+            }
+            StatementKind::RepeatRange(range_stmt) => {
+                self.compile_repeat_range_statement(range_stmt)
+            }
+            StatementKind::RepeatEach(each_stmt) => self.compile_repeat_each_statement(each_stmt),
+            _ => unreachable!("only called for loops"),
+        }
+    }
+
+    /// Gives the lists whose item types are only learned inside the loop
+    /// `statement`, as in `let items be []` followed by a loop that adds to
+    /// it, those item types before the loop is compiled. Otherwise the body
+    /// would be compiled for lists of unknown items, and go wrong from the
+    /// second time round.
+    ///
+    /// The types are found by compiling the loop once into a scratch function,
+    /// like the bodies used to infer return types. When that fails, the real
+    /// compile reports the problem.
+    fn settle_loop_types(&mut self, statement: &Statement) -> Result<(), Box<dyn Error>> {
+        let saved_block = self.builder.get_insert_block();
+        let saved_location = self.debug_current_location();
+        let saved_slots = std::mem::take(&mut self.slots);
+        let saved_loops = std::mem::take(&mut self.loops);
+        let saved_warnings = self.warnings.len();
+        let saved_types = self.types.as_ref().map(Vec::len);
+
+        let scratch_type = self.context.void_type().fn_type(&[], false);
+        let scratch = self
+            .module
+            .add_function("delta.loop.infer", scratch_type, None);
+        self.debug_enter_function(scratch, "", None);
+        let entry = self.context.append_basic_block(scratch, "entry");
+        self.builder.position_at_end(entry);
+
+        // Stand-ins for the variables, so the scratch function never refers to
+        // the slots of the real one
+        let mut stand_ins = Scope::new();
+        for (name, variable) in &self.variables {
+            let llvm_type = self.llvm_type(&variable.var_type);
+            let stand_in = Variable {
+                pointer: self.builder.build_alloca(llvm_type, name)?,
+                var_type: variable.var_type.clone(),
+                shared: variable.shared,
+            };
+            stand_ins.insert(name.clone(), stand_in);
+        }
+        let outer = std::mem::replace(&mut self.variables, stand_ins);
+
+        let result = self.compile_loop(statement);
+        let settled = std::mem::replace(&mut self.variables, outer);
+        // SAFETY: nothing refers to the scratch function once its body is done
+        unsafe { scratch.delete() };
+
+        self.debug_leave_function();
+        self.debug_set_location(saved_location);
+        self.slots = saved_slots;
+        self.loops = saved_loops;
+        self.warnings.truncate(saved_warnings);
+        if let (Some(types), Some(saved_types)) = (&mut self.types, saved_types) {
+            types.truncate(saved_types);
+        }
+        if let Some(block) = saved_block {
+            self.builder.position_at_end(block);
+        }
+
+        if result.is_ok() {
+            for (name, variable) in &mut self.variables {
+                variable.var_type = variable.var_type.filled_from(&settled[name].var_type);
+            }
+        }
+        Ok(())
+    }
+
+    /// Compiles a loop body that continues at `continue_block` and leaves
+    /// through `break_block`. `binding` is the loop variable, which only
+    /// exists inside the body.
+    ///
+    /// Each variable visible before the loop has to keep a type the body was
+    /// compiled for whenever the loop goes round again. After the loop, it has
+    /// a type that holds on every way out.
+    fn compile_loop_body(
+        &mut self,
+        statements: &[Statement],
+        body_block: BasicBlock<'ctx>,
+        continue_block: BasicBlock<'ctx>,
+        break_block: BasicBlock<'ctx>,
+        binding: Option<(&str, Variable<'ctx>)>,
+    ) -> Result<(), Box<dyn Error>> {
+        let entry = self.variables.clone();
+        // A loop variable shadows any outer variable of the same name, so it
+        // must not share that variable's slots either
+        let mut outer_slots = Vec::new();
+        if let Some((name, variable)) = &binding {
+            outer_slots = self.take_slots(name);
+            let kind = mem::discriminant(&variable.var_type);
+            self.slots
+                .insert((name.to_string(), kind), variable.pointer);
+            self.variables.insert(name.to_string(), variable.clone());
+        }
+
+        self.loops.push(LoopTargets {
+            continue_block,
+            break_block,
+            continue_states: Vec::new(),
+            break_states: Vec::new(),
+        });
+        let result = self.compile_block(statements, body_block, continue_block);
+        let targets = self.loops.pop().unwrap();
+        self.variables = entry;
+        if let Some((name, _)) = &binding {
+            self.take_slots(name);
+            self.slots.extend(outer_slots);
+        }
+        let end_state = result?;
+
+        // Outside the body, the loop variable's name means what it did before
+        let unbind = |mut state: Scope<'ctx>| {
+            if let Some((name, _)) = &binding {
+                match self.variables.get(*name) {
+                    Some(variable) => state.insert(name.to_string(), variable.clone()),
+                    None => state.remove(*name),
+                };
+            }
+            state
+        };
+        let repeats: Vec<Scope<'ctx>> = targets
+            .continue_states
+            .into_iter()
+            .chain(end_state)
+            .map(unbind)
+            .collect();
+        let exits: Vec<Scope<'ctx>> = targets.break_states.into_iter().map(unbind).collect();
+
+        for (name, variable) in &self.variables {
+            for state in &repeats {
+                let again = &state[name];
+                let fits = again.pointer == variable.pointer
+                    && variable
+                        .var_type
+                        .merge(&again.var_type)
+                        .is_some_and(|merged| variable.var_type.filled_from(&merged) == merged);
+                if !fits {
+                    return Err(Box::new(
+                        Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            format!(
+                                "'{}' holds a {} when the loop starts but a {} when it goes round again",
+                                name, variable.var_type, again.var_type
+                            ),
+                        )
+                        .with_help("give it the same type of value on every pass"),
+                    ));
+                }
+            }
+        }
+
+        let states: Vec<Scope<'ctx>> = std::iter::once(self.variables.clone())
+            .chain(repeats)
+            .chain(exits)
+            .collect();
+        self.variables = self.merge_scopes(&states)?;
+        Ok(())
+    }
+
+    /// Removes and returns the slots of the variable `name`.
+    fn take_slots(
+        &mut self,
+        name: &str,
+    ) -> Vec<((String, Discriminant<VariableType>), PointerValue<'ctx>)> {
+        let taken = self
+            .slots
+            .iter()
+            .filter(|((slot_name, _), _)| slot_name == name)
+            .map(|(key, pointer)| (key.clone(), *pointer))
+            .collect();
+        self.slots.retain(|(slot_name, _), _| slot_name != name);
+        taken
+    }
+
+    fn innermost_loop(&mut self) -> &mut LoopTargets<'ctx> {
+        self.loops
+            .last_mut()
+            .expect("the parser rejects 'break' and 'continue' outside of loops")
+    }
+
+    /// Compiles `statements` into `block` with a scope of their own, falling
+    /// through to `next` unless the block already ended in a terminator.
+    /// Returns the variables at the end of the block when it fell through.
+    fn compile_block(
+        &mut self,
+        statements: &[Statement],
+        block: BasicBlock<'ctx>,
+        next: BasicBlock<'ctx>,
+    ) -> Result<Option<Scope<'ctx>>, Box<dyn Error>> {
+        let outer = self.variables.clone();
+        self.builder.position_at_end(block);
+        let result = self.compile_statements(statements);
+        let state = std::mem::replace(&mut self.variables, outer);
+        result?;
+        if self.block_is_terminated() {
+            return Ok(None);
+        }
+        self.builder.build_unconditional_branch(next)?;
+        Ok(Some(state))
+    }
+
+    /// The variables where the paths that ended with `states` meet: the ones
+    /// visible here, each with a type that holds on every path.
+    fn merge_scopes(&self, states: &[Scope<'ctx>]) -> Result<Scope<'ctx>, Box<dyn Error>> {
+        let mut merged = Scope::new();
+        for name in self.variables.keys() {
+            let first = &states[0][name];
+            let mut var_type = first.var_type.clone();
+            let mut shared = first.shared;
+            for variable in &states[1..] {
+                let variable = &variable[name];
+                shared |= variable.shared;
+                let both = (variable.pointer == first.pointer)
+                    .then(|| var_type.merge(&variable.var_type))
+                    .flatten();
+                var_type = both.ok_or_else(|| {
+                    Box::new(
+                        Diagnostic::error(
+                            codes::TYPE_MISMATCH,
+                            format!(
+                                "'{}' holds a {} on one path into here and a {} on another",
+                                name, var_type, variable.var_type
+                            ),
+                        )
+                        .with_help("give it the same type of value on every path"),
+                    ) as Box<dyn Error>
+                })?;
+            }
+            merged.insert(
+                name.clone(),
+                Variable {
+                    pointer: first.pointer,
+                    var_type,
+                    shared,
+                },
+            );
+        }
+        Ok(merged)
+    }
+
+    fn expect_float(
+        &self,
+        value: TypedValue<'ctx>,
+        context: &str,
+    ) -> Result<FloatValue<'ctx>, Box<dyn Error>> {
+        match value.var_type {
+            VariableType::Float => Ok(value.value.into_float_value()),
+            other => Err(error(
+                codes::TYPE_MISMATCH,
+                format!("Expected a number for {}, found {}", context, other),
+            )),
+        }
+    }
+
+    fn expect_list(
+        &self,
+        value: TypedValue<'ctx>,
+        context: &str,
+    ) -> Result<(PointerValue<'ctx>, Option<VariableType>), Box<dyn Error>> {
+        match value.var_type {
+            VariableType::List(element_type) => Ok((
+                value.value.into_pointer_value(),
+                element_type.map(|element_type| *element_type),
+            )),
+            other => Err(error(
+                codes::TYPE_MISMATCH,
+                format!("Expected a list for {}, found {}", context, other),
+            )),
+        }
+    }
+
+    fn expect_object(
+        &self,
+        value: TypedValue<'ctx>,
+        context: &str,
+    ) -> Result<(PointerValue<'ctx>, BTreeMap<String, VariableType>), Box<dyn Error>> {
+        match value.var_type {
+            VariableType::Object(fields) => Ok((value.value.into_pointer_value(), fields)),
+            other => Err(error(
+                codes::TYPE_MISMATCH,
+                format!("Expected an object for {}, found {}", context, other),
+            )),
+        }
+    }
+
+    fn known_element_type(
+        element_type: Option<VariableType>,
+    ) -> Result<VariableType, Box<dyn Error>> {
+        element_type.ok_or_else(|| {
+            Box::new(
+                Diagnostic::error(
+                    codes::UNKNOWN_ITEM_TYPE,
+                    "The type of this list's items is not known yet",
+                )
+                .with_help("add an item to the list first"),
+            ) as Box<dyn Error>
+        })
+    }
+
+    fn boolean_value(&self, value: IntValue<'ctx>) -> TypedValue<'ctx> {
+        TypedValue {
+            value: value.into(),
+            var_type: VariableType::Boolean,
+        }
+    }
+
+    fn float_value(&self, value: FloatValue<'ctx>) -> TypedValue<'ctx> {
+        TypedValue {
+            value: value.into(),
+            var_type: VariableType::Float,
+        }
+    }
+
+    fn compile_expression(
+        &mut self,
+        expr: &Expression,
+    ) -> Result<TypedValue<'ctx>, Box<dyn Error>> {
+        let value = self
+            .lower_expression(expr)
+            .map_err(|error| locate(error, expr.span))?;
+        self.note_type(expr.span, || value.var_type.to_string());
+        Ok(value)
+    }
+
+    fn lower_expression(&mut self, expr: &Expression) -> Result<TypedValue<'ctx>, Box<dyn Error>> {
+        match &expr.kind {
+            ExpressionKind::Number(n) => {
+                Ok(self.float_value(self.get_float_type().const_float(*n)))
+            }
+            ExpressionKind::Boolean(value) => {
+                let value = self.context.bool_type().const_int(u64::from(*value), false);
+                Ok(self.boolean_value(value))
+            }
+            ExpressionKind::Nothing => Ok(TypedValue {
+                value: self.get_string_type().const_null().into(),
+                var_type: VariableType::Nothing,
+            }),
+            ExpressionKind::String(s) => {
+                let string_ptr = self.builder.build_global_string_ptr(s, "str")?;
+                Ok(TypedValue {
+                    value: string_ptr.as_pointer_value().into(),
+                    var_type: VariableType::String,
+                })
+            }
+            ExpressionKind::Identifier(name) if self.names_function(name) => {
+                self.compile_value_call(name, &[])
+            }
+            ExpressionKind::Identifier(name) => {
+                if let Some(variable) = self.variables.get(name) {
+                    let loaded = self.builder.build_load(
+                        self.llvm_type(&variable.var_type),
+                        variable.pointer,
+                        name,
+                    )?;
+                    Ok(TypedValue {
+                        value: loaded,
+                        var_type: variable.var_type.clone(),
+                    })
                 } else {
-                    Ok(format!("({} {:?} {})", left_val, binop.operator, right_val))
+                    Err(Box::new(
+                        Diagnostic::error(
+                            codes::UNDEFINED_VARIABLE,
+                            format!("Undefined variable: {}", name),
+                        )
+                        .with_primary(expr.span, "not defined at this point"),
+                    ))
+                }
+            }
+            ExpressionKind::BinaryOp(binop)
+                if matches!(binop.operator, BinaryOperator::And | BinaryOperator::Or) =>
+            {
+                self.compile_logical_value(expr)
+            }
+            ExpressionKind::Not(_) => self.compile_logical_value(expr),
+            ExpressionKind::BinaryOp(binop) => self.compile_binary_operation(binop),
+            ExpressionKind::FunctionCall(call) => {
+                self.compile_value_call(&call.name, &call.arguments)
+            }
+            ExpressionKind::List(items) => self.compile_list_literal(items),
+            ExpressionKind::Object(fields) => self.compile_object_literal(fields),
+            ExpressionKind::Member(member) => {
+                let object = self.compile_expression(&member.object)?;
+                let (object, fields) = self.expect_object(object, "'.'")?;
+                let field_type = match fields.get(&member.field) {
+                    Some(field_type) => field_type.clone(),
+                    None => {
+                        return Err(error(
+                            codes::UNKNOWN_FIELD,
+                            format!("Object has no field '{}'", member.field),
+                        ));
+                    }
+                };
+                self.load_object_field(object, &member.field, field_type)
+            }
+            ExpressionKind::Index(index_expr) => {
+                let list = self.compile_expression(&index_expr.list)?;
+                let (list, element_type) = self.expect_list(list, "indexing")?;
+                let element_type = Self::known_element_type(element_type)?;
+                let index = self.compile_expression(&index_expr.index)?;
+                let index = self.expect_float(index, "a list index")?;
+                let index = self.builder.build_float_to_signed_int(
+                    index,
+                    self.context.i64_type(),
+                    "index",
+                )?;
+                self.load_list_element(list, index, element_type)
+            }
+            ExpressionKind::ListQuery(query) => {
+                let list = self.compile_expression(&query.list)?;
+                let (list, element_type) = self.expect_list(list, "a list query")?;
+                let length = self.build_list_length(list)?;
+                let index = match query.query {
+                    ListQueryKind::Length => {
+                        let length = self.builder.build_signed_int_to_float(
+                            length,
+                            self.get_float_type(),
+                            "length",
+                        )?;
+                        return Ok(self.float_value(length));
+                    }
+                    ListQueryKind::First => self.context.i64_type().const_zero(),
+                    ListQueryKind::Last => self.builder.build_int_sub(
+                        length,
+                        self.context.i64_type().const_int(1, false),
+                        "last",
+                    )?,
+                };
+                let element_type = Self::known_element_type(element_type)?;
+                self.load_list_element(list, index, element_type)
+            }
+        }
+    }
+
+    fn compile_list_literal(
+        &mut self,
+        items: &[Expression],
+    ) -> Result<TypedValue<'ctx>, Box<dyn Error>> {
+        let mut values = Vec::with_capacity(items.len());
+        for item in items {
+            self.share(item);
+            values.push(self.compile_expression(item)?);
+        }
+
+        // Compiled lists hold a single type of item
+        let element_type = values.first().map(|value| value.var_type.clone());
+        if let Some(element_type) = &element_type
+            && let Some(other) = values.iter().find(|value| value.var_type != *element_type)
+        {
+            return Err(error(
+                codes::TYPE_MISMATCH,
+                format!(
+                    "List items must all have the same type, found {} and {}",
+                    element_type, other.var_type
+                ),
+            ));
+        }
+
+        let list = self.build_list_new(values.len() as u64)?;
+        for value in &values {
+            let slot = self.build_list_push_slot(list)?;
+            self.builder.build_store(slot, value.value)?;
+        }
+
+        Ok(TypedValue {
+            value: list.into(),
+            var_type: VariableType::List(element_type.map(Box::new)),
+        })
+    }
+
+    fn compile_object_literal(
+        &mut self,
+        fields: &[ObjectField],
+    ) -> Result<TypedValue<'ctx>, Box<dyn Error>> {
+        let mut values = Vec::with_capacity(fields.len());
+        for field in fields {
+            self.share(&field.value);
+            values.push(self.compile_expression(&field.value)?);
+        }
+
+        let object = self.build_object_new(fields.len())?;
+        let mut field_types = BTreeMap::new();
+        for (field, value) in fields.iter().zip(values) {
+            let slot = self.build_object_slot(object, &field.name)?;
+            self.builder.build_store(slot, value.value)?;
+            field_types.insert(field.name.clone(), value.var_type);
+        }
+
+        Ok(TypedValue {
+            value: object.into(),
+            var_type: VariableType::Object(field_types),
+        })
+    }
+
+    fn load_object_field(
+        &mut self,
+        object: PointerValue<'ctx>,
+        field: &str,
+        field_type: VariableType,
+    ) -> Result<TypedValue<'ctx>, Box<dyn Error>> {
+        let slot = self.build_object_slot(object, field)?;
+        let value = self
+            .builder
+            .build_load(self.llvm_type(&field_type), slot, field)?;
+        Ok(TypedValue {
+            value,
+            var_type: field_type,
+        })
+    }
+
+    fn load_list_element(
+        &mut self,
+        list: PointerValue<'ctx>,
+        index: IntValue<'ctx>,
+        element_type: VariableType,
+    ) -> Result<TypedValue<'ctx>, Box<dyn Error>> {
+        let slot = self.build_list_checked_slot(list, index)?;
+        let value = self
+            .builder
+            .build_load(self.llvm_type(&element_type), slot, "element")?;
+        Ok(TypedValue {
+            value,
+            var_type: element_type,
+        })
+    }
+
+    /// Compiles `and`, `or` or `not` used as a value.
+    fn compile_logical_value(
+        &mut self,
+        expr: &Expression,
+    ) -> Result<TypedValue<'ctx>, Box<dyn Error>> {
+        let condition = self.compile_condition(expr)?;
+        Ok(self.boolean_value(condition))
+    }
+
+    fn compile_binary_operation(
+        &mut self,
+        binop: &BinaryOperation,
+    ) -> Result<TypedValue<'ctx>, Box<dyn Error>> {
+        let left = self.compile_expression(&binop.left)?;
+        let right = self.compile_expression(&binop.right)?;
+
+        // Any two values can be compared for equality; values of different
+        // types are never equal
+        if matches!(
+            binop.operator,
+            BinaryOperator::Equal | BinaryOperator::NotEqual
+        ) {
+            let equal = if left.var_type == right.var_type {
+                self.build_values_equal(&left.var_type, left.value, right.value)?
+            } else {
+                self.context.bool_type().const_zero()
+            };
+            let result = if binop.operator == BinaryOperator::Equal {
+                equal
+            } else {
+                self.builder.build_not(equal, "ne")?
+            };
+            return Ok(self.boolean_value(result));
+        }
+
+        let left = self.expect_float(left, "the left operand")?;
+        let right = self.expect_float(right, "the right operand")?;
+
+        let predicate = match binop.operator {
+            BinaryOperator::Add => {
+                let sum = self.builder.build_float_add(left, right, "add")?;
+                return Ok(self.float_value(sum));
+            }
+            BinaryOperator::Subtract => {
+                let difference = self.builder.build_float_sub(left, right, "sub")?;
+                return Ok(self.float_value(difference));
+            }
+            BinaryOperator::Multiply => {
+                let product = self.builder.build_float_mul(left, right, "mul")?;
+                return Ok(self.float_value(product));
+            }
+            BinaryOperator::Divide => {
+                let current_fn = self.current_function();
+                let zero_block = self.context.append_basic_block(current_fn, "div.zero");
+                let divide_block = self.context.append_basic_block(current_fn, "div");
+                let zero = self.get_float_type().const_zero();
+                let is_zero = self.builder.build_float_compare(
+                    FloatPredicate::OEQ,
+                    right,
+                    zero,
+                    "div.is_zero",
+                )?;
+                self.builder
+                    .build_conditional_branch(is_zero, zero_block, divide_block)?;
+
+                self.builder.position_at_end(zero_block);
+                self.build_runtime_error("division by zero")?;
+
+                self.builder.position_at_end(divide_block);
+                let quotient = self.builder.build_float_div(left, right, "div")?;
+                return Ok(self.float_value(quotient));
+            }
+            BinaryOperator::GreaterThan => FloatPredicate::OGT,
+            BinaryOperator::LessThan => FloatPredicate::OLT,
+            BinaryOperator::GreaterThanOrEqual => FloatPredicate::OGE,
+            BinaryOperator::LessThanOrEqual => FloatPredicate::OLE,
+            BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::And
+            | BinaryOperator::Or => {
+                unreachable!("handled before the operands are checked")
+            }
+        };
+        let comparison = self
+            .builder
+            .build_float_compare(predicate, left, right, "cmp")?;
+        Ok(self.boolean_value(comparison))
+    }
+
+    fn compile_value_call(
+        &mut self,
+        name: &str,
+        arguments: &[Expression],
+    ) -> Result<TypedValue<'ctx>, Box<dyn Error>> {
+        let mut values = self.compile_call(name, arguments)?;
+        match values.len() {
+            1 => Ok(values.remove(0)),
+            0 => Err(error(
+                codes::VALUE_COUNT,
+                format!("Function '{}' does not return a value", name),
+            )),
+            count => Err(Box::new(
+                Diagnostic::error(
+                    codes::VALUE_COUNT,
+                    format!("Function '{}' returns {} values", name, count),
+                )
+                .with_help(format!("unpack them with `let a, b be {}`", name)),
+            )),
+        }
+    }
+
+    fn generate_print_call(&mut self, value: TypedValue<'ctx>) -> Result<(), Box<dyn Error>> {
+        self.build_print_value(&value.var_type, value.value)?;
+        self.build_printf("\n", &[])
+    }
+
+    /// Prints a value without a trailing newline. Lists print as `[1, 2]`.
+    fn build_print_value(
+        &mut self,
+        var_type: &VariableType,
+        value: BasicValueEnum<'ctx>,
+    ) -> Result<(), Box<dyn Error>> {
+        match var_type {
+            VariableType::Float => self.build_printf("%.16g", &[value.into()]),
+            VariableType::String => self.build_printf("%s", &[value.into()]),
+            VariableType::Boolean => {
+                let true_str = self.builder.build_global_string_ptr("true", "true")?;
+                let false_str = self.builder.build_global_string_ptr("false", "false")?;
+                let text = self.builder.build_select(
+                    value.into_int_value(),
+                    true_str.as_pointer_value(),
+                    false_str.as_pointer_value(),
+                    "bool_text",
+                )?;
+                self.build_printf("%s", &[text.into()])
+            }
+            VariableType::Nothing => self.build_printf("nothing", &[]),
+            VariableType::List(element_type) => {
+                self.build_printf("[", &[])?;
+                if let Some(element_type) = element_type {
+                    self.build_print_elements(element_type, value.into_pointer_value())?;
+                }
+                self.build_printf("]", &[])
+            }
+            VariableType::Object(fields) => {
+                // Fields print in name order, whatever order they were added in
+                let object = value.into_pointer_value();
+                self.build_printf("{", &[])?;
+                for (index, (name, field_type)) in fields.iter().enumerate() {
+                    let separator = if index > 0 { ", " } else { "" };
+                    self.build_printf(&format!("{}{}: ", separator, name), &[])?;
+                    let field = self.load_object_field(object, name, field_type.clone())?;
+                    self.build_print_value(field_type, field.value)?;
                 }
+                self.build_printf("}", &[])
             }
-            Expression::FunctionCall(call) => Ok(format!("<function call: {}>", call.name)),
         }
     }
+
+    fn build_print_elements(
+        &mut self,
+        element_type: &VariableType,
+        list: PointerValue<'ctx>,
+    ) -> Result<(), Box<dyn Error>> {
+        let i64_type = self.context.i64_type();
+        let current_fn = self.current_function();
+        let preheader_block = self.builder.get_insert_block().unwrap();
+        let header_block = self.context.append_basic_block(current_fn, "print.header");
+        let body_block = self.context.append_basic_block(current_fn, "print.body");
+        let exit_block = self.context.append_basic_block(current_fn, "print.exit");
+        self.builder.build_unconditional_branch(header_block)?;
+
+        self.builder.position_at_end(header_block);
+        let index = self.builder.build_phi(i64_type, "print.index")?;
+        index.add_incoming(&[(&i64_type.const_zero(), preheader_block)]);
+        let index_value = index.as_basic_value().into_int_value();
+        let length = self.build_list_length(list)?;
+        let keep_going =
+            self.builder
+                .build_int_compare(IntPredicate::SLT, index_value, length, "print.cond")?;
+        self.builder
+            .build_conditional_branch(keep_going, body_block, exit_block)?;
+
+        self.builder.position_at_end(body_block);
+        let is_first = self.builder.build_int_compare(
+            IntPredicate::EQ,
+            index_value,
+            i64_type.const_zero(),
+            "print.first",
+        )?;
+        let comma = self.builder.build_global_string_ptr(", ", "comma")?;
+        let nothing = self.builder.build_global_string_ptr("", "nothing")?;
+        let separator = self.builder.build_select(
+            is_first,
+            nothing.as_pointer_value(),
+            comma.as_pointer_value(),
+            "separator",
+        )?;
+        self.build_printf("%s", &[separator.into()])?;
+        let slot = self.build_list_slot(list, index_value)?;
+        let element = self
+            .builder
+            .build_load(self.llvm_type(element_type), slot, "element")?;
+        self.build_print_value(element_type, element)?;
+        // Nested lists add blocks of their own, so continue from wherever
+        // printing the element left off
+        let latch_block = self.builder.get_insert_block().unwrap();
+        let next =
+            self.builder
+                .build_int_add(index_value, i64_type.const_int(1, false), "print.next")?;
+        index.add_incoming(&[(&next, latch_block)]);
+        self.builder.build_unconditional_branch(header_block)?;
+
+        self.builder.position_at_end(exit_block);
+        Ok(())
+    }
+
+    fn build_printf(
+        &mut self,
+        format: &str,
+        args: &[BasicMetadataValueEnum<'ctx>],
+    ) -> Result<(), Box<dyn Error>> {
+        let printf_fn = self.module.get_function("printf").unwrap();
+        let format_str = self.builder.build_global_string_ptr(format, "fmt")?;
+
+        let mut call_args = vec![format_str.as_pointer_value().into()];
+        call_args.extend_from_slice(args);
+        self.builder
+            .build_call(printf_fn, &call_args, "printf_call")?;
+
+        Ok(())
+    }
+
+    pub fn save_to_file(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        self.module.print_to_file(path)?;
+        Ok(())
+    }
+
+    /// The generated module as LLVM IR text.
+    pub fn ir(&self) -> String {
+        self.module.print_to_string().to_string()
+    }
 }
@@ -0,0 +1,275 @@
+//! DWARF debug information, so gdb and lldb can step through a compiled
+//! program by its `.de` source.
+//!
+//! The file is one compile unit. `main` and each compiled instance of a
+//! `define` get a subprogram, every statement sets the line the following
+//! instructions belong to, and each local gets a variable descriptor on its
+//! stack slot. Delta has no DWARF language of its own, so the unit claims to
+//! be C, which makes debuggers print numbers, booleans and strings sensibly.
+//!
+//! Runtime helpers and the scratch bodies used to infer return types get no
+//! debug information at all.
+//!
+//! The location is tracked here rather than read back from the builder:
+//! LLVM's C API reports "no location" as an empty metadata node, and
+//! setting that node again makes the module fail to verify.
+
+use super::{CodeGenerator, VariableType};
+use delta::span::Span;
+use inkwell::AddressSpace;
+use inkwell::debug_info::{
+    AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants, DILocation, DIScope, DIType,
+    DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+};
+use inkwell::module::FlagBehavior;
+use inkwell::values::{FunctionValue, PointerValue};
+use std::cell::Cell;
+use std::path::Path;
+
+// DW_ATE_* encodings of the basic types
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED_CHAR: u32 = 0x06;
+const DW_ATE_UNSIGNED: u32 = 0x08;
+
+pub(super) struct DebugInfo<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    unit: DICompileUnit<'ctx>,
+    file: DIFile<'ctx>,
+    optimized: bool,
+    source: String,
+    // Byte offset of the start of each line, for turning spans into lines
+    line_starts: Vec<usize>,
+    // The function being compiled, innermost last. `None` for a function
+    // that gets no debug information.
+    scopes: Vec<Option<DIScope<'ctx>>>,
+    // The location the builder attributes instructions to
+    location: Cell<Option<DILocation<'ctx>>>,
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    /// The 1-based line and column of the start of `span`.
+    fn line_col(&self, span: Span) -> (u32, u32) {
+        let start = span.start.min(self.source.len());
+        let line = self
+            .line_starts
+            .partition_point(|&line_start| line_start <= start);
+        let line_start = self.line_starts[line - 1];
+        let column = self.source[line_start..start].chars().count() + 1;
+        (line as u32, column as u32)
+    }
+
+    fn scope(&self) -> Option<DIScope<'ctx>> {
+        self.scopes.last().copied().flatten()
+    }
+
+    fn basic_type(&self, name: &str, size_in_bits: u64, encoding: u32) -> DIType<'ctx> {
+        self.builder
+            .create_basic_type(name, size_in_bits, encoding, DIFlags::PUBLIC)
+            .expect("basic types have names")
+            .as_type()
+    }
+
+    fn di_type(&self, var_type: &VariableType) -> DIType<'ctx> {
+        let pointer_to = |name: &str, pointee: DIType<'ctx>| {
+            self.builder
+                .create_pointer_type(name, pointee, 64, 64, AddressSpace::default())
+                .as_type()
+        };
+        match var_type {
+            VariableType::Float => self.basic_type("number", 64, DW_ATE_FLOAT),
+            VariableType::Boolean => self.basic_type("boolean", 8, DW_ATE_BOOLEAN),
+            VariableType::String => {
+                pointer_to("string", self.basic_type("char", 8, DW_ATE_SIGNED_CHAR))
+            }
+            // Lists, objects and `nothing` are shown as plain addresses
+            other => pointer_to(
+                &other.to_string(),
+                self.basic_type("byte", 8, DW_ATE_UNSIGNED),
+            ),
+        }
+    }
+}
+
+impl<'ctx> CodeGenerator<'ctx> {
+    /// Turns on debug information for the program in `source`, read from
+    /// `path`. Must come before `compile`.
+    pub fn enable_debug_info(&mut self, path: &Path, source: &str, optimized: bool) {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let filename = path
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        let directory = path
+            .parent()
+            .map_or_else(String::new, |parent| parent.to_string_lossy().into_owned());
+
+        let (builder, unit) = self.module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &filename,
+            &directory,
+            concat!("delta ", env!("CARGO_PKG_VERSION")),
+            optimized,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+
+        let i32_type = self.context.i32_type();
+        self.module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            i32_type.const_int(3, false),
+        );
+        self.module.add_basic_value_flag(
+            "Dwarf Version",
+            FlagBehavior::Warning,
+            i32_type.const_int(4, false),
+        );
+
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        self.debug = Some(DebugInfo {
+            file: unit.get_file(),
+            builder,
+            unit,
+            optimized,
+            source: source.to_string(),
+            line_starts,
+            scopes: Vec::new(),
+            location: Cell::new(None),
+        });
+    }
+
+    /// Gives `function` a subprogram and makes it the scope of the
+    /// locations set from here on, until the matching `debug_leave_function`.
+    /// `span` is where the function is defined. Without `span`, the function
+    /// gets no debug information.
+    pub(super) fn debug_enter_function(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        name: &str,
+        span: Option<Span>,
+    ) {
+        let Some(debug) = &mut self.debug else {
+            return;
+        };
+        let Some(span) = span else {
+            debug.scopes.push(None);
+            self.debug_set_location(None);
+            return;
+        };
+
+        let (line, _) = debug.line_col(span);
+        let symbol = function.get_name().to_string_lossy().into_owned();
+        let linkage_name = (symbol != name).then_some(symbol.as_str());
+        let subroutine_type =
+            debug
+                .builder
+                .create_subroutine_type(debug.file, None, &[], DIFlags::ZERO);
+        let subprogram = debug.builder.create_function(
+            debug.unit.as_debug_info_scope(),
+            name,
+            linkage_name,
+            debug.file,
+            line,
+            subroutine_type,
+            false,
+            true,
+            line,
+            DIFlags::PUBLIC,
+            debug.optimized,
+        );
+        function.set_subprogram(subprogram);
+        debug.scopes.push(Some(subprogram.as_debug_info_scope()));
+        self.debug_location(span);
+    }
+
+    pub(super) fn debug_leave_function(&mut self) {
+        if let Some(debug) = &mut self.debug {
+            debug.scopes.pop();
+        }
+    }
+
+    /// Attributes the instructions built from here on to the source at
+    /// `span`.
+    pub(super) fn debug_location(&self, span: Span) {
+        let Some(debug) = &self.debug else {
+            return;
+        };
+        let location = debug.scope().map(|scope| {
+            let (line, column) = debug.line_col(span);
+            debug
+                .builder
+                .create_debug_location(self.context, line, column, scope, None)
+        });
+        self.debug_set_location(location);
+    }
+
+    /// The location instructions are attributed to, to give back to
+    /// `debug_set_location` later.
+    pub(super) fn debug_current_location(&self) -> Option<DILocation<'ctx>> {
+        self.debug.as_ref().and_then(|debug| debug.location.get())
+    }
+
+    /// Attributes the instructions built from here on to `location`, or to
+    /// no source at all.
+    pub(super) fn debug_set_location(&self, location: Option<DILocation<'ctx>>) {
+        let Some(debug) = &self.debug else {
+            return;
+        };
+        debug.location.set(location);
+        match location {
+            Some(location) => self.builder.set_current_debug_location(location),
+            None => self.builder.unset_current_debug_location(),
+        }
+    }
+
+    /// Describes the local `name`, which lives in `pointer`, at the current
+    /// location.
+    pub(super) fn debug_variable(
+        &self,
+        name: &str,
+        pointer: PointerValue<'ctx>,
+        var_type: &VariableType,
+    ) {
+        let Some(debug) = &self.debug else {
+            return;
+        };
+        let (Some(scope), Some(location), Some(block)) = (
+            debug.scope(),
+            debug.location.get(),
+            self.builder.get_insert_block(),
+        ) else {
+            return;
+        };
+
+        let variable = debug.builder.create_auto_variable(
+            scope,
+            name,
+            debug.file,
+            location.get_line(),
+            debug.di_type(var_type),
+            true,
+            DIFlags::ZERO,
+            0,
+        );
+        debug
+            .builder
+            .insert_declare_at_end(pointer, Some(variable), None, location, block);
+    }
+
+    /// Resolves the debug information once the whole module is built.
+    pub(super) fn finish_debug_info(&self) {
+        if let Some(debug) = &self.debug {
+            debug.builder.finalize();
+        }
+    }
+}
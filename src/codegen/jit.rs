@@ -0,0 +1,334 @@
+//! Running compiled code in process: whole programs for `delta run --jit`,
+//! and one entry at a time for `delta repl --jit`.
+//!
+//! A program's module is the one `build` would link, so it behaves exactly
+//! like the executable: its output goes through the C library's `printf`,
+//! and runtime errors end the process through `exit` with the same status.
+//!
+//! A REPL session compiles each entry into a module of its own and adds it
+//! to one execution engine, where it links to the functions and variables
+//! of the entries before it instead of running them again. Top-level
+//! variables live in globals rather than on the stack for that reason, and
+//! `exit` is replaced by a function that unwinds out of the entry, so a
+//! runtime error ends the entry rather than the session.
+
+use super::{
+    CodeGenerator, FunctionInstance, InstanceKey, OptLevel, Scope, VariableType, error, locate,
+    target_machine,
+};
+use crate::ast::{FunctionDef, Program, StatementKind};
+use delta::diagnostics::{Diagnostic, codes};
+use inkwell::OptimizationLevel;
+use inkwell::context::Context;
+use inkwell::execution_engine::ExecutionEngine;
+use inkwell::module::Module;
+use inkwell::targets::TargetMachine;
+use inkwell::types::BasicTypeEnum;
+use inkwell::values::{FunctionValue, PointerValue};
+use std::collections::HashMap;
+use std::error::Error;
+use std::ffi::{c_int, c_void};
+use std::mem::{self, Discriminant};
+use std::panic;
+use std::ptr;
+
+/// The signature of the `main` every module defines.
+type MainFunction = unsafe extern "C" fn() -> i32;
+
+/// The signature of a REPL entry, which may unwind through `end_entry`.
+type EntryFunction = unsafe extern "C-unwind" fn();
+
+unsafe extern "C" {
+    fn fflush(stream: *mut c_void) -> c_int;
+}
+
+/// Stands in for `exit` while a session runs an entry.
+extern "C-unwind" fn end_entry(status: c_int) {
+    panic::resume_unwind(Box::new(status))
+}
+
+impl<'ctx> CodeGenerator<'ctx> {
+    /// Compiles the module to machine code in memory and calls its `main`,
+    /// returning the status it returns.
+    pub fn run_jit(&self, level: OptLevel) -> Result<i32, Box<dyn Error>> {
+        let engine = self
+            .module
+            .create_jit_execution_engine(level.machine_level())
+            .map_err(|message| {
+                error(
+                    codes::INTERNAL,
+                    format!("Could not start the JIT: {}", message),
+                )
+            })?;
+
+        // SAFETY: `compile` always defines `main` with this signature, and the
+        // engine outlives the call
+        let main = unsafe { engine.get_function::<MainFunction>("main") }
+            .map_err(|err| error(codes::INTERNAL, format!("No 'main' to run: {}", err)))?;
+        Ok(unsafe { main.call() })
+    }
+
+    /// In a session, a new global for a variable set at the top level of an
+    /// entry, where later entries can find it. `None` anywhere else, where
+    /// variables live on the stack.
+    pub(super) fn session_global(
+        &mut self,
+        var_type: &VariableType,
+        name: &str,
+    ) -> Option<PointerValue<'ctx>> {
+        let current = self.builder.get_insert_block()?.get_parent()?;
+        let llvm_type = self.llvm_type(var_type);
+        let session = self.session.as_mut()?;
+        if session.function != Some(current) {
+            return None;
+        }
+
+        let symbol = format!("delta.repl.{}.{}", name, session.globals.len());
+        let global = self.module.add_global(llvm_type, None, &symbol);
+        global.set_initializer(&llvm_type.const_zero());
+        session.globals.push((symbol, llvm_type));
+        Some(global.as_pointer_value())
+    }
+
+    /// Compiles one REPL entry into the current module, as a function of its
+    /// own, and returns the function's name. An entry that is a lone
+    /// expression prints its values, as the interpreter's REPL does.
+    fn compile_entry(&mut self, program: &Program) -> Result<String, Box<dyn Error>> {
+        self.add_printf_declaration();
+        self.add_exit_declaration();
+        self.declare_earlier_entries();
+
+        let session = self.session.as_mut().expect("entries belong to a session");
+        session.entry += 1;
+        let name = format!("delta.entry.{}", session.entry);
+        let entry_type = self.context.void_type().fn_type(&[], false);
+        let function = self.module.add_function(&name, entry_type, None);
+        session.function = Some(function);
+        let block = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(block);
+
+        // A `define` replaces any earlier function of the same name, and with
+        // it every compiled function that might call the old one
+        let mut replaced = false;
+        for statement in &program.statements {
+            if let StatementKind::FunctionDef(func_def) = &statement.kind {
+                replaced |= self.functions.remove(&func_def.name).is_some();
+            }
+        }
+        if replaced {
+            self.instances.clear();
+        }
+        self.register_functions(&program.statements)?;
+
+        if let [statement] = program.statements.as_slice()
+            && let StatementKind::Expression(expression) = &statement.kind
+        {
+            let values = self
+                .compile_values(expression)
+                .map_err(|err| locate(err, statement.span))?;
+            let silent = match values.as_slice() {
+                [] => true,
+                [value] => value.var_type == VariableType::Nothing,
+                _ => false,
+            };
+            if !silent {
+                for (index, value) in values.iter().enumerate() {
+                    if index > 0 {
+                        self.build_printf(", ", &[])?;
+                    }
+                    self.build_print_value(&value.var_type, value.value)?;
+                }
+                self.build_printf("\n", &[])?;
+            }
+        } else {
+            self.compile_statements(&program.statements)?;
+        }
+        self.builder.build_return(None)?;
+
+        if let Err(errors) = self.module.verify() {
+            return Err(Box::new(
+                Diagnostic::error(
+                    codes::INTERNAL,
+                    format!("Module verification failed: {}", errors),
+                )
+                .with_help("this is a bug in the compiler"),
+            ));
+        }
+        Ok(name)
+    }
+
+    /// Declares the globals and functions of earlier entries in the current
+    /// module, and points the variables and instances at the declarations.
+    fn declare_earlier_entries(&mut self) {
+        let session = self.session.as_ref().expect("entries belong to a session");
+        for (symbol, llvm_type) in &session.globals {
+            self.module.add_global(*llvm_type, None, symbol);
+        }
+        let redeclare = |pointer: &mut PointerValue<'ctx>| {
+            let symbol = pointer.get_name().to_string_lossy().into_owned();
+            if let Some(global) = self.module.get_global(&symbol) {
+                *pointer = global.as_pointer_value();
+            }
+        };
+        for variable in self.variables.values_mut() {
+            redeclare(&mut variable.pointer);
+        }
+        for pointer in self.slots.values_mut() {
+            redeclare(pointer);
+        }
+
+        for instance in self.instances.values_mut() {
+            let symbol = instance.function.get_name().to_string_lossy().into_owned();
+            let function_type = instance.function.get_type();
+            instance.function = self.module.add_function(&symbol, function_type, None);
+        }
+    }
+}
+
+/// What a session's code generator keeps from one entry to the next.
+pub(super) struct SessionState<'ctx> {
+    // Counts the entries compiled, which keeps their symbols apart
+    pub(super) entry: usize,
+    // The function holding the top level of the entry being compiled
+    function: Option<FunctionValue<'ctx>>,
+    // The globals holding top-level variables, by symbol, with their types
+    globals: Vec<(String, BasicTypeEnum<'ctx>)>,
+}
+
+/// The compiler's state before an entry, given back when the entry fails.
+struct Snapshot<'ctx> {
+    variables: Scope<'ctx>,
+    slots: HashMap<(String, Discriminant<VariableType>), PointerValue<'ctx>>,
+    functions: HashMap<String, FunctionDef>,
+    instances: HashMap<InstanceKey, FunctionInstance<'ctx>>,
+    globals: usize,
+    entry: usize,
+}
+
+/// A program compiled and run one entry at a time, for `delta repl --jit`.
+pub struct JitSession<'ctx> {
+    codegen: CodeGenerator<'ctx>,
+    engine: ExecutionEngine<'ctx>,
+    machine: TargetMachine,
+    // The modules of earlier entries, which the engine keeps running code from
+    modules: Vec<Module<'ctx>>,
+}
+
+impl<'ctx> JitSession<'ctx> {
+    pub fn new(context: &'ctx Context) -> Result<Self, Box<dyn Error>> {
+        let machine = target_machine(None, OptimizationLevel::None)?;
+        let mut codegen = CodeGenerator::new(context, "delta_repl")?;
+        codegen.set_target(&machine);
+        codegen.add_exit_declaration();
+        let engine = codegen
+            .module
+            .create_jit_execution_engine(OptimizationLevel::None)
+            .map_err(|message| {
+                error(
+                    codes::INTERNAL,
+                    format!("Could not start the JIT: {}", message),
+                )
+            })?;
+        // Mappings go by name, so this covers the `exit` of every entry
+        let exit = codegen.module.get_function("exit").unwrap();
+        engine.add_global_mapping(&exit, end_entry as *const () as usize);
+
+        codegen.session = Some(SessionState {
+            entry: 0,
+            function: None,
+            globals: Vec::new(),
+        });
+        Ok(JitSession {
+            codegen,
+            engine,
+            machine,
+            modules: Vec::new(),
+        })
+    }
+
+    /// Compiles the next entry and runs it. Errors found while compiling are
+    /// returned, and leave the session as it was. A runtime error has been
+    /// reported by the entry itself and gives `Ok(false)`; what the entry
+    /// did to existing variables and functions before it stays done.
+    pub fn run(&mut self, program: &Program) -> Result<bool, Box<dyn Error>> {
+        let snapshot = self.snapshot();
+        let session = self.codegen.session.as_ref().expect("sessions have state");
+        let module = self
+            .codegen
+            .context
+            .create_module(&format!("delta_repl.{}", session.entry + 1));
+        let previous = mem::replace(&mut self.codegen.module, module);
+        self.codegen.set_target(&self.machine);
+
+        let name = match self.codegen.compile_entry(program) {
+            Ok(name) => name,
+            Err(err) => {
+                self.codegen.module = previous;
+                self.restore(snapshot);
+                return Err(err);
+            }
+        };
+        self.modules.push(previous);
+        self.engine
+            .add_module(&self.codegen.module)
+            .map_err(|()| error(codes::INTERNAL, "Could not add the entry to the JIT"))?;
+        let address = self
+            .engine
+            .get_function_address(&name)
+            .map_err(|err| error(codes::INTERNAL, format!("No '{}' to run: {}", name, err)))?;
+
+        // SAFETY: `compile_entry` defines the entry with this signature, the
+        // engine outlives the call, and only `end_entry` unwinds through it
+        let entry = unsafe { mem::transmute::<usize, EntryFunction>(address) };
+        let finished = panic::catch_unwind(|| unsafe { entry() }).is_ok();
+        // The entry printed through C's stdio, which may still hold the output
+        unsafe { fflush(ptr::null_mut()) };
+
+        if !finished {
+            // Variables the entry had yet to set would hold garbage
+            self.codegen.variables = snapshot.variables;
+            self.codegen.slots = snapshot.slots;
+        }
+        Ok(finished)
+    }
+
+    /// Returns the warnings found so far, leaving none behind.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        self.codegen.take_warnings()
+    }
+
+    /// The IR of every entry so far.
+    pub fn ir(&self) -> String {
+        self.modules
+            .iter()
+            .chain([&self.codegen.module])
+            .map(|module| module.print_to_string().to_string())
+            .collect()
+    }
+
+    fn snapshot(&self) -> Snapshot<'ctx> {
+        let codegen = &self.codegen;
+        let session = codegen.session.as_ref().expect("sessions have state");
+        Snapshot {
+            variables: codegen.variables.clone(),
+            slots: codegen.slots.clone(),
+            functions: codegen.functions.clone(),
+            instances: codegen.instances.clone(),
+            globals: session.globals.len(),
+            entry: session.entry,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot<'ctx>) {
+        let codegen = &mut self.codegen;
+        codegen.variables = snapshot.variables;
+        codegen.slots = snapshot.slots;
+        codegen.functions = snapshot.functions;
+        codegen.instances = snapshot.instances;
+        codegen.loops.clear();
+        codegen.frame = None;
+        let session = codegen.session.as_mut().expect("sessions have state");
+        session.globals.truncate(snapshot.globals);
+        session.entry = snapshot.entry;
+    }
+}
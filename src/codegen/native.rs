@@ -0,0 +1,221 @@
+//! Turning the module into machine code with LLVM's own backend, and
+//! linking the result into an executable.
+//!
+//! Object files, assembly and bitcode are written in process. Linking goes
+//! through the target's system linker: the C compiler driver `cc`, which
+//! knows where the C runtime and libc live on this machine, or `link.exe`
+//! for MSVC targets. Nothing else from a C toolchain is needed.
+
+use super::{CodeGenerator, error};
+use delta::diagnostics::codes;
+use inkwell::OptimizationLevel;
+use inkwell::targets::{
+    CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+};
+use std::error::Error;
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Command;
+
+/// A file format the module can be written in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NativeFormat {
+    Assembly,
+    Object,
+    Bitcode,
+}
+
+/// Creates a machine for `triple`, or for this machine when there is none.
+/// Only the host gets its exact CPU and features; other targets get the
+/// generic CPU so the output runs anywhere the triple does.
+pub fn target_machine(
+    triple: Option<&str>,
+    level: OptimizationLevel,
+) -> Result<TargetMachine, Box<dyn Error>> {
+    Target::initialize_all(&InitializationConfig::default());
+
+    let (triple, cpu, features) = match triple {
+        Some(triple) => (
+            TargetMachine::normalize_triple(&TargetTriple::create(triple)),
+            String::new(),
+            String::new(),
+        ),
+        None => (
+            TargetMachine::get_default_triple(),
+            TargetMachine::get_host_cpu_name().to_string(),
+            TargetMachine::get_host_cpu_features().to_string(),
+        ),
+    };
+
+    let target = Target::from_triple(&triple).map_err(|message| {
+        error(
+            codes::INTERNAL,
+            format!("Unknown target '{}': {}", triple, message),
+        )
+    })?;
+    target
+        .create_target_machine(
+            &triple,
+            &cpu,
+            &features,
+            level,
+            // Position independent, as most systems link executables as PIE
+            RelocMode::PIC,
+            CodeModel::Default,
+        )
+        .ok_or_else(|| {
+            error(
+                codes::INTERNAL,
+                format!("LLVM cannot generate code for '{}'", triple),
+            )
+        })
+}
+
+impl<'ctx> CodeGenerator<'ctx> {
+    /// Records `machine`'s triple and data layout in the module. This comes
+    /// before any output, so IR written afterwards describes the target too.
+    pub fn set_target(&self, machine: &TargetMachine) {
+        self.module.set_triple(&machine.get_triple());
+        self.module
+            .set_data_layout(&machine.get_target_data().get_data_layout());
+    }
+
+    /// Writes the compiled module for `machine` in the given format.
+    pub fn write_native(
+        &self,
+        machine: &TargetMachine,
+        format: NativeFormat,
+        path: &Path,
+    ) -> Result<(), Box<dyn Error>> {
+        let written = match format {
+            NativeFormat::Assembly => machine
+                .write_to_file(&self.module, FileType::Assembly, path)
+                .map_err(|message| message.to_string()),
+            NativeFormat::Object => machine
+                .write_to_file(&self.module, FileType::Object, path)
+                .map_err(|message| message.to_string()),
+            NativeFormat::Bitcode => match self.module.write_bitcode_to_path(path) {
+                true => Ok(()),
+                false => Err("LLVM could not write the bitcode".to_string()),
+            },
+        };
+        written.map_err(|message| {
+            error(
+                codes::INTERNAL,
+                format!("Failed to write '{}': {}", path.display(), message),
+            )
+        })
+    }
+}
+
+/// The C runtime libraries an MSVC executable needs. Objects from clang or
+/// cl name them in directives, but LLVM does not add those to ours.
+const MSVC_LIBRARIES: &[&str] = &[
+    "libcmt.lib",
+    "libucrt.lib",
+    "libvcruntime.lib",
+    // printf is inline in the UCRT headers; this has the out-of-line one
+    "legacy_stdio_definitions.lib",
+    // POSIX names such as `write`
+    "oldnames.lib",
+    "kernel32.lib",
+];
+
+/// Whether `triple` links with the MSVC toolchain rather than a Unix-style
+/// C compiler driver.
+fn is_msvc(triple: &str) -> bool {
+    triple.contains("windows-msvc")
+}
+
+/// The linker used for `triple` when none is given: the C compiler driver
+/// `cc`, or `link.exe` for MSVC targets, which have no `cc`.
+pub fn default_linker(triple: &str) -> &'static str {
+    if is_msvc(triple) { "link.exe" } else { "cc" }
+}
+
+/// The command that links object files into an executable for `triple`.
+/// MSVC targets take `link.exe`-style arguments, which `lld-link` accepts
+/// too; everything else takes those of a C compiler driver.
+fn link_command(linker: &str, triple: &str, objects: &[&Path], output: &Path) -> Command {
+    let mut command = Command::new(linker);
+    if is_msvc(triple) {
+        let mut out = OsString::from("/OUT:");
+        out.push(output);
+        command
+            .arg("/NOLOGO")
+            .arg("/SUBSYSTEM:CONSOLE")
+            .arg(out)
+            .args(objects)
+            .args(MSVC_LIBRARIES);
+    } else {
+        command.args(objects).arg("-o").arg(output);
+    }
+    command
+}
+
+/// Links object files into an executable for `triple` with `linker`, or
+/// with the default linker for the target when there is none. Unix-style
+/// linkers are C compiler drivers, which know where the C runtime and libc
+/// live; cross-compiled objects need one for that target, such as
+/// `aarch64-linux-gnu-gcc`. `link.exe` finds the MSVC libraries through the
+/// `LIB` variable that a developer command prompt sets. The error is a
+/// message for the user.
+pub fn link(
+    linker: Option<&str>,
+    triple: &str,
+    objects: &[&Path],
+    output: &Path,
+) -> Result<(), String> {
+    let linker = linker.unwrap_or_else(|| default_linker(triple));
+    let status = link_command(linker, triple, objects, output).status();
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(format!("Linking with '{}' failed: {}", linker, status)),
+        Err(err) => Err(format!("Could not run the linker '{}': {}", linker, err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(triple: &str) -> Vec<String> {
+        let objects = [Path::new("hello.o")];
+        let command = link_command(default_linker(triple), triple, &objects, Path::new("hello"));
+        let mut arguments = vec![command.get_program().to_string_lossy().into_owned()];
+        arguments.extend(
+            command
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned()),
+        );
+        arguments
+    }
+
+    #[test]
+    fn each_target_links_with_its_own_toolchain() {
+        assert_eq!(
+            arguments("x86_64-unknown-linux-gnu"),
+            ["cc", "hello.o", "-o", "hello"]
+        );
+        assert_eq!(
+            arguments("aarch64-apple-darwin"),
+            ["cc", "hello.o", "-o", "hello"]
+        );
+
+        let msvc = arguments("x86_64-pc-windows-msvc");
+        assert_eq!(
+            msvc[..5],
+            [
+                "link.exe",
+                "/NOLOGO",
+                "/SUBSYSTEM:CONSOLE",
+                "/OUT:hello",
+                "hello.o"
+            ]
+        );
+        assert!(msvc.iter().any(|arg| arg == "libcmt.lib"));
+        // MinGW has a C compiler driver like any other
+        assert_eq!(arguments("x86_64-pc-windows-gnu")[0], "cc");
+    }
+}
@@ -0,0 +1,72 @@
+//! Optimisation with LLVM's new pass manager.
+//!
+//! Each level runs LLVM's standard pipeline for it, the same one `clang -O2`
+//! or `opt -passes='default<O2>'` would. Those pipelines already cover what
+//! the generated code needs most: mem2reg to turn the allocas every local
+//! lives in into registers, instcombine, GVN, inlining of small functions,
+//! and the loop passes.
+
+use super::{CodeGenerator, error};
+use delta::diagnostics::codes;
+use inkwell::OptimizationLevel;
+use inkwell::passes::PassBuilderOptions;
+use inkwell::targets::TargetMachine;
+use std::error::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OptLevel {
+    O0,
+    O1,
+    O2,
+    O3,
+    /// Like `O2`, but avoids optimisations that grow the code
+    Os,
+    /// Smallest code, even at some cost in speed
+    Oz,
+}
+
+impl OptLevel {
+    /// The level's flag without the dash, as in `O2`.
+    pub fn name(self) -> &'static str {
+        match self {
+            OptLevel::O0 => "O0",
+            OptLevel::O1 => "O1",
+            OptLevel::O2 => "O2",
+            OptLevel::O3 => "O3",
+            OptLevel::Os => "Os",
+            OptLevel::Oz => "Oz",
+        }
+    }
+
+    /// The level for instruction selection and scheduling in the backend.
+    /// The size levels use the default, as clang does.
+    pub fn machine_level(self) -> OptimizationLevel {
+        match self {
+            OptLevel::O0 => OptimizationLevel::None,
+            OptLevel::O1 => OptimizationLevel::Less,
+            OptLevel::O2 | OptLevel::Os | OptLevel::Oz => OptimizationLevel::Default,
+            OptLevel::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+}
+
+impl<'ctx> CodeGenerator<'ctx> {
+    /// Runs the pipeline for `level` over the module. `O0` leaves the module
+    /// exactly as generated.
+    pub fn optimize(&self, machine: &TargetMachine, level: OptLevel) -> Result<(), Box<dyn Error>> {
+        if level == OptLevel::O0 {
+            return Ok(());
+        }
+
+        let options = PassBuilderOptions::create();
+        options.set_loop_vectorization(level == OptLevel::O3);
+        options.set_loop_slp_vectorization(matches!(level, OptLevel::O2 | OptLevel::O3));
+        options.set_loop_unrolling(!matches!(level, OptLevel::Os | OptLevel::Oz));
+        options.set_merge_functions(matches!(level, OptLevel::Os | OptLevel::Oz));
+
+        let passes = format!("default<{}>", level.name());
+        self.module
+            .run_passes(&passes, machine, options)
+            .map_err(|message| error(codes::INTERNAL, format!("Optimisation failed: {}", message)))
+    }
+}
@@ -0,0 +1,845 @@
+//! Support code emitted into every module that uses lists or objects.
+//!
+//! A list is a pointer to a heap header `{ i64 length, i64 capacity, ptr
+//! data }`. `data` holds 8-byte slots, which is enough for every value type,
+//! and doubles in size whenever it fills up.
+//!
+//! An object is a hash table with the same header shape, where `data` holds
+//! `{ ptr key, i64 value }` entries. Keys are C strings, found by FNV-1a hash
+//! and linear probing, and the table doubles once it is three quarters full.
+//!
+//! Both are shared by reference, so `add` on a list passed to a function is
+//! visible to the caller. The helpers are ordinary LLVM functions built on
+//! first use. They only manage memory; loading and storing the values is
+//! left to the caller, which knows their type.
+
+use super::{CodeGenerator, error};
+use delta::diagnostics::codes;
+use inkwell::IntPredicate;
+use inkwell::module::Linkage;
+use inkwell::types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType};
+use inkwell::values::{FunctionValue, IntValue, PointerValue};
+use std::error::Error;
+
+const SLOT_SIZE: u64 = 8;
+const MIN_CAPACITY: u64 = 4;
+const MIN_OBJECT_CAPACITY: u64 = 8;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+const LENGTH_FIELD: u32 = 0;
+const CAPACITY_FIELD: u32 = 1;
+const DATA_FIELD: u32 = 2;
+
+impl<'ctx> CodeGenerator<'ctx> {
+    pub(super) fn list_header_type(&self) -> StructType<'ctx> {
+        let i64_type = self.context.i64_type();
+        let ptr_type = self.get_string_type();
+        self.context
+            .struct_type(&[i64_type.into(), i64_type.into(), ptr_type.into()], false)
+    }
+
+    /// Allocates an empty list with room for `capacity` elements.
+    pub(super) fn build_list_new(
+        &mut self,
+        capacity: u64,
+    ) -> Result<PointerValue<'ctx>, Box<dyn Error>> {
+        let function = self.list_new_function()?;
+        let capacity = self.context.i64_type().const_int(capacity, false);
+        self.call_pointer_helper(function, &[capacity.into()], "list")
+    }
+
+    /// Appends an uninitialised slot to `list` and returns its address.
+    pub(super) fn build_list_push_slot(
+        &mut self,
+        list: PointerValue<'ctx>,
+    ) -> Result<PointerValue<'ctx>, Box<dyn Error>> {
+        let function = self.list_push_slot_function()?;
+        self.call_pointer_helper(function, &[list.into()], "list.slot")
+    }
+
+    /// Returns the address of element `index`, stopping the program with a
+    /// runtime error when it is out of range.
+    pub(super) fn build_list_checked_slot(
+        &mut self,
+        list: PointerValue<'ctx>,
+        index: IntValue<'ctx>,
+    ) -> Result<PointerValue<'ctx>, Box<dyn Error>> {
+        let function = self.list_checked_slot_function()?;
+        self.call_pointer_helper(function, &[list.into(), index.into()], "list.slot")
+    }
+
+    /// Returns the address of element `index` without a bounds check.
+    pub(super) fn build_list_slot(
+        &self,
+        list: PointerValue<'ctx>,
+        index: IntValue<'ctx>,
+    ) -> Result<PointerValue<'ctx>, Box<dyn Error>> {
+        let data = self.build_list_field(list, DATA_FIELD, "list.data")?;
+        let i64_type = self.context.i64_type();
+        // SAFETY: callers only pass indexes below the list's length
+        let slot = unsafe {
+            self.builder
+                .build_in_bounds_gep(i64_type, data, &[index], "list.slot")?
+        };
+        Ok(slot)
+    }
+
+    pub(super) fn build_list_length(
+        &self,
+        list: PointerValue<'ctx>,
+    ) -> Result<IntValue<'ctx>, Box<dyn Error>> {
+        let length = self.build_list_field(list, LENGTH_FIELD, "list.length")?;
+        Ok(length)
+    }
+
+    /// Removes element `index`, shifting the ones after it down by one.
+    pub(super) fn build_list_remove_at(
+        &mut self,
+        list: PointerValue<'ctx>,
+        index: IntValue<'ctx>,
+    ) -> Result<(), Box<dyn Error>> {
+        let function = self.list_remove_at_function()?;
+        self.builder
+            .build_call(function, &[list.into(), index.into()], "")?;
+        Ok(())
+    }
+
+    fn build_list_field<T: TryFrom<inkwell::values::BasicValueEnum<'ctx>>>(
+        &self,
+        list: PointerValue<'ctx>,
+        field: u32,
+        name: &str,
+    ) -> Result<T, Box<dyn Error>> {
+        let header = self.list_header_type();
+        let field_type = header.get_field_type_at_index(field).unwrap();
+        let pointer =
+            self.builder
+                .build_struct_gep(header, list, field, &format!("{}.ptr", name))?;
+        let value = self.builder.build_load(field_type, pointer, name)?;
+        T::try_from(value).map_err(|_| error(codes::INTERNAL, "Malformed list header"))
+    }
+
+    fn call_pointer_helper(
+        &self,
+        function: FunctionValue<'ctx>,
+        args: &[inkwell::values::BasicMetadataValueEnum<'ctx>],
+        name: &str,
+    ) -> Result<PointerValue<'ctx>, Box<dyn Error>> {
+        let call = self.builder.build_call(function, args, name)?;
+        Ok(call
+            .try_as_basic_value()
+            .left()
+            .expect("helpers returning a pointer")
+            .into_pointer_value())
+    }
+
+    /// Declares a C library function unless the module already has it.
+    pub(super) fn libc_function(
+        &self,
+        name: &str,
+        fn_type: FunctionType<'ctx>,
+    ) -> FunctionValue<'ctx> {
+        self.module
+            .get_function(name)
+            .unwrap_or_else(|| self.module.add_function(name, fn_type, None))
+    }
+
+    fn malloc_function(&self) -> FunctionValue<'ctx> {
+        let ptr_type = self.get_string_type();
+        let fn_type = ptr_type.fn_type(&[self.context.i64_type().into()], false);
+        self.libc_function("malloc", fn_type)
+    }
+
+    fn realloc_function(&self) -> FunctionValue<'ctx> {
+        let ptr_type = self.get_string_type();
+        let fn_type = ptr_type.fn_type(&[ptr_type.into(), self.context.i64_type().into()], false);
+        self.libc_function("realloc", fn_type)
+    }
+
+    fn memmove_function(&self) -> FunctionValue<'ctx> {
+        let ptr_type = self.get_string_type();
+        let fn_type = ptr_type.fn_type(
+            &[
+                ptr_type.into(),
+                ptr_type.into(),
+                self.context.i64_type().into(),
+            ],
+            false,
+        );
+        self.libc_function("memmove", fn_type)
+    }
+
+    /// Returns the helper called `name`, building its body with `build` the
+    /// first time it is asked for. The builder is restored afterwards.
+    fn runtime_helper(
+        &mut self,
+        name: &str,
+        params: &[BasicMetadataTypeEnum<'ctx>],
+        return_type: Option<BasicTypeEnum<'ctx>>,
+        build: impl FnOnce(&mut Self, FunctionValue<'ctx>) -> Result<(), Box<dyn Error>>,
+    ) -> Result<FunctionValue<'ctx>, Box<dyn Error>> {
+        if let Some(function) = self.module.get_function(name) {
+            return Ok(function);
+        }
+
+        let fn_type = match return_type {
+            Some(return_type) => return_type.fn_type(params, false),
+            None => self.context.void_type().fn_type(params, false),
+        };
+        let function = self
+            .module
+            .add_function(name, fn_type, Some(Linkage::Internal));
+
+        // Helpers have no source, so no debug locations either
+        let saved_block = self.builder.get_insert_block();
+        let saved_location = self.debug_current_location();
+        self.debug_set_location(None);
+        let entry = self.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        let result = build(self, function);
+        if let Some(block) = saved_block {
+            self.builder.position_at_end(block);
+        }
+        self.debug_set_location(saved_location);
+        result?;
+
+        Ok(function)
+    }
+
+    fn list_new_function(&mut self) -> Result<FunctionValue<'ctx>, Box<dyn Error>> {
+        let i64_type = self.context.i64_type();
+        self.runtime_helper(
+            "delta.list.new",
+            &[i64_type.into()],
+            Some(self.get_string_type().into()),
+            |this, function| {
+                let header_type = this.list_header_type();
+                let malloc = this.malloc_function();
+                let requested = function.get_nth_param(0).unwrap().into_int_value();
+
+                let minimum = i64_type.const_int(MIN_CAPACITY, false);
+                let too_small = this.builder.build_int_compare(
+                    IntPredicate::ULT,
+                    requested,
+                    minimum,
+                    "too_small",
+                )?;
+                let capacity = this
+                    .builder
+                    .build_select(too_small, minimum, requested, "capacity")?
+                    .into_int_value();
+
+                let header_size = header_type.size_of().unwrap();
+                let header = this
+                    .builder
+                    .build_call(malloc, &[header_size.into()], "header")?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_pointer_value();
+                let bytes = this.builder.build_int_mul(
+                    capacity,
+                    i64_type.const_int(SLOT_SIZE, false),
+                    "bytes",
+                )?;
+                let data = this
+                    .builder
+                    .build_call(malloc, &[bytes.into()], "data")?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap();
+
+                let length_ptr =
+                    this.builder
+                        .build_struct_gep(header_type, header, LENGTH_FIELD, "length")?;
+                this.builder
+                    .build_store(length_ptr, i64_type.const_zero())?;
+                let capacity_ptr = this.builder.build_struct_gep(
+                    header_type,
+                    header,
+                    CAPACITY_FIELD,
+                    "capacity",
+                )?;
+                this.builder.build_store(capacity_ptr, capacity)?;
+                let data_ptr =
+                    this.builder
+                        .build_struct_gep(header_type, header, DATA_FIELD, "data")?;
+                this.builder.build_store(data_ptr, data)?;
+
+                this.builder.build_return(Some(&header))?;
+                Ok(())
+            },
+        )
+    }
+
+    fn list_push_slot_function(&mut self) -> Result<FunctionValue<'ctx>, Box<dyn Error>> {
+        let ptr_type = self.get_string_type();
+        self.runtime_helper(
+            "delta.list.push_slot",
+            &[ptr_type.into()],
+            Some(self.get_string_type().into()),
+            |this, function| {
+                let i64_type = this.context.i64_type();
+                let header_type = this.list_header_type();
+                let realloc = this.realloc_function();
+                let list = function.get_nth_param(0).unwrap().into_pointer_value();
+
+                let grow_block = this.context.append_basic_block(function, "grow");
+                let store_block = this.context.append_basic_block(function, "store");
+
+                let length: IntValue = this.build_list_field(list, LENGTH_FIELD, "length")?;
+                let capacity: IntValue = this.build_list_field(list, CAPACITY_FIELD, "capacity")?;
+                let full =
+                    this.builder
+                        .build_int_compare(IntPredicate::EQ, length, capacity, "full")?;
+                this.builder
+                    .build_conditional_branch(full, grow_block, store_block)?;
+
+                this.builder.position_at_end(grow_block);
+                let data: PointerValue = this.build_list_field(list, DATA_FIELD, "data")?;
+                let doubled = this.builder.build_int_mul(
+                    capacity,
+                    i64_type.const_int(2, false),
+                    "doubled",
+                )?;
+                let bytes = this.builder.build_int_mul(
+                    doubled,
+                    i64_type.const_int(SLOT_SIZE, false),
+                    "bytes",
+                )?;
+                let grown = this
+                    .builder
+                    .build_call(realloc, &[data.into(), bytes.into()], "grown")?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap();
+                let capacity_ptr = this.builder.build_struct_gep(
+                    header_type,
+                    list,
+                    CAPACITY_FIELD,
+                    "capacity.ptr",
+                )?;
+                this.builder.build_store(capacity_ptr, doubled)?;
+                let data_ptr =
+                    this.builder
+                        .build_struct_gep(header_type, list, DATA_FIELD, "data.ptr")?;
+                this.builder.build_store(data_ptr, grown)?;
+                this.builder.build_unconditional_branch(store_block)?;
+
+                this.builder.position_at_end(store_block);
+                let slot = this.build_list_slot(list, length)?;
+                let new_length = this.builder.build_int_add(
+                    length,
+                    i64_type.const_int(1, false),
+                    "new_length",
+                )?;
+                let length_ptr =
+                    this.builder
+                        .build_struct_gep(header_type, list, LENGTH_FIELD, "length.ptr")?;
+                this.builder.build_store(length_ptr, new_length)?;
+                this.builder.build_return(Some(&slot))?;
+                Ok(())
+            },
+        )
+    }
+
+    fn list_checked_slot_function(&mut self) -> Result<FunctionValue<'ctx>, Box<dyn Error>> {
+        let ptr_type = self.get_string_type();
+        let i64_type = self.context.i64_type();
+        self.runtime_helper(
+            "delta.list.checked_slot",
+            &[ptr_type.into(), i64_type.into()],
+            Some(self.get_string_type().into()),
+            |this, function| {
+                let list = function.get_nth_param(0).unwrap().into_pointer_value();
+                let index = function.get_nth_param(1).unwrap().into_int_value();
+
+                let in_range_block = this.context.append_basic_block(function, "in_range");
+                let out_of_range_block = this.context.append_basic_block(function, "out_of_range");
+
+                // A negative index wraps around to a huge unsigned one, so a
+                // single unsigned comparison covers both ends
+                let length: IntValue = this.build_list_field(list, LENGTH_FIELD, "length")?;
+                let in_range =
+                    this.builder
+                        .build_int_compare(IntPredicate::ULT, index, length, "in_range")?;
+                this.builder.build_conditional_branch(
+                    in_range,
+                    in_range_block,
+                    out_of_range_block,
+                )?;
+
+                this.builder.position_at_end(out_of_range_block);
+                this.build_runtime_error("list index out of range")?;
+
+                this.builder.position_at_end(in_range_block);
+                let slot = this.build_list_slot(list, index)?;
+                this.builder.build_return(Some(&slot))?;
+                Ok(())
+            },
+        )
+    }
+
+    fn list_remove_at_function(&mut self) -> Result<FunctionValue<'ctx>, Box<dyn Error>> {
+        let ptr_type = self.get_string_type();
+        let i64_type = self.context.i64_type();
+        self.runtime_helper(
+            "delta.list.remove_at",
+            &[ptr_type.into(), i64_type.into()],
+            None,
+            |this, function| {
+                let header_type = this.list_header_type();
+                let memmove = this.memmove_function();
+                let list = function.get_nth_param(0).unwrap().into_pointer_value();
+                let index = function.get_nth_param(1).unwrap().into_int_value();
+                let one = i64_type.const_int(1, false);
+
+                let length: IntValue = this.build_list_field(list, LENGTH_FIELD, "length")?;
+                let destination = this.build_list_slot(list, index)?;
+                let next = this.builder.build_int_add(index, one, "next")?;
+                let source = this.build_list_slot(list, next)?;
+                let remaining = this.builder.build_int_sub(length, next, "remaining")?;
+                let bytes = this.builder.build_int_mul(
+                    remaining,
+                    i64_type.const_int(SLOT_SIZE, false),
+                    "bytes",
+                )?;
+                this.builder.build_call(
+                    memmove,
+                    &[destination.into(), source.into(), bytes.into()],
+                    "",
+                )?;
+
+                let new_length = this.builder.build_int_sub(length, one, "new_length")?;
+                let length_ptr =
+                    this.builder
+                        .build_struct_gep(header_type, list, LENGTH_FIELD, "length.ptr")?;
+                this.builder.build_store(length_ptr, new_length)?;
+                this.builder.build_return(None)?;
+                Ok(())
+            },
+        )
+    }
+
+    fn object_entry_type(&self) -> StructType<'ctx> {
+        self.context.struct_type(
+            &[
+                self.get_string_type().into(),
+                self.context.i64_type().into(),
+            ],
+            false,
+        )
+    }
+
+    /// Allocates an empty object with room for `fields` fields before it
+    /// has to grow.
+    pub(super) fn build_object_new(
+        &mut self,
+        fields: usize,
+    ) -> Result<PointerValue<'ctx>, Box<dyn Error>> {
+        let function = self.object_new_function()?;
+        let capacity = (fields as u64 * 2)
+            .next_power_of_two()
+            .max(MIN_OBJECT_CAPACITY);
+        let capacity = self.context.i64_type().const_int(capacity, false);
+        self.call_pointer_helper(function, &[capacity.into()], "object")
+    }
+
+    /// Returns the address of the value stored under `field`, adding the
+    /// field first when the object does not have it yet.
+    pub(super) fn build_object_slot(
+        &mut self,
+        object: PointerValue<'ctx>,
+        field: &str,
+    ) -> Result<PointerValue<'ctx>, Box<dyn Error>> {
+        let function = self.object_slot_function()?;
+        let key = self.builder.build_global_string_ptr(field, "field")?;
+        self.call_pointer_helper(
+            function,
+            &[object.into(), key.as_pointer_value().into()],
+            &format!("{}.slot", field),
+        )
+    }
+
+    fn calloc_function(&self) -> FunctionValue<'ctx> {
+        let i64_type = self.context.i64_type();
+        let fn_type = self
+            .get_string_type()
+            .fn_type(&[i64_type.into(), i64_type.into()], false);
+        self.libc_function("calloc", fn_type)
+    }
+
+    fn free_function(&self) -> FunctionValue<'ctx> {
+        let fn_type = self
+            .context
+            .void_type()
+            .fn_type(&[self.get_string_type().into()], false);
+        self.libc_function("free", fn_type)
+    }
+
+    fn object_new_function(&mut self) -> Result<FunctionValue<'ctx>, Box<dyn Error>> {
+        let i64_type = self.context.i64_type();
+        self.runtime_helper(
+            "delta.object.new",
+            &[i64_type.into()],
+            Some(self.get_string_type().into()),
+            |this, function| {
+                let header_type = this.list_header_type();
+                let malloc = this.malloc_function();
+                let calloc = this.calloc_function();
+                let capacity = function.get_nth_param(0).unwrap().into_int_value();
+
+                let header_size = header_type.size_of().unwrap();
+                let header = this
+                    .builder
+                    .build_call(malloc, &[header_size.into()], "header")?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_pointer_value();
+                let entry_size = this.object_entry_type().size_of().unwrap();
+                let entries = this
+                    .builder
+                    .build_call(calloc, &[capacity.into(), entry_size.into()], "entries")?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap();
+
+                this.store_header(header, i64_type.const_zero(), capacity, entries)?;
+                this.builder.build_return(Some(&header))?;
+                Ok(())
+            },
+        )
+    }
+
+    fn store_header(
+        &self,
+        header: PointerValue<'ctx>,
+        length: IntValue<'ctx>,
+        capacity: IntValue<'ctx>,
+        data: inkwell::values::BasicValueEnum<'ctx>,
+    ) -> Result<(), Box<dyn Error>> {
+        let header_type = self.list_header_type();
+        let length_ptr =
+            self.builder
+                .build_struct_gep(header_type, header, LENGTH_FIELD, "length.ptr")?;
+        self.builder.build_store(length_ptr, length)?;
+        let capacity_ptr =
+            self.builder
+                .build_struct_gep(header_type, header, CAPACITY_FIELD, "capacity.ptr")?;
+        self.builder.build_store(capacity_ptr, capacity)?;
+        let data_ptr =
+            self.builder
+                .build_struct_gep(header_type, header, DATA_FIELD, "data.ptr")?;
+        self.builder.build_store(data_ptr, data)?;
+        Ok(())
+    }
+
+    /// FNV-1a over the bytes of a C string.
+    fn object_hash_function(&mut self) -> Result<FunctionValue<'ctx>, Box<dyn Error>> {
+        let i64_type = self.context.i64_type();
+        self.runtime_helper(
+            "delta.object.hash",
+            &[self.get_string_type().into()],
+            Some(i64_type.into()),
+            |this, function| {
+                let i8_type = this.context.i8_type();
+                let key = function.get_nth_param(0).unwrap().into_pointer_value();
+                let entry_block = this.builder.get_insert_block().unwrap();
+                let loop_block = this.context.append_basic_block(function, "loop");
+                let body_block = this.context.append_basic_block(function, "body");
+                let done_block = this.context.append_basic_block(function, "done");
+                this.builder.build_unconditional_branch(loop_block)?;
+
+                this.builder.position_at_end(loop_block);
+                let cursor = this.builder.build_phi(this.get_string_type(), "cursor")?;
+                let hash = this.builder.build_phi(i64_type, "hash")?;
+                cursor.add_incoming(&[(&key, entry_block)]);
+                hash.add_incoming(&[(&i64_type.const_int(FNV_OFFSET_BASIS, false), entry_block)]);
+                let cursor_value = cursor.as_basic_value().into_pointer_value();
+                let hash_value = hash.as_basic_value().into_int_value();
+                let byte = this
+                    .builder
+                    .build_load(i8_type, cursor_value, "byte")?
+                    .into_int_value();
+                let at_end = this.builder.build_int_compare(
+                    IntPredicate::EQ,
+                    byte,
+                    i8_type.const_zero(),
+                    "at_end",
+                )?;
+                this.builder
+                    .build_conditional_branch(at_end, done_block, body_block)?;
+
+                this.builder.position_at_end(body_block);
+                let widened = this.builder.build_int_z_extend(byte, i64_type, "widened")?;
+                let mixed = this.builder.build_xor(hash_value, widened, "mixed")?;
+                let next_hash = this.builder.build_int_mul(
+                    mixed,
+                    i64_type.const_int(FNV_PRIME, false),
+                    "next_hash",
+                )?;
+                // SAFETY: the loop stops at the string's terminating zero
+                let next_cursor = unsafe {
+                    this.builder.build_in_bounds_gep(
+                        i8_type,
+                        cursor_value,
+                        &[i64_type.const_int(1, false)],
+                        "next_cursor",
+                    )?
+                };
+                cursor.add_incoming(&[(&next_cursor, body_block)]);
+                hash.add_incoming(&[(&next_hash, body_block)]);
+                this.builder.build_unconditional_branch(loop_block)?;
+
+                this.builder.position_at_end(done_block);
+                this.builder.build_return(Some(&hash_value))?;
+                Ok(())
+            },
+        )
+    }
+
+    fn object_slot_function(&mut self) -> Result<FunctionValue<'ctx>, Box<dyn Error>> {
+        let ptr_type = self.get_string_type();
+        self.runtime_helper(
+            "delta.object.slot",
+            &[ptr_type.into(), ptr_type.into()],
+            Some(ptr_type.into()),
+            |this, function| {
+                let i64_type = this.context.i64_type();
+                let header_type = this.list_header_type();
+                let entry_type = this.object_entry_type();
+                let grow_fn = this.object_grow_function()?;
+                let hash_fn = this.object_hash_function()?;
+                let strcmp_fn = this.strcmp_function();
+                let object = function.get_nth_param(0).unwrap().into_pointer_value();
+                let key = function.get_nth_param(1).unwrap().into_pointer_value();
+                let one = i64_type.const_int(1, false);
+
+                let grow_block = this.context.append_basic_block(function, "grow");
+                let search_block = this.context.append_basic_block(function, "search");
+                let probe_block = this.context.append_basic_block(function, "probe");
+                let compare_block = this.context.append_basic_block(function, "compare");
+                let found_block = this.context.append_basic_block(function, "found");
+                let advance_block = this.context.append_basic_block(function, "advance");
+                let insert_block = this.context.append_basic_block(function, "insert");
+
+                // Grow first if one more field would make the table 3/4 full
+                let count: IntValue = this.build_list_field(object, LENGTH_FIELD, "count")?;
+                let capacity: IntValue =
+                    this.build_list_field(object, CAPACITY_FIELD, "capacity")?;
+                let new_count = this.builder.build_int_add(count, one, "new_count")?;
+                let load =
+                    this.builder
+                        .build_int_mul(new_count, i64_type.const_int(4, false), "load")?;
+                let limit =
+                    this.builder
+                        .build_int_mul(capacity, i64_type.const_int(3, false), "limit")?;
+                let too_full =
+                    this.builder
+                        .build_int_compare(IntPredicate::UGT, load, limit, "too_full")?;
+                this.builder
+                    .build_conditional_branch(too_full, grow_block, search_block)?;
+
+                this.builder.position_at_end(grow_block);
+                this.builder.build_call(grow_fn, &[object.into()], "")?;
+                this.builder.build_unconditional_branch(search_block)?;
+
+                this.builder.position_at_end(search_block);
+                let capacity: IntValue =
+                    this.build_list_field(object, CAPACITY_FIELD, "capacity")?;
+                let entries: PointerValue = this.build_list_field(object, DATA_FIELD, "entries")?;
+                let mask = this.builder.build_int_sub(capacity, one, "mask")?;
+                let hash = this
+                    .builder
+                    .build_call(hash_fn, &[key.into()], "hash")?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_int_value();
+                let start = this.builder.build_and(hash, mask, "start")?;
+                this.builder.build_unconditional_branch(probe_block)?;
+
+                this.builder.position_at_end(probe_block);
+                let index = this.builder.build_phi(i64_type, "index")?;
+                index.add_incoming(&[(&start, search_block)]);
+                let index_value = index.as_basic_value().into_int_value();
+                // SAFETY: the index is masked to the table's capacity
+                let entry = unsafe {
+                    this.builder.build_in_bounds_gep(
+                        entry_type,
+                        entries,
+                        &[index_value],
+                        "entry",
+                    )?
+                };
+                let key_ptr = this
+                    .builder
+                    .build_struct_gep(entry_type, entry, 0, "key.ptr")?;
+                let value_ptr = this
+                    .builder
+                    .build_struct_gep(entry_type, entry, 1, "value.ptr")?;
+                let existing = this
+                    .builder
+                    .build_load(ptr_type, key_ptr, "existing")?
+                    .into_pointer_value();
+                let is_free = this.builder.build_is_null(existing, "is_free")?;
+                this.builder
+                    .build_conditional_branch(is_free, insert_block, compare_block)?;
+
+                this.builder.position_at_end(compare_block);
+                let order = this
+                    .builder
+                    .build_call(strcmp_fn, &[existing.into(), key.into()], "order")?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_int_value();
+                let is_match = this.builder.build_int_compare(
+                    IntPredicate::EQ,
+                    order,
+                    this.context.i32_type().const_zero(),
+                    "is_match",
+                )?;
+                this.builder
+                    .build_conditional_branch(is_match, found_block, advance_block)?;
+
+                this.builder.position_at_end(found_block);
+                this.builder.build_return(Some(&value_ptr))?;
+
+                this.builder.position_at_end(advance_block);
+                let next = this.builder.build_int_add(index_value, one, "next")?;
+                let wrapped = this.builder.build_and(next, mask, "wrapped")?;
+                index.add_incoming(&[(&wrapped, advance_block)]);
+                this.builder.build_unconditional_branch(probe_block)?;
+
+                this.builder.position_at_end(insert_block);
+                this.builder.build_store(key_ptr, key)?;
+                let count: IntValue = this.build_list_field(object, LENGTH_FIELD, "count")?;
+                let new_count = this.builder.build_int_add(count, one, "new_count")?;
+                let count_ptr = this.builder.build_struct_gep(
+                    header_type,
+                    object,
+                    LENGTH_FIELD,
+                    "count.ptr",
+                )?;
+                this.builder.build_store(count_ptr, new_count)?;
+                this.builder.build_return(Some(&value_ptr))?;
+                Ok(())
+            },
+        )
+    }
+
+    /// Doubles an object's table and inserts every field again.
+    fn object_grow_function(&mut self) -> Result<FunctionValue<'ctx>, Box<dyn Error>> {
+        let ptr_type = self.get_string_type();
+        self.runtime_helper(
+            "delta.object.grow",
+            &[ptr_type.into()],
+            None,
+            |this, function| {
+                let i64_type = this.context.i64_type();
+                let entry_type = this.object_entry_type();
+                let calloc = this.calloc_function();
+                let free = this.free_function();
+                // Declared before this body is built, so the recursion is fine
+                let slot_fn = this.object_slot_function()?;
+                let object = function.get_nth_param(0).unwrap().into_pointer_value();
+                let one = i64_type.const_int(1, false);
+
+                let entry_block = this.builder.get_insert_block().unwrap();
+                let loop_block = this.context.append_basic_block(function, "loop");
+                let body_block = this.context.append_basic_block(function, "body");
+                let reinsert_block = this.context.append_basic_block(function, "reinsert");
+                let next_block = this.context.append_basic_block(function, "next");
+                let done_block = this.context.append_basic_block(function, "done");
+
+                let old_capacity: IntValue =
+                    this.build_list_field(object, CAPACITY_FIELD, "old_capacity")?;
+                let old_entries: PointerValue =
+                    this.build_list_field(object, DATA_FIELD, "old_entries")?;
+                let capacity = this.builder.build_int_mul(
+                    old_capacity,
+                    i64_type.const_int(2, false),
+                    "capacity",
+                )?;
+                let entries = this
+                    .builder
+                    .build_call(
+                        calloc,
+                        &[capacity.into(), entry_type.size_of().unwrap().into()],
+                        "entries",
+                    )?
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap();
+                this.store_header(object, i64_type.const_zero(), capacity, entries)?;
+                this.builder.build_unconditional_branch(loop_block)?;
+
+                this.builder.position_at_end(loop_block);
+                let index = this.builder.build_phi(i64_type, "index")?;
+                index.add_incoming(&[(&i64_type.const_zero(), entry_block)]);
+                let index_value = index.as_basic_value().into_int_value();
+                let keep_going = this.builder.build_int_compare(
+                    IntPredicate::ULT,
+                    index_value,
+                    old_capacity,
+                    "keep_going",
+                )?;
+                this.builder
+                    .build_conditional_branch(keep_going, body_block, done_block)?;
+
+                this.builder.position_at_end(body_block);
+                // SAFETY: the index stays below the old capacity
+                let entry = unsafe {
+                    this.builder.build_in_bounds_gep(
+                        entry_type,
+                        old_entries,
+                        &[index_value],
+                        "entry",
+                    )?
+                };
+                let key_ptr = this
+                    .builder
+                    .build_struct_gep(entry_type, entry, 0, "key.ptr")?;
+                let key = this
+                    .builder
+                    .build_load(ptr_type, key_ptr, "key")?
+                    .into_pointer_value();
+                let is_free = this.builder.build_is_null(key, "is_free")?;
+                this.builder
+                    .build_conditional_branch(is_free, next_block, reinsert_block)?;
+
+                this.builder.position_at_end(reinsert_block);
+                let value_ptr = this
+                    .builder
+                    .build_struct_gep(entry_type, entry, 1, "value.ptr")?;
+                let value = this.builder.build_load(i64_type, value_ptr, "value")?;
+                let slot =
+                    this.call_pointer_helper(slot_fn, &[object.into(), key.into()], "slot")?;
+                this.builder.build_store(slot, value)?;
+                this.builder.build_unconditional_branch(next_block)?;
+
+                this.builder.position_at_end(next_block);
+                let next = this.builder.build_int_add(index_value, one, "next")?;
+                index.add_incoming(&[(&next, next_block)]);
+                this.builder.build_unconditional_branch(loop_block)?;
+
+                this.builder.position_at_end(done_block);
+                this.builder.build_call(free, &[old_entries.into()], "")?;
+                this.builder.build_return(None)?;
+                Ok(())
+            },
+        )
+    }
+
+    pub(super) fn strcmp_function(&self) -> FunctionValue<'ctx> {
+        let fn_type = self.context.i32_type().fn_type(
+            &[self.get_string_type().into(), self.get_string_type().into()],
+            false,
+        );
+        self.libc_function("strcmp", fn_type)
+    }
+}
@@ -0,0 +1,207 @@
+//! Parameter types for functions the program never calls.
+//!
+//! A function is compiled once per combination of argument types, so a
+//! function without calls has no types to compile with. It is still compiled,
+//! so that its errors are reported and it ends up in the binary, with each
+//! parameter typed after how the body uses it: a parameter that is indexed,
+//! looped over or added to is a list, one with fields read or set is an
+//! object with those fields, one compared with a string or boolean literal
+//! has that type, and anything else is a number.
+
+use super::VariableType;
+use crate::ast::*;
+use std::collections::BTreeMap;
+
+/// The types `func_def` is compiled with when nothing calls it.
+pub(super) fn guess_parameter_types(func_def: &FunctionDef) -> Vec<VariableType> {
+    func_def
+        .parameters
+        .iter()
+        .map(|parameter| {
+            let mut guess = Guess {
+                parameter,
+                found: None,
+            };
+            guess.statements(&func_def.body);
+            guess.found.unwrap_or(VariableType::Float)
+        })
+        .collect()
+}
+
+/// Walks a body, gathering what its uses say about one parameter.
+struct Guess<'a> {
+    parameter: &'a str,
+    found: Option<VariableType>,
+}
+
+impl Guess<'_> {
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Let(let_stmt) => self.expression(&let_stmt.value),
+            StatementKind::Show(show_stmt) => self.expression(&show_stmt.value),
+            StatementKind::When(when_stmt) => {
+                self.expression(&when_stmt.condition);
+                self.statements(&when_stmt.then_block);
+                if let Some(otherwise) = &when_stmt.otherwise_block {
+                    self.statements(otherwise);
+                }
+            }
+            StatementKind::Return(return_stmt) => {
+                for value in &return_stmt.values {
+                    self.expression(value);
+                }
+            }
+            StatementKind::RepeatWhile(repeat) => {
+                self.expression(&repeat.condition);
+                self.statements(&repeat.body);
+            }
+            StatementKind::RepeatRange(repeat) => {
+                self.expression(&repeat.start);
+                self.expression(&repeat.end);
+                if let Some(step) = &repeat.step {
+                    self.expression(step);
+                }
+                self.statements(&repeat.body);
+            }
+            StatementKind::RepeatEach(repeat) => {
+                self.demand(&repeat.list, VariableType::list_of(VariableType::Float));
+                self.expression(&repeat.list);
+                self.statements(&repeat.body);
+            }
+            StatementKind::Add(AddStatement { item, list })
+            | StatementKind::Remove(RemoveStatement { item, list }) => {
+                let item_type = literal_type(item).unwrap_or(VariableType::Float);
+                self.demand(list, VariableType::list_of(item_type));
+                self.expression(item);
+                self.expression(list);
+            }
+            StatementKind::SetField(set_stmt) => {
+                let value_type = literal_type(&set_stmt.value).unwrap_or(VariableType::Float);
+                let fields = BTreeMap::from([(set_stmt.field.clone(), value_type)]);
+                self.demand(&set_stmt.object, VariableType::Object(fields));
+                self.expression(&set_stmt.object);
+                self.expression(&set_stmt.value);
+            }
+            StatementKind::Expression(expr) => self.expression(expr),
+            // A nested definition has parameters of its own
+            StatementKind::FunctionDef(_)
+            | StatementKind::Break
+            | StatementKind::Continue
+            | StatementKind::Error => {}
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match &expr.kind {
+            ExpressionKind::BinaryOp(operation) => {
+                if matches!(
+                    operation.operator,
+                    BinaryOperator::Equal | BinaryOperator::NotEqual
+                ) {
+                    if let Some(other) = literal_type(&operation.right) {
+                        self.demand(&operation.left, other);
+                    }
+                    if let Some(other) = literal_type(&operation.left) {
+                        self.demand(&operation.right, other);
+                    }
+                }
+                self.expression(&operation.left);
+                self.expression(&operation.right);
+            }
+            ExpressionKind::FunctionCall(call) => {
+                for argument in &call.arguments {
+                    self.expression(argument);
+                }
+            }
+            ExpressionKind::List(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            }
+            ExpressionKind::Index(index) => {
+                self.demand(&index.list, VariableType::list_of(VariableType::Float));
+                self.expression(&index.list);
+                self.expression(&index.index);
+            }
+            ExpressionKind::ListQuery(query) => {
+                self.demand(&query.list, VariableType::list_of(VariableType::Float));
+                self.expression(&query.list);
+            }
+            ExpressionKind::Object(fields) => {
+                for field in fields {
+                    self.expression(&field.value);
+                }
+            }
+            ExpressionKind::Member(member) => {
+                let fields = BTreeMap::from([(member.field.clone(), VariableType::Float)]);
+                self.demand(&member.object, VariableType::Object(fields));
+                self.expression(&member.object);
+            }
+            ExpressionKind::Not(operand) => self.expression(operand),
+            ExpressionKind::Number(_)
+            | ExpressionKind::String(_)
+            | ExpressionKind::Boolean(_)
+            | ExpressionKind::Nothing
+            | ExpressionKind::Identifier(_) => {}
+        }
+    }
+
+    /// Notes that `expr` is used as a `wanted`. Through indexing and field
+    /// access, that says something about the parameter they start from.
+    fn demand(&mut self, expr: &Expression, wanted: VariableType) {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) if name == self.parameter => {
+                self.found = Some(match self.found.take() {
+                    Some(found) => combine(found, wanted),
+                    None => wanted,
+                });
+            }
+            ExpressionKind::Index(index) => self.demand(&index.list, VariableType::list_of(wanted)),
+            ExpressionKind::ListQuery(query) if query.query != ListQueryKind::Length => {
+                self.demand(&query.list, VariableType::list_of(wanted))
+            }
+            ExpressionKind::Member(member) => {
+                let fields = BTreeMap::from([(member.field.clone(), wanted)]);
+                self.demand(&member.object, VariableType::Object(fields));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// What two uses say together. An object has the fields of both, and the
+/// first use wins where they disagree.
+fn combine(found: VariableType, wanted: VariableType) -> VariableType {
+    match (found, wanted) {
+        (VariableType::List(Some(found)), VariableType::List(Some(wanted))) => {
+            VariableType::list_of(combine(*found, *wanted))
+        }
+        (VariableType::Object(mut found), VariableType::Object(wanted)) => {
+            for (name, wanted) in wanted {
+                let field = match found.remove(&name) {
+                    Some(existing) => combine(existing, wanted),
+                    None => wanted,
+                };
+                found.insert(name, field);
+            }
+            VariableType::Object(found)
+        }
+        (found, _) => found,
+    }
+}
+
+/// The type of a literal, which is all a guess can go on without compiling.
+fn literal_type(expr: &Expression) -> Option<VariableType> {
+    match expr.kind {
+        ExpressionKind::Number(_) => Some(VariableType::Float),
+        ExpressionKind::String(_) => Some(VariableType::String),
+        ExpressionKind::Boolean(_) => Some(VariableType::Boolean),
+        _ => None,
+    }
+}
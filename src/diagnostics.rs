@@ -0,0 +1,465 @@
+//! Errors and warnings about a source file, shared by every stage of the
+//! compiler, and their rendering as annotated source snippets:
+//!
+//! ```text
+//! error[E0009]: Undefined variable: total
+//!  --> example.de:3:10
+//!   |
+//! 3 |     show total + 1
+//!   |          ^^^^^ not defined at this point
+//! ```
+
+use crate::span::Span;
+use serde_json::json;
+use std::error::Error;
+use std::fmt;
+
+pub mod codes;
+
+pub use codes::Code;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A span of source with a short explanation printed under it. The
+/// primary label marks the problem itself; secondary labels mark other
+/// places that explain it, such as an earlier definition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub message: String,
+    /// At most one primary label, which comes first. It is only missing when
+    /// no part of the source is to blame, such as when LLVM rejects the
+    /// generated module.
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn error(code: Code, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: Code, message: impl Into<String>) -> Self {
+        Diagnostic::new(Severity::Warning, code, message)
+    }
+
+    fn new(severity: Severity, code: Code, message: impl Into<String>) -> Self {
+        Diagnostic {
+            severity,
+            code,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.retain(|label| !label.primary);
+        self.labels.insert(
+            0,
+            Label {
+                span,
+                message: message.into(),
+                primary: true,
+            },
+        );
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Points the diagnostic at `span` unless it already points somewhere.
+    /// Errors are located on their way out of the tree, so the innermost
+    /// node that knows about the problem wins.
+    pub fn locate(self, span: Span) -> Self {
+        if self.labels.iter().any(|label| label.primary) {
+            return self;
+        }
+        self.with_primary(span, "")
+    }
+
+    /// Renders the diagnostic with the lines of `source` it points at.
+    /// `color` adds ANSI escapes for terminals.
+    pub fn render(&self, filename: &str, source: &str, color: bool) -> String {
+        let paint = Painter { color };
+        let accent = match self.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+        };
+
+        let mut out = String::new();
+        out += &paint.apply(&format!("{}[{}]", self.severity, self.code.id()), accent);
+        out += &paint.apply(&format!(": {}", self.message), BOLD);
+        out.push('\n');
+
+        // Labels in source order, each line of source shown once
+        let mut lines: Vec<SnippetLine> = self
+            .labels
+            .iter()
+            .map(|label| SnippetLine::new(source, label))
+            .collect();
+        lines.sort_by_key(|line| (line.number, line.underline_start));
+        let gutter = lines
+            .iter()
+            .map(|line| line.number.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let pad = " ".repeat(gutter);
+
+        let location = lines.iter().find(|line| line.primary).or(lines.first());
+        if let Some(location) = location {
+            out += &format!(
+                "{}{} {}:{}:{}\n",
+                pad,
+                paint.apply("-->", BLUE),
+                filename,
+                location.number,
+                location.column
+            );
+            out += &format!("{} {}\n", pad, paint.apply("|", BLUE));
+        }
+        for (index, line) in lines.iter().enumerate() {
+            if index == 0 || lines[index - 1].number != line.number {
+                out += &format!(
+                    "{} {} {}\n",
+                    paint.apply(&format!("{:>gutter$}", line.number), BLUE),
+                    paint.apply("|", BLUE),
+                    line.text
+                );
+            }
+            let marker = if line.primary { "^" } else { "-" };
+            let style = if line.primary { accent } else { BLUE };
+            let underline = format!(
+                "{}{} {}",
+                " ".repeat(line.underline_start),
+                marker.repeat(line.underline_len),
+                line.message
+            );
+            out += &format!(
+                "{} {} {}\n",
+                pad,
+                paint.apply("|", BLUE),
+                paint.apply(underline.trim_end(), style)
+            );
+        }
+        if !lines.is_empty() && (!self.notes.is_empty() || self.help.is_some()) {
+            out += &format!("{} {}\n", pad, paint.apply("|", BLUE));
+        }
+
+        for note in &self.notes {
+            out += &format!(
+                "{} {} {}\n",
+                pad,
+                paint.apply("=", BLUE),
+                note_line("note", note, &paint)
+            );
+        }
+        if let Some(help) = &self.help {
+            out += &format!(
+                "{} {} {}\n",
+                pad,
+                paint.apply("=", BLUE),
+                note_line("help", help, &paint)
+            );
+        }
+        out
+    }
+
+    /// The diagnostic as one JSON object, for editors and CI:
+    ///
+    /// ```text
+    /// {"file": "example.de", "severity": "error", "code": "E0009",
+    ///  "message": "Undefined variable: total",
+    ///  "span": {"start": 29, "end": 34, "line": 3, "column": 10,
+    ///           "end_line": 3, "end_column": 15},
+    ///  "labels": [{"span": {...}, "message": "not defined at this point",
+    ///              "primary": true}],
+    ///  "notes": [], "help": null}
+    /// ```
+    ///
+    /// `span` is the primary label's span, or null when nothing in the source
+    /// is to blame. `help` is the suggested fix, if there is one.
+    pub fn to_json(&self, filename: &str, source: &str) -> serde_json::Value {
+        let span_json = |span: Span| {
+            let (line, column) = span.line_col(source);
+            let (end_line, end_column) = Span::new(span.end, span.end).line_col(source);
+            json!({
+                "start": span.start,
+                "end": span.end,
+                "line": line,
+                "column": column,
+                "end_line": end_line,
+                "end_column": end_column,
+            })
+        };
+        let primary = self.labels.iter().find(|label| label.primary);
+        let labels: Vec<serde_json::Value> = self
+            .labels
+            .iter()
+            .map(|label| {
+                json!({
+                    "span": span_json(label.span),
+                    "message": label.message,
+                    "primary": label.primary,
+                })
+            })
+            .collect();
+
+        json!({
+            "file": filename,
+            "severity": self.severity.to_string(),
+            "code": self.code.id(),
+            "message": self.message,
+            "span": primary.map(|label| span_json(label.span)),
+            "labels": labels,
+            "notes": self.notes,
+            "help": self.help,
+        })
+    }
+}
+
+fn note_line(kind: &str, text: &str, paint: &Painter) -> String {
+    format!("{}: {}", paint.apply(kind, BOLD), text)
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for Diagnostic {}
+
+/// The source line a label sits on and where to draw its underline. Spans
+/// running over several lines are underlined to the end of their first line.
+struct SnippetLine<'a> {
+    number: usize,
+    column: usize,
+    // Tabs are expanded so the underline lines up with the text
+    text: String,
+    underline_start: usize,
+    underline_len: usize,
+    message: &'a str,
+    primary: bool,
+}
+
+impl<'a> SnippetLine<'a> {
+    fn new(source: &'a str, label: &'a Label) -> Self {
+        let (number, column) = label.span.line_col(source);
+        let start = label.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |newline| newline + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |newline| start + newline);
+        let end = label.span.end.clamp(start, line_end);
+
+        SnippetLine {
+            number,
+            column,
+            text: expand_tabs(source[line_start..line_end].trim_end_matches('\r')),
+            underline_start: display_width(&source[line_start..start]),
+            // Empty spans, such as the end of the file, still get one marker
+            underline_len: display_width(&source[start..end]).max(1),
+            message: &label.message,
+            primary: label.primary,
+        }
+    }
+}
+
+const TAB_WIDTH: usize = 4;
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|ch| if ch == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+struct Painter {
+    color: bool,
+}
+
+impl Painter {
+    fn apply(&self, text: &str, style: &str) -> String {
+        if self.color && !text.is_empty() {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(diagnostic: &Diagnostic, source: &str) -> String {
+        diagnostic.render("test.de", source, false)
+    }
+
+    /// The span of the first `text` in `source`.
+    fn span_of(source: &str, text: &str) -> Span {
+        let start = source.find(text).unwrap();
+        Span::new(start, start + text.len())
+    }
+
+    #[test]
+    fn the_gutter_fits_the_widest_line_number() {
+        let source = "let x be 1\n".repeat(9) + "show y\n";
+        let error = Diagnostic::error(codes::UNDEFINED_VARIABLE, "Undefined variable: y")
+            .with_primary(span_of(&source, "y"), "not defined")
+            .with_secondary(span_of(&source, "x"), "did you mean this?")
+            .with_help("define it first");
+        assert_eq!(
+            render(&error, &source),
+            "\
+error[E0009]: Undefined variable: y
+  --> test.de:10:6
+   |
+ 1 | let x be 1
+   |     - did you mean this?
+10 | show y
+   |      ^ not defined
+   |
+   = help: define it first
+"
+        );
+    }
+
+    #[test]
+    fn tabs_are_expanded_under_the_text() {
+        let source = "when true then\n\tshow\tmissing\n";
+        let error = Diagnostic::error(codes::UNDEFINED_VARIABLE, "Undefined variable: missing")
+            .with_primary(span_of(source, "missing"), "");
+        assert_eq!(
+            render(&error, source),
+            "\
+error[E0009]: Undefined variable: missing
+ --> test.de:2:7
+  |
+2 |     show    missing
+  |             ^^^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn labels_on_one_line_share_it() {
+        let source = "let pair be {a: 1, a: 2}\n";
+        let (first, second) = (Span::new(13, 14), Span::new(19, 20));
+        let error = Diagnostic::error(codes::DUPLICATE_FIELD, "Field 'a' is given twice")
+            .with_primary(second, "given again here")
+            .with_secondary(first, "first given here");
+        assert_eq!(
+            render(&error, source),
+            "\
+error[E0007]: Field 'a' is given twice
+ --> test.de:1:20
+  |
+1 | let pair be {a: 1, a: 2}
+  |              - first given here
+  |                    ^ given again here
+"
+        );
+    }
+
+    #[test]
+    fn a_span_over_several_lines_is_underlined_to_the_end_of_its_first() {
+        let source = "show 1 +\n  2\n";
+        let error = Diagnostic::warning(codes::UNREACHABLE_CODE, "Unreachable code")
+            .with_primary(Span::new(5, source.len() - 1), "this can never run")
+            .with_note("it comes after a return");
+        assert_eq!(
+            render(&error, source),
+            "\
+warning[W0001]: Unreachable code
+ --> test.de:1:6
+  |
+1 | show 1 +
+  |      ^^^ this can never run
+  |
+  = note: it comes after a return
+"
+        );
+    }
+
+    #[test]
+    fn the_end_of_the_file_still_gets_a_marker() {
+        let source = "show (1 +";
+        let end = Span::new(source.len(), source.len());
+        let error = Diagnostic::error(codes::UNEXPECTED_TOKEN, "Expected an expression")
+            .with_primary(end, "expected an expression");
+        assert_eq!(
+            render(&error, source),
+            "\
+error[E0004]: Expected an expression
+ --> test.de:1:10
+  |
+1 | show (1 +
+  |          ^ expected an expression
+"
+        );
+    }
+
+    #[test]
+    fn a_diagnostic_without_labels_is_just_its_message() {
+        let error = Diagnostic::error(codes::INTERNAL, "Module verification failed")
+            .with_help("this is a bug in the compiler");
+        assert_eq!(
+            render(&error, "show 1\n"),
+            "\
+error[E0999]: Module verification failed
+ = help: this is a bug in the compiler
+"
+        );
+    }
+}
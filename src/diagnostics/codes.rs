@@ -0,0 +1,91 @@
+//! Every diagnostic code the compiler can report. Codes are stable: once
+//! released, a code keeps its meaning and is never reused, so it is safe to
+//! search for one or to match on it in tools.
+
+/// A stable identifier for one kind of problem, like `E0009`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Code(&'static str);
+
+impl Code {
+    pub fn id(&self) -> &'static str {
+        self.0
+    }
+}
+
+// Reading the source
+
+/// A character that cannot start any token, like `@`.
+pub const UNEXPECTED_CHARACTER: Code = Code("E0001");
+/// A string with no closing quote.
+pub const UNTERMINATED_STRING: Code = Code("E0002");
+/// A backslash followed by something other than `n`, `t`, `r`, `\` or `"`.
+pub const INVALID_ESCAPE: Code = Code("E0003");
+/// A `/*` comment with no closing `*/`.
+pub const UNTERMINATED_COMMENT: Code = Code("E0024");
+
+// Parsing
+
+/// A token that does not fit the grammar at this point.
+pub const UNEXPECTED_TOKEN: Code = Code("E0004");
+/// `return` outside of a `define` block.
+pub const RETURN_OUTSIDE_FUNCTION: Code = Code("E0005");
+/// `break` or `continue` outside of a loop.
+pub const CONTROL_OUTSIDE_LOOP: Code = Code("E0006");
+/// An object literal that names a field twice.
+pub const DUPLICATE_FIELD: Code = Code("E0007");
+
+// Names and calls
+
+/// Two top-level functions with the same name.
+pub const DUPLICATE_FUNCTION: Code = Code("E0008");
+/// A variable that is used before it is given a value.
+pub const UNDEFINED_VARIABLE: Code = Code("E0009");
+/// A call to a function that is never defined.
+pub const UNDEFINED_FUNCTION: Code = Code("E0010");
+/// A call with more or fewer arguments than the function has parameters.
+pub const ARGUMENT_COUNT: Code = Code("E0011");
+/// A number of values that does not match where they are used, such as
+/// `let a, b be f` when `f` returns one value.
+pub const VALUE_COUNT: Code = Code("E0012");
+
+// Types
+
+/// A value of the wrong type, such as adding a string to a number.
+pub const TYPE_MISMATCH: Code = Code("E0013");
+/// Reading a field that the object does not have.
+pub const UNKNOWN_FIELD: Code = Code("E0014");
+/// Adding a field to an object other than through the variable it was
+/// made in, or after it has been given another name.
+pub const FIELD_NOT_ADDABLE: Code = Code("E0015");
+/// Reading an item of a list whose item type is not known yet, or adding
+/// its first item through a name other than the variable it was made in.
+pub const UNKNOWN_ITEM_TYPE: Code = Code("E0016");
+/// A function that returns different types or counts of values in
+/// different places.
+pub const RETURN_MISMATCH: Code = Code("E0017");
+/// A function whose return type depends on calling itself.
+pub const RECURSIVE_RETURN_TYPE: Code = Code("E0018");
+
+// Running the program
+
+/// Dividing by zero.
+pub const DIVISION_BY_ZERO: Code = Code("E0019");
+/// A list index past either end of the list.
+pub const INDEX_OUT_OF_RANGE: Code = Code("E0020");
+/// `first of` or `last of` an empty list.
+pub const EMPTY_LIST: Code = Code("E0021");
+/// A range loop with a step of zero.
+pub const ZERO_STEP: Code = Code("E0022");
+/// A range loop with more steps than fit in a 64-bit count, or with an end
+/// that is not a finite number.
+pub const RANGE_TOO_LONG: Code = Code("E0025");
+/// Calls nested too deeply, usually recursion without a base case.
+pub const CALL_DEPTH: Code = Code("E0023");
+
+/// A bug in the compiler itself, such as LLVM rejecting generated code.
+pub const INTERNAL: Code = Code("E0999");
+
+// Warnings
+
+/// Statements after a `return`, `break` or `continue` that can never run.
+pub const UNREACHABLE_CODE: Code = Code("W0001");
@@ -0,0 +1,593 @@
+//! `delta fmt`: prints a parsed program back out in one canonical layout.
+//!
+//! - Blocks are indented by four spaces, whatever the source used.
+//! - Binary operators get one space on each side, and comparisons are
+//!   spelled out in full (`is equal`, not `is`).
+//! - Parentheses are written only where the grammar needs them.
+//! - A run of blank lines between statements becomes one. Blocks never start
+//!   with a blank line, and top-level definitions get one on each side.
+//! - Every `define` is closed with `end`, as in the examples, so where a
+//!   function stops is visible without counting indentation.
+//!
+//! Number literals keep their digits as written, and list and object
+//! literals that were spread over several lines get one entry per line.
+//!
+//! Comments are kept. One that followed code stays at the end of that
+//! code's line, and one on a line of its own is reindented with the code
+//! around it. A comment inside an expression moves to the end of the
+//! statement, as expressions are printed afresh.
+
+use crate::ast::*;
+use delta::lexer::Comment;
+
+const INDENT: &str = "    ";
+
+// How tightly each kind of expression binds, loosest first, following the
+// parser's functions from `parse_or` down to `parse_primary`
+const OR: u8 = 1;
+const AND: u8 = 2;
+const NOT: u8 = 3;
+const COMPARISON: u8 = 4;
+const SUM: u8 = 5;
+const PRODUCT: u8 = 6;
+// `length of` reads a whole postfix expression, so `length of x[0]` is the
+// length of `x[0]`
+const QUERY: u8 = 7;
+const PRIMARY: u8 = 8;
+
+/// Formats `program`, which was parsed from `source` and has `comments`
+/// in it. Statements that failed to parse are kept as written.
+pub fn format_program(program: &Program, comments: &[Comment], source: &str) -> String {
+    let mut formatter = Formatter {
+        source,
+        out: String::new(),
+        indent: 0,
+        comments,
+        next_comment: 0,
+        written: 0,
+        block_start: true,
+    };
+    formatter.statements(&program.statements, true);
+    formatter.comments_before(usize::MAX, 0, false);
+    formatter.out
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    out: String,
+    indent: usize,
+    comments: &'a [Comment],
+    // The first comment not written yet
+    next_comment: usize,
+    // Where in the source the last statement or comment written ends
+    written: usize,
+    // Whether nothing has been written in the current block yet
+    block_start: bool,
+}
+
+fn precedence(expr: &Expression) -> u8 {
+    match &expr.kind {
+        ExpressionKind::BinaryOp(operation) => match operation.operator {
+            BinaryOperator::Or => OR,
+            BinaryOperator::And => AND,
+            BinaryOperator::Add | BinaryOperator::Subtract => SUM,
+            BinaryOperator::Multiply | BinaryOperator::Divide => PRODUCT,
+            _ => COMPARISON,
+        },
+        ExpressionKind::Not(_) => NOT,
+        ExpressionKind::ListQuery(_) => QUERY,
+        _ => PRIMARY,
+    }
+}
+
+/// A string literal that reads back as `value`.
+fn quote(value: &str) -> String {
+    let mut quoted = String::from('"');
+    for ch in value.chars() {
+        match ch {
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            '\r' => quoted.push_str("\\r"),
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            _ => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl<'a> Formatter<'a> {
+    fn line(&mut self, text: &str) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+        self.out.push_str(text);
+        self.out.push('\n');
+        self.block_start = false;
+    }
+
+    /// The source `span` covers, or nothing if it is out of range.
+    fn text(&self, span: Span) -> &'a str {
+        self.source.get(span.start..span.end).unwrap_or_default()
+    }
+
+    /// The column `offset` is at in the source.
+    fn column(&self, offset: usize) -> usize {
+        let before = self.source.get(..offset).unwrap_or_default();
+        before.len() - before.rfind('\n').map_or(0, |newline| newline + 1)
+    }
+
+    /// Where the code after `offset` starts, past whitespace and comments.
+    fn next_code(&self, mut offset: usize) -> usize {
+        let from = offset;
+        let mut comments = self
+            .comments
+            .iter()
+            .filter(|comment| comment.span.start >= from)
+            .peekable();
+        loop {
+            let rest = self.source.get(offset..).unwrap_or_default();
+            offset += rest.len() - rest.trim_start().len();
+            match comments.next_if(|comment| comment.span.start == offset) {
+                Some(comment) => offset = comment.span.end,
+                None => return offset,
+            }
+        }
+    }
+
+    /// Writes a blank line before what starts at `start`, if the source
+    /// has one right above it or `forced` says so, but never first thing
+    /// in a block.
+    fn separate(&mut self, start: usize, forced: bool) {
+        let gap = self.text(Span::new(self.written, start));
+        let blank = gap[gap.trim_end().len()..].matches('\n').count() > 1;
+        if !self.block_start && (forced || blank) {
+            self.out.push('\n');
+        }
+    }
+
+    /// Writes the comments that start before `limit`. One that followed
+    /// code goes at the end of the last line written. The others get lines
+    /// of their own, for as long as they are indented by at least `column`.
+    /// `forced` asks for a blank line before the first of those. Returns
+    /// whether any was written.
+    fn comments_before(&mut self, limit: usize, column: usize, forced: bool) -> bool {
+        let mut wrote = false;
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.start >= limit {
+                break;
+            }
+            let line_start = comment.span.start - self.column(comment.span.start);
+            let after_code = !self
+                .text(Span::new(line_start, comment.span.start))
+                .trim()
+                .is_empty();
+            if after_code && self.out.ends_with('\n') {
+                self.out.pop();
+                self.out.push_str("  ");
+                self.out.push_str(&comment.text);
+                self.out.push('\n');
+            } else {
+                if self.column(comment.span.start) < column {
+                    break;
+                }
+                self.separate(comment.span.start, forced && !wrote);
+                self.line(&comment.text);
+                wrote = true;
+            }
+            self.written = comment.span.end;
+            self.next_comment += 1;
+        }
+        wrote
+    }
+
+    fn statements(&mut self, statements: &[Statement], top_level: bool) {
+        for (i, statement) in statements.iter().enumerate() {
+            // Definitions are set apart, along with the comments above them
+            let is_definition =
+                |statement: &Statement| matches!(statement.kind, StatementKind::FunctionDef(_));
+            let set_apart = top_level
+                && i > 0
+                && (is_definition(&statements[i - 1]) || is_definition(statement));
+            let commented = self.comments_before(statement.span.start, 0, set_apart);
+            self.separate(statement.span.start, set_apart && !commented);
+            self.statement(statement);
+            self.written = self.written.max(statement.span.end);
+        }
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        self.indent += 1;
+        self.block_start = true;
+        self.statements(statements, false);
+        // Comments after the last statement belong to the block if they
+        // are indented as far as its statements
+        if let (Some(first), Some(last)) = (statements.first(), statements.last()) {
+            let limit = self.next_code(last.span.end);
+            self.comments_before(limit, self.column(first.span.start), false);
+        }
+        self.indent -= 1;
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match &statement.kind {
+            StatementKind::Let(let_statement) => {
+                let value = self.expression(&let_statement.value, 0, false);
+                self.line(&format!(
+                    "let {} be {}",
+                    let_statement.identifiers.join(", "),
+                    value
+                ));
+            }
+            StatementKind::SetField(set_field) => {
+                let object = self.expression(&set_field.object, PRIMARY, true);
+                let value = self.expression(&set_field.value, 0, false);
+                self.line(&format!("let {}.{} be {}", object, set_field.field, value));
+            }
+            StatementKind::Show(show) => {
+                let value = self.expression(&show.value, 0, false);
+                self.line(&format!("show {}", value));
+            }
+            StatementKind::When(when) => {
+                let condition = self.expression(&when.condition, 0, false);
+                self.line(&format!("when {} then", condition));
+                self.block(&when.then_block);
+                if let Some(otherwise_block) = &when.otherwise_block {
+                    // Comments left before `otherwise` go above it
+                    if let Some(last) = when.then_block.last() {
+                        let otherwise = self.next_code(last.span.end);
+                        self.comments_before(otherwise, 0, false);
+                    }
+                    self.line("otherwise");
+                    self.block(otherwise_block);
+                }
+            }
+            StatementKind::FunctionDef(function) => {
+                let mut header = format!("define {}", function.name);
+                if !function.parameters.is_empty() {
+                    header += &format!(" with {}", function.parameters.join(", "));
+                }
+                self.line(&header);
+                self.block(&function.body);
+                self.line("end");
+            }
+            StatementKind::Return(return_statement) => {
+                let values = self.list(&return_statement.values);
+                if values.is_empty() {
+                    self.line("return");
+                } else {
+                    self.line(&format!("return {}", values.join(", ")));
+                }
+            }
+            StatementKind::RepeatWhile(repeat) => {
+                let condition = self.expression(&repeat.condition, 0, false);
+                self.line(&format!("repeat while {}", condition));
+                self.block(&repeat.body);
+            }
+            StatementKind::RepeatRange(repeat) => {
+                let mut header = format!(
+                    "repeat for each {} from {} to {}",
+                    repeat.variable,
+                    self.expression(&repeat.start, 0, false),
+                    self.expression(&repeat.end, 0, false)
+                );
+                if let Some(step) = &repeat.step {
+                    header += &format!(" by {}", self.expression(step, 0, false));
+                }
+                self.line(&header);
+                self.block(&repeat.body);
+            }
+            StatementKind::RepeatEach(repeat) => {
+                let list = self.expression(&repeat.list, 0, false);
+                self.line(&format!("repeat for each {} in {}", repeat.variable, list));
+                self.block(&repeat.body);
+            }
+            StatementKind::Add(add) => {
+                let item = self.expression(&add.item, 0, false);
+                let list = self.expression(&add.list, 0, false);
+                self.line(&format!("add {} to {}", item, list));
+            }
+            StatementKind::Remove(remove) => {
+                let item = self.expression(&remove.item, 0, false);
+                let list = self.expression(&remove.list, 0, false);
+                self.line(&format!("remove {} from {}", item, list));
+            }
+            StatementKind::Break => self.line("break"),
+            StatementKind::Continue => self.line("continue"),
+            StatementKind::Expression(expr) => {
+                let expr = self.expression(expr, 0, false);
+                self.line(&expr);
+            }
+            StatementKind::Error => {
+                // Copied with any comments inside it
+                while self
+                    .comments
+                    .get(self.next_comment)
+                    .is_some_and(|comment| comment.span.start < statement.span.end)
+                {
+                    self.next_comment += 1;
+                }
+                let text = self.text(statement.span).trim();
+                self.line(text);
+            }
+        }
+    }
+
+    /// Formats comma-separated expressions. A call that is not last gets
+    /// parentheses, as it would otherwise take the rest as arguments.
+    fn list(&mut self, items: &[Expression]) -> Vec<String> {
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| self.expression(item, 0, i + 1 < items.len()))
+            .collect()
+    }
+
+    /// Formats `expr`, in parentheses if it binds less tightly than `min`.
+    /// `followed` says whether an arithmetic operator, a comma, `[` or `.`
+    /// comes next: a call's arguments would take those in, so such a call
+    /// needs parentheses too.
+    fn expression(&mut self, expr: &Expression, min: u8, followed: bool) -> String {
+        let open_call = matches!(
+            &expr.kind,
+            ExpressionKind::FunctionCall(call) if !call.arguments.is_empty()
+        );
+        if precedence(expr) < min || (open_call && followed) {
+            return format!("({})", self.expression(expr, 0, false));
+        }
+
+        match &expr.kind {
+            ExpressionKind::Number(value) => {
+                // As written, so `2.50` stays `2.50`; the span may include
+                // parentheses that were around it
+                let text = self
+                    .text(expr.span)
+                    .trim_matches(|ch: char| !ch.is_ascii_digit() && ch != '.');
+                if text.is_empty() {
+                    value.to_string()
+                } else {
+                    text.to_string()
+                }
+            }
+            ExpressionKind::String(value) => quote(value),
+            ExpressionKind::Boolean(value) => value.to_string(),
+            ExpressionKind::Nothing => "nothing".to_string(),
+            ExpressionKind::Identifier(name) => name.clone(),
+            ExpressionKind::BinaryOp(operation) => {
+                let level = precedence(expr);
+                let arithmetic = matches!(
+                    operation.operator,
+                    BinaryOperator::Add
+                        | BinaryOperator::Subtract
+                        | BinaryOperator::Multiply
+                        | BinaryOperator::Divide
+                );
+                // Operators group to the left, so only the right side needs
+                // parentheses at the same level
+                let left = self.expression(&operation.left, level, arithmetic);
+                let right = self.expression(&operation.right, level + 1, followed);
+                format!("{} {} {}", left, operation.operator, right)
+            }
+            ExpressionKind::Not(operand) => {
+                format!("not {}", self.expression(operand, NOT, followed))
+            }
+            ExpressionKind::ListQuery(query) => {
+                let keyword = match query.query {
+                    ListQueryKind::Length => "length of",
+                    ListQueryKind::First => "first of",
+                    ListQueryKind::Last => "last of",
+                };
+                format!(
+                    "{} {}",
+                    keyword,
+                    self.expression(&query.list, QUERY, followed)
+                )
+            }
+            ExpressionKind::Index(index) => {
+                let list = self.expression(&index.list, PRIMARY, true);
+                let position = self.expression(&index.index, 0, false);
+                format!("{}[{}]", list, position)
+            }
+            ExpressionKind::Member(member) => {
+                let mut object = self.expression(&member.object, PRIMARY, true);
+                // `1.x` would lex as the number `1.` followed by `x`
+                if matches!(member.object.kind, ExpressionKind::Number(_)) {
+                    object = format!("({})", object);
+                }
+                format!("{}.{}", object, member.field)
+            }
+            ExpressionKind::FunctionCall(call) => {
+                let mut arguments = Vec::new();
+                for (i, argument) in call.arguments.iter().enumerate() {
+                    let last = i + 1 == call.arguments.len();
+                    let mut text = self.expression(argument, SUM, !last);
+                    // `f [1, 2]` would index `f`
+                    if i == 0 && text.starts_with('[') {
+                        text = format!("({})", text);
+                    }
+                    arguments.push(text);
+                }
+                if arguments.is_empty() {
+                    call.name.clone()
+                } else {
+                    format!("{} {}", call.name, arguments.join(", "))
+                }
+            }
+            ExpressionKind::List(items) => {
+                let items = self.entries(expr.span, |formatter| formatter.list(items));
+                self.bracketed(expr.span, "[", items, "]")
+            }
+            ExpressionKind::Object(fields) => {
+                let fields = self.entries(expr.span, |formatter| {
+                    fields
+                        .iter()
+                        .enumerate()
+                        .map(|(i, field)| {
+                            let value = formatter.expression(&field.value, 0, i + 1 < fields.len());
+                            format!("{}: {}", field.name, value)
+                        })
+                        .collect()
+                });
+                if fields.is_empty() {
+                    "{}".to_string()
+                } else if self.text(expr.span).contains('\n') {
+                    self.bracketed(expr.span, "{", fields, "}")
+                } else {
+                    format!("{{ {} }}", fields.join(", "))
+                }
+            }
+        }
+    }
+
+    /// Formats the entries of a literal, one level deeper if the literal
+    /// is spread over several lines.
+    fn entries(
+        &mut self,
+        span: Span,
+        format: impl FnOnce(&mut Self) -> Vec<String>,
+    ) -> Vec<String> {
+        let multiline = self.text(span).contains('\n');
+        self.indent += multiline as usize;
+        let entries = format(self);
+        self.indent -= multiline as usize;
+        entries
+    }
+
+    /// Joins entries on one line, or one per line if the literal at `span`
+    /// was written over several.
+    fn bracketed(&self, span: Span, open: &str, entries: Vec<String>, close: &str) -> String {
+        if entries.is_empty() || !self.text(span).contains('\n') {
+            return format!("{}{}{}", open, entries.join(", "), close);
+        }
+        let inner = INDENT.repeat(self.indent + 1);
+        let outer = INDENT.repeat(self.indent);
+        format!(
+            "{}\n{}{}\n{}{}",
+            open,
+            inner,
+            entries.join(&format!(",\n{}", inner)),
+            outer,
+            close
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+    use delta::lexer::{self, Lexer};
+    use std::fs;
+    use std::path::Path;
+
+    fn format(source: &str) -> String {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let comments = lexer::comments(&tokens);
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse();
+        assert!(
+            parser.take_errors().is_empty(),
+            "{:?} does not parse",
+            source
+        );
+        format_program(&program, &comments, source)
+    }
+
+    const COMMENTED: &str = "\
+# A header comment
+let x be 1   # after code
+define area with w, h
+\tlet result be w*h
+  \t# own line, oddly indented
+\treturn result
+let total be (1 + /* inside */ 2) * 3
+when x is 1 then
+    show \"one\"
+        # deeper than the code
+otherwise
+    show \"other\"
+    # before the dedent
+show area 2, 3
+// the end
+";
+
+    #[test]
+    fn formatting_twice_changes_nothing_more() {
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let mut sources = vec![COMMENTED.to_string()];
+        for entry in fs::read_dir(examples).unwrap() {
+            sources.push(fs::read_to_string(entry.unwrap().path()).unwrap());
+        }
+        for source in sources {
+            let formatted = format(&source);
+            assert_eq!(format(&formatted), formatted);
+        }
+    }
+
+    #[test]
+    fn comments_stay_with_the_code_around_them() {
+        let expected = "\
+# A header comment
+let x be 1  # after code
+
+define area with w, h
+    let result be w * h
+    # own line, oddly indented
+    return result
+end
+
+let total be (1 + 2) * 3  /* inside */
+when x is equal 1 then
+    show \"one\"
+    # deeper than the code
+otherwise
+    show \"other\"
+    # before the dedent
+show area 2, 3
+// the end
+";
+        assert_eq!(format(COMMENTED), expected);
+    }
+
+    #[test]
+    fn tabs_and_spaces_become_four_spaces() {
+        let source = "\
+repeat while x is 1
+\tshow 1
+    when x then
+    \t\t  show 2
+";
+        let expected = "\
+repeat while x is equal 1
+    show 1
+    when x then
+        show 2
+";
+        assert_eq!(format(source), expected);
+    }
+
+    #[test]
+    fn every_define_ends_with_end() {
+        let source = "\
+define twice with n
+    return n * 2
+define shout with text
+    show text
+end
+show twice 2
+";
+        let expected = "\
+define twice with n
+    return n * 2
+end
+
+define shout with text
+    show text
+end
+
+show twice 2
+";
+        assert_eq!(format(source), expected);
+    }
+}
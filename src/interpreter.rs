@@ -0,0 +1,663 @@
+//! A tree-walking interpreter, used by `delta run` and the REPL.
+//!
+//! Variables live in lexical scopes: every block opens a scope, and `let`
+//! updates the innermost scope that already has the name or declares it in
+//! the current one otherwise. Each function call gets a frame of its own
+//! that starts out with only the parameters, as in compiled code.
+
+use crate::ast::*;
+use delta::diagnostics::{Code, Diagnostic, codes};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+mod value;
+
+pub use value::Value;
+
+/// Deep enough for ordinary recursion, shallow enough to report runaway
+/// recursion as an error instead of overflowing the native stack.
+const MAX_CALL_DEPTH: usize = 500;
+
+/// An error on its way out of the program, collecting the functions it
+/// passes through.
+struct RuntimeError {
+    // Boxed to keep `Result`s small on the hot path
+    diagnostic: Box<Diagnostic>,
+    // The functions being run when the error happened, innermost first
+    trace: Vec<String>,
+}
+
+impl RuntimeError {
+    fn new(code: Code, message: impl Into<String>) -> Self {
+        Diagnostic::error(code, message).into()
+    }
+
+    /// Points the error at `span` unless a more precise node already did.
+    fn locate(mut self, span: Span) -> Self {
+        *self.diagnostic = self.diagnostic.locate(span);
+        self
+    }
+
+    /// The finished diagnostic, with a note for each function the error
+    /// passed through.
+    fn into_diagnostic(self) -> Diagnostic {
+        let mut diagnostic = *self.diagnostic;
+        // Recursion would repeat the same note hundreds of times
+        let mut index = 0;
+        while index < self.trace.len() {
+            let function = &self.trace[index];
+            let repeats = self.trace[index..]
+                .iter()
+                .take_while(|name| *name == function)
+                .count();
+            diagnostic = if repeats > 1 {
+                diagnostic.with_note(format!("in function '{}' ({} calls)", function, repeats))
+            } else {
+                diagnostic.with_note(format!("in function '{}'", function))
+            };
+            index += repeats;
+        }
+        diagnostic
+    }
+}
+
+impl From<Diagnostic> for RuntimeError {
+    fn from(diagnostic: Diagnostic) -> Self {
+        RuntimeError {
+            diagnostic: Box::new(diagnostic),
+            trace: Vec::new(),
+        }
+    }
+}
+
+type RuntimeResult<T> = Result<T, RuntimeError>;
+
+/// What to do after running a statement.
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Vec<Value>),
+}
+
+/// The variables of one function call, or of the top level of the program.
+struct Frame {
+    // Innermost scope last
+    scopes: Vec<HashMap<String, Value>>,
+}
+
+impl Frame {
+    fn new() -> Self {
+        Frame {
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Value> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn assign(&mut self, name: &str, value: Value) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(slot) = scope.get_mut(name) {
+                *slot = value;
+                return;
+            }
+        }
+        self.declare(name, value);
+    }
+
+    fn declare(&mut self, name: &str, value: Value) {
+        self.scopes
+            .last_mut()
+            .expect("a frame always has a scope")
+            .insert(name.to_string(), value);
+    }
+}
+
+pub struct Interpreter {
+    functions: HashMap<String, Rc<FunctionDef>>,
+    // The top level first, the function being run last
+    frames: Vec<Frame>,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Interpreter {
+            functions: HashMap::new(),
+            frames: vec![Frame::new()],
+        }
+    }
+
+    pub fn run(&mut self, program: &Program) -> Result<(), Diagnostic> {
+        self.register_functions(&program.statements)
+            .and_then(|()| self.run_statements(&program.statements))
+            .map(|_| ())
+            .map_err(RuntimeError::into_diagnostic)
+    }
+
+    /// Runs another part of a program in the state left by earlier runs, as
+    /// the REPL does with each entry. A `define` replaces any earlier
+    /// function of the same name. An entry that is a lone expression gives
+    /// back its values.
+    pub fn run_more(&mut self, program: &Program) -> Result<Vec<Value>, Diagnostic> {
+        for statement in &program.statements {
+            if let StatementKind::FunctionDef(func_def) = &statement.kind {
+                self.functions.remove(&func_def.name);
+            }
+        }
+
+        if let [statement] = program.statements.as_slice()
+            && let StatementKind::Expression(expression) = &statement.kind
+        {
+            return self
+                .evaluate_values(expression)
+                .map_err(RuntimeError::into_diagnostic);
+        }
+        self.run(program).map(|()| Vec::new())
+    }
+
+    /// Records the top-level `define` blocks of a program so that calls can
+    /// be made before the definition.
+    fn register_functions(&mut self, statements: &[Statement]) -> RuntimeResult<()> {
+        for statement in statements {
+            if let StatementKind::FunctionDef(func_def) = &statement.kind {
+                if let Some(first) = self.functions.get(&func_def.name) {
+                    return Err(Diagnostic::error(
+                        codes::DUPLICATE_FUNCTION,
+                        format!("Function '{}' is defined more than once", func_def.name),
+                    )
+                    .with_primary(func_def.name_span, "defined again here")
+                    .with_secondary(first.name_span, "first defined here")
+                    .into());
+                }
+                self.functions
+                    .insert(func_def.name.clone(), Rc::new(func_def.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames
+            .last()
+            .expect("the top-level frame is never popped")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.frames
+            .last_mut()
+            .expect("the top-level frame is never popped")
+    }
+
+    /// Runs statements in order, stopping early at `break`, `continue` and
+    /// `return`.
+    fn run_statements(&mut self, statements: &[Statement]) -> RuntimeResult<Flow> {
+        for statement in statements {
+            let flow = self.run_statement(statement)?;
+            if !matches!(flow, Flow::Normal) {
+                return Ok(flow);
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Runs a block in a scope of its own, optionally starting with one
+    /// variable already bound, such as a loop variable.
+    fn run_block(
+        &mut self,
+        statements: &[Statement],
+        binding: Option<(&str, Value)>,
+    ) -> RuntimeResult<Flow> {
+        let mut scope = HashMap::new();
+        if let Some((name, value)) = binding {
+            scope.insert(name.to_string(), value);
+        }
+        self.frame_mut().scopes.push(scope);
+        let flow = self.run_statements(statements);
+        self.frame_mut().scopes.pop();
+        flow
+    }
+
+    fn run_statement(&mut self, statement: &Statement) -> RuntimeResult<Flow> {
+        self.execute(statement)
+            .map_err(|error| error.locate(statement.span))
+    }
+
+    fn execute(&mut self, statement: &Statement) -> RuntimeResult<Flow> {
+        match &statement.kind {
+            StatementKind::Show(show) => {
+                let value = self.evaluate(&show.value)?;
+                println!("{}", value);
+            }
+            StatementKind::Let(let_stmt) => {
+                let values = self.evaluate_values(&let_stmt.value)?;
+                if values.len() != let_stmt.identifiers.len() {
+                    return Err(RuntimeError::new(
+                        codes::VALUE_COUNT,
+                        format!(
+                            "Expected {} value(s) to assign to {}, found {}",
+                            let_stmt.identifiers.len(),
+                            let_stmt.identifiers.join(", "),
+                            values.len()
+                        ),
+                    ));
+                }
+                for (identifier, value) in let_stmt.identifiers.iter().zip(values) {
+                    self.frame_mut().assign(identifier, value);
+                }
+            }
+            StatementKind::When(when_stmt) => {
+                if self.evaluate_condition(&when_stmt.condition)? {
+                    return self.run_block(&when_stmt.then_block, None);
+                } else if let Some(otherwise_block) = &when_stmt.otherwise_block {
+                    return self.run_block(otherwise_block, None);
+                }
+            }
+            StatementKind::RepeatWhile(repeat_stmt) => {
+                while self.evaluate_condition(&repeat_stmt.condition)? {
+                    match self.run_block(&repeat_stmt.body, None)? {
+                        Flow::Break => break,
+                        Flow::Normal | Flow::Continue => {}
+                        flow @ Flow::Return(_) => return Ok(flow),
+                    }
+                }
+            }
+            StatementKind::RepeatRange(range_stmt) => return self.run_repeat_range(range_stmt),
+            StatementKind::RepeatEach(each_stmt) => return self.run_repeat_each(each_stmt),
+            StatementKind::Add(add_stmt) => {
+                let item = self.evaluate(&add_stmt.item)?;
+                let items = self.evaluate(&add_stmt.list)?;
+                let Value::List(items) = items else {
+                    return Err(Self::expected("a list", "'add ... to'", &items));
+                };
+                items.borrow_mut().push(item);
+            }
+            StatementKind::Remove(remove_stmt) => {
+                let item = self.evaluate(&remove_stmt.item)?;
+                let items = self.evaluate(&remove_stmt.list)?;
+                let Value::List(items) = items else {
+                    return Err(Self::expected("a list", "'remove ... from'", &items));
+                };
+                let mut items = items.borrow_mut();
+                if let Some(position) = items.iter().position(|existing| existing.equals(&item)) {
+                    items.remove(position);
+                }
+            }
+            StatementKind::SetField(set_stmt) => {
+                let value = self.evaluate(&set_stmt.value)?;
+                let object = self.evaluate(&set_stmt.object)?;
+                let Value::Object(fields) = object else {
+                    return Err(Self::expected("an object", "a field assignment", &object));
+                };
+                fields.borrow_mut().insert(set_stmt.field.clone(), value);
+            }
+            StatementKind::Break => return Ok(Flow::Break),
+            StatementKind::Continue => return Ok(Flow::Continue),
+            StatementKind::Error => {}
+            StatementKind::FunctionDef(func_def) => {
+                // Nested definitions become visible once they are reached
+                self.functions
+                    .entry(func_def.name.clone())
+                    .or_insert_with(|| Rc::new(func_def.clone()));
+            }
+            StatementKind::Return(return_stmt) => {
+                let mut values = Vec::with_capacity(return_stmt.values.len());
+                for value in &return_stmt.values {
+                    values.push(self.evaluate(value)?);
+                }
+                return Ok(Flow::Return(values));
+            }
+            StatementKind::Expression(expr) => {
+                self.evaluate_values(expr)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Runs a range loop with the same trip count computation as the
+    /// compiled version, so both produce identical loop values.
+    fn run_repeat_range(&mut self, range_stmt: &RepeatRangeStatement) -> RuntimeResult<Flow> {
+        let start = self.evaluate_number(&range_stmt.start, "the start of a range")?;
+        let end = self.evaluate_number(&range_stmt.end, "the end of a range")?;
+        let magnitude = match &range_stmt.step {
+            Some(step) => self.evaluate_number(step, "the step of a range")?.abs(),
+            None => 1.0,
+        };
+        if magnitude == 0.0 {
+            return Err(RuntimeError::new(
+                codes::ZERO_STEP,
+                "The step of a range cannot be zero",
+            ));
+        }
+
+        let (delta, distance) = if end >= start {
+            (magnitude, end - start)
+        } else {
+            (-magnitude, start - end)
+        };
+        // NaN comes from an end that is not finite
+        let steps = (distance / magnitude).floor();
+        if steps.is_nan() || steps >= i64::MAX as f64 {
+            return Err(RuntimeError::new(
+                codes::RANGE_TOO_LONG,
+                "The range has too many steps to count",
+            ));
+        }
+        let trip_count = steps as i64 + 1;
+
+        for index in 0..trip_count {
+            let current = Value::Number(start + index as f64 * delta);
+            match self.run_block(&range_stmt.body, Some((&range_stmt.variable, current)))? {
+                Flow::Break => break,
+                Flow::Normal | Flow::Continue => {}
+                flow @ Flow::Return(_) => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Runs `repeat for each item in list`, checking the length before every
+    /// iteration like the compiled version does.
+    fn run_repeat_each(&mut self, each_stmt: &RepeatEachStatement) -> RuntimeResult<Flow> {
+        let items = self.evaluate(&each_stmt.list)?;
+        let Value::List(items) = items else {
+            return Err(Self::expected("a list", "a 'repeat for each' loop", &items));
+        };
+
+        let mut index = 0;
+        loop {
+            // The borrow ends before the body runs, so the body may change the list
+            let item = match items.borrow().get(index) {
+                Some(item) => item.clone(),
+                None => break,
+            };
+            index += 1;
+
+            match self.run_block(&each_stmt.body, Some((&each_stmt.variable, item)))? {
+                Flow::Break => break,
+                Flow::Normal | Flow::Continue => {}
+                flow @ Flow::Return(_) => return Ok(flow),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn expected(kind: &str, context: &str, found: &Value) -> RuntimeError {
+        RuntimeError::new(
+            codes::TYPE_MISMATCH,
+            format!(
+                "Expected {} for {}, found {} '{}'",
+                kind,
+                context,
+                found.type_name(),
+                found
+            ),
+        )
+    }
+
+    fn evaluate_number(&mut self, expression: &Expression, context: &str) -> RuntimeResult<f64> {
+        match self.evaluate(expression)? {
+            Value::Number(n) => Ok(n),
+            other => Err(Self::expected("a number", context, &other)),
+        }
+    }
+
+    fn evaluate_condition(&mut self, condition: &Expression) -> RuntimeResult<bool> {
+        Ok(self.evaluate(condition)?.is_truthy())
+    }
+
+    /// A bare identifier that is not a variable but a function is a call
+    /// without arguments.
+    fn names_function(&self, name: &str) -> bool {
+        self.frame().lookup(name).is_none() && self.functions.contains_key(name)
+    }
+
+    /// Evaluates an expression that may produce any number of values, which
+    /// only calls to functions do.
+    fn evaluate_values(&mut self, expression: &Expression) -> RuntimeResult<Vec<Value>> {
+        let values = match &expression.kind {
+            ExpressionKind::FunctionCall(call) => self.call_function(&call.name, &call.arguments),
+            ExpressionKind::Identifier(name) if self.names_function(name) => {
+                self.call_function(name, &[])
+            }
+            _ => return Ok(vec![self.evaluate(expression)?]),
+        };
+        values.map_err(|error| error.locate(expression.span))
+    }
+
+    /// Evaluates an expression that produces exactly one value.
+    fn evaluate(&mut self, expression: &Expression) -> RuntimeResult<Value> {
+        self.compute(expression)
+            .map_err(|error| error.locate(expression.span))
+    }
+
+    fn compute(&mut self, expression: &Expression) -> RuntimeResult<Value> {
+        match &expression.kind {
+            ExpressionKind::Number(n) => Ok(Value::Number(*n)),
+            ExpressionKind::String(s) => Ok(Value::Text(s.clone())),
+            ExpressionKind::Boolean(value) => Ok(Value::Boolean(*value)),
+            ExpressionKind::Nothing => Ok(Value::Nothing),
+            ExpressionKind::Identifier(name) if self.names_function(name) => {
+                self.evaluate_call(name, &[])
+            }
+            ExpressionKind::Identifier(name) => match self.frame().lookup(name) {
+                Some(value) => Ok(value.clone()),
+                None => Err(Diagnostic::error(
+                    codes::UNDEFINED_VARIABLE,
+                    format!("Undefined variable: {}", name),
+                )
+                .with_primary(expression.span, "not defined at this point")
+                .into()),
+            },
+            ExpressionKind::BinaryOp(binop) => self.evaluate_binary_operation(binop),
+            ExpressionKind::Not(operand) => Ok(Value::Boolean(!self.evaluate_condition(operand)?)),
+            ExpressionKind::FunctionCall(call) => self.evaluate_call(&call.name, &call.arguments),
+            ExpressionKind::List(items) => {
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    values.push(self.evaluate(item)?);
+                }
+                Ok(Value::list(values))
+            }
+            ExpressionKind::Index(index_expr) => {
+                let items = self.evaluate(&index_expr.list)?;
+                let Value::List(items) = items else {
+                    return Err(Self::expected("a list", "indexing", &items));
+                };
+                // Fractions are truncated, as in compiled code
+                let index = self
+                    .evaluate_number(&index_expr.index, "a list index")?
+                    .trunc();
+                let items = items.borrow();
+                if index < 0.0 || index >= items.len() as f64 {
+                    return Err(RuntimeError::new(
+                        codes::INDEX_OUT_OF_RANGE,
+                        format!(
+                            "List index {} is out of range for a list of length {}",
+                            index,
+                            items.len()
+                        ),
+                    ));
+                }
+                Ok(items[index as usize].clone())
+            }
+            ExpressionKind::ListQuery(query) => {
+                let items = self.evaluate(&query.list)?;
+                let Value::List(items) = items else {
+                    return Err(Self::expected("a list", "a list query", &items));
+                };
+                let items = items.borrow();
+                let item = match query.query {
+                    ListQueryKind::Length => return Ok(Value::Number(items.len() as f64)),
+                    ListQueryKind::First => items.first(),
+                    ListQueryKind::Last => items.last(),
+                };
+                item.cloned().ok_or_else(|| {
+                    RuntimeError::new(codes::EMPTY_LIST, "Cannot take an item from an empty list")
+                })
+            }
+            ExpressionKind::Object(fields) => {
+                let mut values = HashMap::with_capacity(fields.len());
+                for field in fields {
+                    let value = self.evaluate(&field.value)?;
+                    values.insert(field.name.clone(), value);
+                }
+                Ok(Value::object(values))
+            }
+            ExpressionKind::Member(member) => {
+                let object = self.evaluate(&member.object)?;
+                let Value::Object(fields) = object else {
+                    return Err(Self::expected("an object", "'.'", &object));
+                };
+                let fields = fields.borrow();
+                fields.get(&member.field).cloned().ok_or_else(|| {
+                    RuntimeError::new(
+                        codes::UNKNOWN_FIELD,
+                        format!("Object has no field '{}'", member.field),
+                    )
+                })
+            }
+        }
+    }
+
+    fn evaluate_binary_operation(&mut self, binop: &BinaryOperation) -> RuntimeResult<Value> {
+        // The right side only runs when the left side does not decide it
+        match binop.operator {
+            BinaryOperator::And => {
+                let result = self.evaluate_condition(&binop.left)?
+                    && self.evaluate_condition(&binop.right)?;
+                return Ok(Value::Boolean(result));
+            }
+            BinaryOperator::Or => {
+                let result = self.evaluate_condition(&binop.left)?
+                    || self.evaluate_condition(&binop.right)?;
+                return Ok(Value::Boolean(result));
+            }
+            _ => {}
+        }
+
+        let left = self.evaluate(&binop.left)?;
+        let right = self.evaluate(&binop.right)?;
+
+        // Any two values can be compared for equality
+        match binop.operator {
+            BinaryOperator::Equal => return Ok(Value::Boolean(left.equals(&right))),
+            BinaryOperator::NotEqual => return Ok(Value::Boolean(!left.equals(&right))),
+            _ => {}
+        }
+
+        let (Value::Number(left_num), Value::Number(right_num)) = (&left, &right) else {
+            return Err(RuntimeError::new(
+                codes::TYPE_MISMATCH,
+                format!(
+                    "Cannot use '{}' on {} and {}",
+                    binop.operator,
+                    left.type_name(),
+                    right.type_name()
+                ),
+            ));
+        };
+        let (left_num, right_num) = (*left_num, *right_num);
+
+        let result = match binop.operator {
+            BinaryOperator::GreaterThan => Value::Boolean(left_num > right_num),
+            BinaryOperator::LessThan => Value::Boolean(left_num < right_num),
+            BinaryOperator::GreaterThanOrEqual => Value::Boolean(left_num >= right_num),
+            BinaryOperator::LessThanOrEqual => Value::Boolean(left_num <= right_num),
+            BinaryOperator::Add => Value::Number(left_num + right_num),
+            BinaryOperator::Subtract => Value::Number(left_num - right_num),
+            BinaryOperator::Multiply => Value::Number(left_num * right_num),
+            BinaryOperator::Divide => {
+                if right_num == 0.0 {
+                    return Err(RuntimeError::new(
+                        codes::DIVISION_BY_ZERO,
+                        "Division by zero",
+                    ));
+                }
+                Value::Number(left_num / right_num)
+            }
+            BinaryOperator::Equal
+            | BinaryOperator::NotEqual
+            | BinaryOperator::And
+            | BinaryOperator::Or => unreachable!("handled before the operands are checked"),
+        };
+        Ok(result)
+    }
+
+    /// Evaluates a call whose result is used as a single value.
+    fn evaluate_call(&mut self, name: &str, arguments: &[Expression]) -> RuntimeResult<Value> {
+        let mut values = self.call_function(name, arguments)?;
+        match values.len() {
+            1 => Ok(values.remove(0)),
+            0 => Err(RuntimeError::new(
+                codes::VALUE_COUNT,
+                format!("Function '{}' does not return a value", name),
+            )),
+            count => Err(Diagnostic::error(
+                codes::VALUE_COUNT,
+                format!("Function '{}' returns {} values", name, count),
+            )
+            .with_help(format!("unpack them with `let a, b be {}`", name))
+            .into()),
+        }
+    }
+
+    fn call_function(&mut self, name: &str, arguments: &[Expression]) -> RuntimeResult<Vec<Value>> {
+        let func_def = match self.functions.get(name) {
+            Some(func_def) => Rc::clone(func_def),
+            None => {
+                return Err(RuntimeError::new(
+                    codes::UNDEFINED_FUNCTION,
+                    format!("Undefined function: {}", name),
+                ));
+            }
+        };
+
+        if func_def.parameters.len() != arguments.len() {
+            return Err(Diagnostic::error(
+                codes::ARGUMENT_COUNT,
+                format!(
+                    "Function '{}' expects {} argument(s), but {} were given",
+                    name,
+                    func_def.parameters.len(),
+                    arguments.len()
+                ),
+            )
+            .with_secondary(func_def.name_span, "defined here")
+            .into());
+        }
+        if self.frames.len() > MAX_CALL_DEPTH {
+            return Err(Diagnostic::error(
+                codes::CALL_DEPTH,
+                format!("Too many nested calls (more than {})", MAX_CALL_DEPTH),
+            )
+            .with_help(format!(
+                "check that '{}' has a base case that stops the recursion",
+                name
+            ))
+            .into());
+        }
+
+        // Arguments are evaluated in the caller's frame
+        let mut frame = Frame::new();
+        for (param, argument) in func_def.parameters.iter().zip(arguments) {
+            let value = self.evaluate(argument)?;
+            frame.declare(param, value);
+        }
+
+        self.frames.push(frame);
+        let flow = self.run_statements(&func_def.body);
+        self.frames.pop();
+
+        match flow {
+            Ok(Flow::Return(values)) => Ok(values),
+            // The parser keeps `break` and `continue` inside loops
+            Ok(Flow::Normal | Flow::Break | Flow::Continue) => Ok(Vec::new()),
+            Err(mut error) => {
+                error.trace.push(name.to_string());
+                Err(error)
+            }
+        }
+    }
+}
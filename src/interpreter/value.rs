@@ -0,0 +1,216 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+/// A runtime value. Lists and objects are shared, so changing one through a
+/// variable is visible through every other variable holding it, just like in
+/// compiled code.
+#[derive(Clone)]
+pub enum Value {
+    Number(f64),
+    Text(String),
+    Boolean(bool),
+    Nothing,
+    List(Rc<RefCell<Vec<Value>>>),
+    Object(Rc<RefCell<HashMap<String, Value>>>),
+}
+
+impl Value {
+    pub fn list(items: Vec<Value>) -> Self {
+        Value::List(Rc::new(RefCell::new(items)))
+    }
+
+    pub fn object(fields: HashMap<String, Value>) -> Self {
+        Value::Object(Rc::new(RefCell::new(fields)))
+    }
+
+    /// The name of the value's kind, spelled like the compiler's types.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::Text(_) => "string",
+            Value::Boolean(_) => "boolean",
+            Value::Nothing => "nothing",
+            Value::List(_) => "list",
+            Value::Object(_) => "object",
+        }
+    }
+
+    /// Equality as used by `is equal` and `remove`: lists and objects by
+    /// identity, everything else by content. Values of different kinds are
+    /// never equal.
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Number(left), Value::Number(right)) => left == right,
+            (Value::Text(left), Value::Text(right)) => left == right,
+            (Value::Boolean(left), Value::Boolean(right)) => left == right,
+            (Value::Nothing, Value::Nothing) => true,
+            (Value::List(left), Value::List(right)) => Rc::ptr_eq(left, right),
+            (Value::Object(left), Value::Object(right)) => Rc::ptr_eq(left, right),
+            _ => false,
+        }
+    }
+
+    /// The same rules as the compiled `build_truthiness`: `false`, `nothing`,
+    /// zero, the empty string and the empty list are false.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Number(n) => *n != 0.0,
+            Value::Text(text) => !text.is_empty(),
+            Value::Boolean(value) => *value,
+            Value::Nothing => false,
+            Value::List(items) => !items.borrow().is_empty(),
+            Value::Object(_) => true,
+        }
+    }
+}
+
+impl Value {
+    /// Writes the value as `show` prints it. `open` holds the lists and
+    /// objects being written further out, so one that contains itself is
+    /// written as `[...]` or `{...}` when it comes round again, rather than
+    /// forever.
+    fn write(&self, f: &mut fmt::Formatter, open: &mut Vec<*const ()>) -> fmt::Result {
+        match self {
+            Value::Number(n) => write!(f, "{}", format_number(*n)),
+            Value::Text(text) => write!(f, "{}", text),
+            Value::Boolean(value) => write!(f, "{}", value),
+            Value::Nothing => write!(f, "nothing"),
+            Value::List(items) => {
+                let id = Rc::as_ptr(items) as *const ();
+                if open.contains(&id) {
+                    return write!(f, "[...]");
+                }
+                open.push(id);
+                write!(f, "[")?;
+                for (index, item) in items.borrow().iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.write(f, open)?;
+                }
+                open.pop();
+                write!(f, "]")
+            }
+            Value::Object(fields) => {
+                let id = Rc::as_ptr(fields) as *const ();
+                if open.contains(&id) {
+                    return write!(f, "{{...}}");
+                }
+                open.push(id);
+                // Sorted so the output matches the compiled version
+                let fields = fields.borrow();
+                let mut names: Vec<&String> = fields.keys().collect();
+                names.sort();
+                write!(f, "{{")?;
+                for (index, name) in names.into_iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", name)?;
+                    fields[name].write(f, open)?;
+                }
+                open.pop();
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, &mut Vec::new())
+    }
+}
+
+// Written like `Display` rather than derived, which would recurse forever
+// into a list that contains itself
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Text(text) => write!(f, "{:?}", text),
+            other => write!(f, "{}", other),
+        }
+    }
+}
+
+/// Significant digits in a printed number, as in the compiled `%.16g`
+const NUMBER_DIGITS: i32 = 16;
+
+/// Spells `n` the way C's `printf("%.16g")` does, so that interpreted and
+/// compiled programs print the same thing.
+fn format_number(n: f64) -> String {
+    if n.is_nan() {
+        return if n.is_sign_negative() { "-nan" } else { "nan" }.to_string();
+    }
+    if n.is_infinite() {
+        return if n < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+
+    let scientific = format!("{:.*e}", (NUMBER_DIGITS - 1) as usize, n);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    if !(-4..NUMBER_DIGITS).contains(&exponent) {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", trim_fraction(mantissa), sign, exponent.abs())
+    } else {
+        let fixed = format!("{:.*}", (NUMBER_DIGITS - 1 - exponent) as usize, n);
+        trim_fraction(&fixed).to_string()
+    }
+}
+
+/// Drops the trailing zeros of a fraction, and the point if nothing is left.
+fn trim_fraction(digits: &str) -> &str {
+    if digits.contains('.') {
+        digits.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        digits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_print_like_printf() {
+        let cases = [
+            (10.0 / 3.0, "3.333333333333333"),
+            (0.1 + 0.2, "0.3"),
+            (100.0, "100"),
+            (-2.5, "-2.5"),
+            (1e15, "1000000000000000"),
+            (1e16, "1e+16"),
+            (1e-5, "1e-05"),
+            (0.0001, "0.0001"),
+            (123456789.12345679, "123456789.1234568"),
+            (f64::INFINITY, "inf"),
+        ];
+        for (number, expected) in cases {
+            assert_eq!(Value::Number(number).to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn values_that_contain_themselves_print_once() {
+        let list = Value::list(vec![Value::Number(1.0)]);
+        let Value::List(items) = &list else {
+            unreachable!()
+        };
+        items.borrow_mut().push(list.clone());
+        assert_eq!(list.to_string(), "[1, [...]]");
+        assert!(list.equals(&list));
+
+        let object = Value::object(HashMap::new());
+        let Value::Object(fields) = &object else {
+            unreachable!()
+        };
+        fields.borrow_mut().insert("me".to_string(), object.clone());
+        fields
+            .borrow_mut()
+            .insert("items".to_string(), list.clone());
+        assert_eq!(object.to_string(), "{items: [1, [...]], me: {...}}");
+        assert_eq!(format!("{:?}", object), object.to_string());
+    }
+}
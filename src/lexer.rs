@@ -1,3 +1,7 @@
+use crate::diagnostics::{codes, Diagnostic};
+use crate::span::Span;
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     // Keywords
@@ -10,6 +14,25 @@ pub enum Token {
     Define,
     With,
     End,
+    Return,
+    Repeat,
+    While,
+    Break,
+    Continue,
+    For,
+    Each,
+    From,
+    To,
+    By,
+    In,
+    Add,
+    Remove,
+    LengthOf,
+    FirstOf,
+    LastOf,
+    And,
+    Or,
+    Not,
     
     // Comparators
     IsGreaterThan,
@@ -23,12 +46,26 @@ pub enum Token {
     Number(f64),
     String(String),
     Identifier(String),
+    True,
+    False,
+    Nothing,
 
     // Operators
     Plus,
     Minus,
     Multiply,
     Divide,
+
+    // Punctuation
+    Comma,
+    LeftParen,
+    RightParen,
+    LeftBracket,
+    RightBracket,
+    LeftBrace,
+    RightBrace,
+    Colon,
+    Dot,
     
     // Whitespace and structure
     Newline,
@@ -39,43 +76,147 @@ pub enum Token {
     Eof,
 }
 
+/// A token and the source it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+    /// The comments between the previous token and this one. A comment at
+    /// the end of a line belongs to the `Newline` after it.
+    pub comments: Vec<Comment>,
+}
+
+/// A `#` or `//` comment, which runs to the end of the line, or a
+/// `/* */` block comment. The parser never sees comments; they ride along
+/// on tokens for the tools that print source back out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    /// The comment as written, markers included
+    pub text: String,
+    pub span: Span,
+}
+
+/// Every comment attached to `tokens`, in source order.
+pub fn comments(tokens: &[SpannedToken]) -> Vec<Comment> {
+    tokens.iter().flat_map(|token| token.comments.iter().cloned()).collect()
+}
+
+/// Describes a token the way it is written, for error messages.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let spelling = match self {
+            Token::Let => "let",
+            Token::Be => "be",
+            Token::When => "when",
+            Token::Then => "then",
+            Token::Otherwise => "otherwise",
+            Token::Show => "show",
+            Token::Define => "define",
+            Token::With => "with",
+            Token::End => "end",
+            Token::Return => "return",
+            Token::Repeat => "repeat",
+            Token::While => "while",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::For => "for",
+            Token::Each => "each",
+            Token::From => "from",
+            Token::To => "to",
+            Token::By => "by",
+            Token::In => "in",
+            Token::Add => "add",
+            Token::Remove => "remove",
+            Token::LengthOf => "length of",
+            Token::FirstOf => "first of",
+            Token::LastOf => "last of",
+            Token::And => "and",
+            Token::Or => "or",
+            Token::Not => "not",
+            Token::IsGreaterThan => "is greater than",
+            Token::IsLessThan => "is less than",
+            Token::IsGreaterThanOrEqual => "is greater than or equal",
+            Token::IsLessThanOrEqual => "is less than or equal",
+            Token::IsEqual => "is equal",
+            Token::IsNotEqual => "is not equal",
+            Token::Number(n) => return write!(f, "number {}", n),
+            Token::String(s) => return write!(f, "string {:?}", s),
+            Token::Identifier(name) => return write!(f, "name '{}'", name),
+            Token::True => "true",
+            Token::False => "false",
+            Token::Nothing => "nothing",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Multiply => "*",
+            Token::Divide => "/",
+            Token::Comma => ",",
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::LeftBracket => "[",
+            Token::RightBracket => "]",
+            Token::LeftBrace => "{",
+            Token::RightBrace => "}",
+            Token::Colon => ":",
+            Token::Dot => ".",
+            Token::Newline => return write!(f, "end of line"),
+            Token::Indent => return write!(f, "indented block"),
+            Token::Dedent => return write!(f, "end of block"),
+            Token::Eof => return write!(f, "end of file"),
+        };
+        write!(f, "'{}'", spelling)
+    }
+}
+
+/// How a piece of input ends, as `Lexer::ending` finds it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ending {
+    /// Nothing is left open.
+    Complete,
+    /// Inside an indented block, or after a line that opens one. A blank
+    /// line or a line back at the left margin closes it.
+    InBlock,
+    /// Inside brackets or a block comment, which only their closing token
+    /// ends.
+    Open,
+}
+
+/// Turns source text into tokens in one pass. The cursor is a byte offset
+/// into the input, so each step is constant time however long the input.
 pub struct Lexer<'a> {
     input: &'a str,
-    position: usize,
+    // Byte offset of `current_char`, also used for spans
+    offset: usize,
     current_char: Option<char>,
-    line: usize,
-    column: usize,
     indent_stack: Vec<usize>,
+    nesting: usize,
+    // Comments read since the last token, for the next one
+    trivia: Vec<Comment>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
         let mut lexer = Lexer {
             input,
-            position: 0,
+            offset: 0,
             current_char: None,
-            line: 1,
-            column: 0,
             indent_stack: vec![0],
+            nesting: 0,
+            trivia: Vec::new(),
         };
         lexer.current_char = lexer.input.chars().next();
         lexer
     }
 
     fn advance(&mut self) {
-        if self.current_char == Some('\n') {
-            self.line = self.line + 1;
-            self.column = 0;
-        } else {
-            self.column = self.column + 1;
+        if let Some(ch) = self.current_char {
+            self.offset += ch.len_utf8();
         }
 
-        self.position = self.position + 1;
-        self.current_char = self.input.chars().nth(self.position);
-    }   
+        self.current_char = self.input[self.offset..].chars().next();
+    }
 
     fn peek(&self) -> Option<char> {
-        self.input.chars().nth(self.position + 1)
+        self.input[self.offset..].chars().nth(1)
     }
 
     fn skip_whitespace(&mut self) {
@@ -88,23 +229,74 @@ impl<'a> Lexer<'a> {
         }
     }
 
+    /// Whether a comment starts at the current character.
+    fn at_comment(&self) -> bool {
+        match self.current_char {
+            Some('#') => true,
+            Some('/') => matches!(self.peek(), Some('/') | Some('*')),
+            _ => false,
+        }
+    }
+
+    /// Reads a comment into the trivia for the next token. A line comment
+    /// stops before the newline, which still ends the statement.
+    fn read_comment(&mut self) -> Result<(), Diagnostic> {
+        let start = self.offset;
+        let mut text = String::new();
+
+        if self.current_char == Some('/') && self.peek() == Some('*') {
+            loop {
+                match self.current_char {
+                    Some('*') if self.peek() == Some('/') && text.len() > 1 => {
+                        text.push_str("*/");
+                        self.advance();
+                        self.advance();
+                        break;
+                    }
+                    Some(ch) => {
+                        text.push(ch);
+                        self.advance();
+                    }
+                    None => {
+                        return Err(Diagnostic::error(codes::UNTERMINATED_COMMENT, "Unterminated block comment")
+                            .with_primary(Span::new(start, start + 2), "this comment is never closed")
+                            .with_help("add a closing '*/'"));
+                    }
+                }
+            }
+        } else {
+            while let Some(ch) = self.current_char {
+                if ch == '\n' {
+                    break;
+                }
+                text.push(ch);
+                self.advance();
+            }
+            text.truncate(text.trim_end().len());
+        }
+
+        let span = Span::new(start, start + text.len());
+        self.trivia.push(Comment { text, span });
+        Ok(())
+    }
+
     fn read_number(&mut self) -> f64 {
-        let mut number = String::new();
+        let start = self.offset;
         
         while let Some(ch) = self.current_char {
             if ch.is_ascii_digit() || ch == '.' { //Check for ASCII Digit OR Decimal
-                number.push(ch);
                 self.advance(); 
             } else {
                 break;
             }
         }
         
-        number.parse().unwrap_or(0.0)
+        self.input[start..self.offset].parse().unwrap_or(0.0)
     }
 
-    fn read_string(&mut self) -> Result<String, String> {
+    fn read_string(&mut self) -> Result<String, Diagnostic> {
         let mut string = String::new();
+        let start = self.offset;
         self.advance(); // Go over the Starting Quote
         
         while let Some(ch) = self.current_char {
@@ -113,6 +305,7 @@ impl<'a> Lexer<'a> {
                 return Ok(string);
             }
             if ch == '\\' {
+                let escape_start = self.offset;
                 self.advance();
                 match self.current_char {
                     Some('n') => string.push('\n'),
@@ -120,7 +313,12 @@ impl<'a> Lexer<'a> {
                     Some('r') => string.push('\r'),
                     Some('\\') => string.push('\\'),
                     Some('"') => string.push('"'),
-                    _ => return Err("Invalid escape sequence".to_string()),
+                    _ => {
+                        let span = Span::new(escape_start, self.offset + self.current_char.map_or(0, char::len_utf8));
+                        return Err(Diagnostic::error(codes::INVALID_ESCAPE, "Invalid escape sequence")
+                            .with_primary(span, "unknown escape")
+                            .with_help("the escapes are \\n, \\t, \\r, \\\\ and \\\""));
+                    }
                 }
             } else {
                 string.push(ch);
@@ -128,22 +326,25 @@ impl<'a> Lexer<'a> {
             self.advance();
         }
         
-        Err("Unterminated string".to_string())
+        Err(Diagnostic::error(codes::UNTERMINATED_STRING, "Unterminated string")
+            .with_primary(Span::new(start, start + 1), "this string is never closed")
+            .with_help("add a closing '\"'"))
     }
 
-    fn read_identifier(&mut self) -> String {
-        let mut identifier = String::new();
+    /// Reads a word, borrowed from the input so that trying a keyword and
+    /// backing out again costs no allocation.
+    fn read_identifier(&mut self) -> &'a str {
+        let start = self.offset;
 
         while let Some(ch) = self.current_char {
             if ch.is_alphanumeric() || ch == '_' {
-                identifier.push(ch);
                 self.advance();
             } else {
                 break;
             }
         }
 
-        identifier
+        &self.input[start..self.offset]
     }
     /*
     fn read_multi_word_token(&mut self) -> String {
@@ -172,25 +373,20 @@ impl<'a> Lexer<'a> {
     */
 
     fn try_match_keyword(&mut self, keyword: &str) -> bool {
-        let saved_position = self.position;
+        // The cursor is just these two, so backing out is restoring them
+        let saved_offset = self.offset;
         let saved_char = self.current_char;
-        let saved_line = self.line;
-        let saved_column = self.column;
-
-        let words: Vec<&str> = keyword.split(' ').collect();
 
-        for (i, word) in words.iter().enumerate() {
-            if (i > 0) {
+        for (i, word) in keyword.split(' ').enumerate() {
+            if i > 0 {
                 self.skip_whitespace();
             }
             
             let read_word = self.read_identifier();
 
-            if read_word != *word {
-                self.position = saved_position;
+            if read_word != word {
+                self.offset = saved_offset;
                 self.current_char = saved_char;
-                self.line = saved_line;
-                self.column = saved_column;
 
                 return false;
             }
@@ -208,17 +404,27 @@ impl<'a> Lexer<'a> {
             ("is less than", Token::IsLessThan),
             ("is not equal", Token::IsNotEqual),
             ("is equal", Token::IsEqual),
+            ("is not", Token::IsNotEqual),
+            ("length of", Token::LengthOf),
+            ("first of", Token::FirstOf),
+            ("last of", Token::LastOf),
         ];
         
+        // Only keywords that start with the word here can match, which
+        // spares reading most words ten times over
+        let first = self.input[self.offset..]
+            .split(|ch: char| !ch.is_alphanumeric() && ch != '_')
+            .next()
+            .unwrap_or_default();
         for (keyword, token) in keywords.iter() {
-            if self.try_match_keyword(keyword) {
+            if keyword.split(' ').next() == Some(first) && self.try_match_keyword(keyword) {
                 return token.clone();
             }
         }
         
         // Try single-word keywords
         let word = self.read_identifier();
-        match word.as_str() {
+        match word {
             "let" => Token::Let,
             "be" => Token::Be,
             "when" => Token::When,
@@ -228,100 +434,360 @@ impl<'a> Lexer<'a> {
             "define" => Token::Define,
             "with" => Token::With,
             "end" => Token::End,
-            _ => Token::Identifier(word),
+            "return" => Token::Return,
+            "repeat" => Token::Repeat,
+            "while" => Token::While,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
+            "for" => Token::For,
+            "each" => Token::Each,
+            "from" => Token::From,
+            "to" => Token::To,
+            "by" => Token::By,
+            "in" => Token::In,
+            "add" => Token::Add,
+            "remove" => Token::Remove,
+            "is" => Token::IsEqual,
+            "and" => Token::And,
+            "or" => Token::Or,
+            "not" => Token::Not,
+            "true" => Token::True,
+            "false" => Token::False,
+            "nothing" => Token::Nothing,
+            _ => Token::Identifier(word.to_string()),
         }
     }
 
     // The below two functions are AI Generated.
 
-    fn handle_newline_and_indentation(&mut self) -> Vec<Token> {
-        let mut tokens = vec![Token::Newline];
+    fn handle_newline_and_indentation(&mut self) -> Result<Vec<SpannedToken>, Diagnostic> {
+        let newline_start = self.offset;
         self.advance(); // Skip the newline
+        let mut tokens = vec![self.spanned(Token::Newline, newline_start)];
         
-        // Count indentation
-        let mut indent_level = 0;
-        while let Some(ch) = self.current_char {
-            if ch == ' ' {
-                indent_level += 1;
-                self.advance();
-            } else if ch == '\t' {
-                indent_level += 4; // Treat tab as 4 spaces
-                self.advance();
-            } else {
-                break;
+        // Count indentation. Blank lines and lines with only comments on
+        // them are passed over, so they can be indented any way at all.
+        let (indent, indent_level) = loop {
+            let indent_start = self.offset;
+            let mut indent_level = 0;
+            while let Some(ch) = self.current_char {
+                if ch == ' ' {
+                    indent_level += 1;
+                    self.advance();
+                } else if ch == '\t' {
+                    indent_level += 4; // Treat tab as 4 spaces
+                    self.advance();
+                } else {
+                    break;
+                }
             }
-        }
+            let indent = Span::new(indent_start, self.offset);
+
+            while self.at_comment() {
+                self.read_comment()?;
+                self.skip_whitespace();
+            }
+            match self.current_char {
+                Some('\n') => self.advance(),
+                // The end of the input closes every block; `tokenize` does that
+                None => return Ok(tokens),
+                Some(_) => break (indent, indent_level),
+            }
+        };
         
         let current_indent = *self.indent_stack.last().unwrap();
         
         if indent_level > current_indent {
             self.indent_stack.push(indent_level);
-            tokens.push(Token::Indent);
+            // Comments before the line are left for its first token
+            tokens.push(SpannedToken { token: Token::Indent, span: indent, comments: Vec::new() });
         } else if indent_level < current_indent {
             while let Some(&last_indent) = self.indent_stack.last() {
                 if last_indent <= indent_level {
                     break;
                 }
                 self.indent_stack.pop();
-                tokens.push(Token::Dedent);
+                // Dedents take no space; they sit where the next line starts
+                tokens.push(self.spanned(Token::Dedent, self.offset));
             }
         }
         
-        tokens
+        Ok(tokens)
+    }
+
+    /// Wraps a token that started at `start` and ends at the current
+    /// position. It takes the comments read since the last token, unless it
+    /// is a dedent, which leaves them for the token it comes before.
+    fn spanned(&mut self, token: Token, start: usize) -> SpannedToken {
+        let comments = match token {
+            Token::Dedent => Vec::new(),
+            _ => std::mem::take(&mut self.trivia),
+        };
+        SpannedToken {
+            token,
+            span: Span::new(start, self.offset),
+            comments,
+        }
+    }
+
+    /// Consumes a one-character token.
+    fn single(&mut self, token: Token) -> SpannedToken {
+        let start = self.offset;
+        self.advance();
+        self.spanned(token, start)
     }
     
-    pub fn tokenize(&mut self) -> Result<Vec<Token>, String> {
+    pub fn tokenize(&mut self) -> Result<Vec<SpannedToken>, Diagnostic> {
+        let mut tokens = self.scan()?;
+
+        // Add final dedents for any remaining indentation
+        while self.indent_stack.len() > 1 {
+            self.indent_stack.pop();
+            tokens.push(self.spanned(Token::Dedent, self.offset));
+        }
+        
+        tokens.push(self.spanned(Token::Eof, self.offset));
+        Ok(tokens)
+    }
+
+    /// Reads every token of the input, leaving open blocks and brackets as
+    /// they are at the end of it.
+    fn scan(&mut self) -> Result<Vec<SpannedToken>, Diagnostic> {
         let mut tokens = Vec::new();
         
         while let Some(ch) = self.current_char {
+            let start = self.offset;
             match ch {
                 ' ' | '\t' => self.skip_whitespace(),
+                // Line breaks inside brackets continue the expression.
+                '\n' if self.nesting > 0 => self.advance(),
                 '\n' => {
-                    let newline_tokens = self.handle_newline_and_indentation();
+                    let newline_tokens = self.handle_newline_and_indentation()?;
                     tokens.extend(newline_tokens);
                 }
+                '#' | '/' if self.at_comment() => self.read_comment()?,
                 '"' => {
                     let string = self.read_string()?;
-                    tokens.push(Token::String(string));
+                    tokens.push(self.spanned(Token::String(string), start));
                 }
                 '0'..='9' => {
                     let number = self.read_number();
-                    tokens.push(Token::Number(number));
+                    tokens.push(self.spanned(Token::Number(number), start));
                 }
                 'a'..='z' | 'A'..='Z' | '_' => {
                     let token = self.keyword_or_identifier();
-                    tokens.push(token);
+                    tokens.push(self.spanned(token, start));
                 }
-                '+' => {
-                    tokens.push(Token::Plus);
-                    self.advance();
+                '+' => tokens.push(self.single(Token::Plus)),
+                '-' => tokens.push(self.single(Token::Minus)),
+                '*' => tokens.push(self.single(Token::Multiply)),
+                '/' => tokens.push(self.single(Token::Divide)),
+                ',' => tokens.push(self.single(Token::Comma)),
+                '(' => {
+                    tokens.push(self.single(Token::LeftParen));
+                    self.nesting += 1;
                 }
-                '-' => {
-                    tokens.push(Token::Minus);
-                    self.advance();
+                ')' => {
+                    tokens.push(self.single(Token::RightParen));
+                    self.nesting = self.nesting.saturating_sub(1);
                 }
-                '*' => {
-                    tokens.push(Token::Multiply);
-                    self.advance();
+                '[' => {
+                    tokens.push(self.single(Token::LeftBracket));
+                    self.nesting += 1;
                 }
-                '/' => {
-                    tokens.push(Token::Divide);
-                    self.advance();
+                ']' => {
+                    tokens.push(self.single(Token::RightBracket));
+                    self.nesting = self.nesting.saturating_sub(1);
                 }
+                '{' => {
+                    tokens.push(self.single(Token::LeftBrace));
+                    self.nesting += 1;
+                }
+                '}' => {
+                    tokens.push(self.single(Token::RightBrace));
+                    self.nesting = self.nesting.saturating_sub(1);
+                }
+                ':' => tokens.push(self.single(Token::Colon)),
+                '.' => tokens.push(self.single(Token::Dot)),
                 _ => {
-                    return Err(format!("Unexpected character: '{}' at line {}, column {}", 
-                                     ch, self.line, self.column));
+                    let span = Span::new(start, start + ch.len_utf8());
+                    return Err(Diagnostic::error(codes::UNEXPECTED_CHARACTER, format!("Unexpected character: '{}'", ch))
+                        .with_primary(span, "not part of the language"));
                 }
             }
         }
-        
-        // Add final dedents for any remaining indentation
-        while self.indent_stack.len() > 1 {
-            self.indent_stack.pop();
-            tokens.push(Token::Dedent);
-        }
-        
-        tokens.push(Token::Eof);
+
         Ok(tokens)
     }
-}
\ No newline at end of file
+
+    /// How `input` ends: complete, or partway through something that more
+    /// lines would finish. Errors other than an unclosed block comment are
+    /// left for `tokenize` to report, so input with them counts as complete.
+    pub fn ending(input: &str) -> Ending {
+        let mut lexer = Lexer::new(input);
+        let tokens = match lexer.scan() {
+            Ok(tokens) => tokens,
+            Err(error) if error.code == codes::UNTERMINATED_COMMENT => return Ending::Open,
+            Err(_) => return Ending::Complete,
+        };
+        if lexer.nesting > 0 {
+            return Ending::Open;
+        }
+        if lexer.indent_stack.len() > 1 {
+            return Ending::InBlock;
+        }
+
+        // The last line with tokens on it, which may open a block
+        let end = tokens
+            .iter()
+            .rposition(|spanned| {
+                !matches!(spanned.token, Token::Newline | Token::Indent | Token::Dedent)
+            })
+            .map_or(0, |last| last + 1);
+        let line_start = tokens[..end]
+            .iter()
+            .rposition(|spanned| matches!(spanned.token, Token::Newline))
+            .map_or(0, |newline| newline + 1);
+        let line: Vec<&Token> = tokens[line_start..end]
+            .iter()
+            .map(|spanned| &spanned.token)
+            .filter(|token| !matches!(token, Token::Indent | Token::Dedent))
+            .collect();
+        let opens_block = matches!(
+            line.first(),
+            Some(Token::When | Token::Define | Token::Repeat | Token::Otherwise)
+        ) || matches!(line.last(), Some(Token::Then));
+        if opens_block { Ending::InBlock } else { Ending::Complete }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> Vec<Token> {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        tokens.into_iter().map(|spanned| spanned.token).collect()
+    }
+
+    fn name(name: &str) -> Token {
+        Token::Identifier(name.to_string())
+    }
+
+    #[test]
+    fn the_longest_multi_word_keyword_wins() {
+        assert_eq!(
+            tokens("a is greater than or equal b"),
+            [
+                name("a"),
+                Token::IsGreaterThanOrEqual,
+                name("b"),
+                Token::Eof
+            ]
+        );
+        assert_eq!(
+            tokens("a is  greater\tthan b or c"),
+            [
+                name("a"),
+                Token::IsGreaterThan,
+                name("b"),
+                Token::Or,
+                name("c"),
+                Token::Eof
+            ]
+        );
+        assert_eq!(
+            tokens("a is not equal b"),
+            [name("a"), Token::IsNotEqual, name("b"), Token::Eof]
+        );
+    }
+
+    #[test]
+    fn a_partial_keyword_backs_out_to_single_words() {
+        // `is not` followed by a name, not by `equal`
+        assert_eq!(
+            tokens("a is not equality"),
+            [name("a"), Token::IsNotEqual, name("equality"), Token::Eof]
+        );
+        assert_eq!(
+            tokens("a is greater b"),
+            [
+                name("a"),
+                Token::IsEqual,
+                name("greater"),
+                name("b"),
+                Token::Eof
+            ]
+        );
+        assert_eq!(
+            tokens("show length off lengthy"),
+            [
+                Token::Show,
+                name("length"),
+                name("off"),
+                name("lengthy"),
+                Token::Eof
+            ]
+        );
+        assert_eq!(
+            tokens("a is less thanks"),
+            [
+                name("a"),
+                Token::IsEqual,
+                name("less"),
+                name("thanks"),
+                Token::Eof
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_are_byte_offsets_past_non_ascii_text() {
+        let source = "show \"héllo → wörld\" /* ünïcode */ is not size\nlet u be \"ü\"";
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let spelled: Vec<&str> = tokens
+            .iter()
+            .map(|spanned| &source[spanned.span.start..spanned.span.end])
+            .collect();
+        assert_eq!(
+            spelled,
+            [
+                "show",
+                "\"héllo → wörld\"",
+                "is not",
+                "size",
+                "\n",
+                "let",
+                "u",
+                "be",
+                "\"ü\"",
+                ""
+            ]
+        );
+        assert_eq!(tokens[1].token, Token::String("héllo → wörld".to_string()));
+        assert_eq!(tokens[2].comments[0].text, "/* ünïcode */");
+        assert_eq!(tokens[3].token, name("size"));
+    }
+
+    #[test]
+    fn input_ends_open_until_its_brackets_and_blocks_close() {
+        let cases = [
+            ("let x be 1", Ending::Complete),
+            ("let xs be [", Ending::Open),
+            ("let xs be [\n    1,\n", Ending::Open),
+            ("let xs be [\n    1,\n]", Ending::Complete),
+            ("let o be {a: 1,\nb: 2}", Ending::Complete),
+            ("/* a comment", Ending::Open),
+            ("when x then", Ending::InBlock),
+            ("when x then # why", Ending::InBlock),
+            ("when x then\n    show 1", Ending::InBlock),
+            ("when x then\n    show 1\notherwise", Ending::InBlock),
+            ("repeat while x is 1", Ending::InBlock),
+            ("define f\n    show 1\nend", Ending::Complete),
+            // Reported when the entry is lexed for real
+            ("show \"unclosed", Ending::Complete),
+        ];
+        for (input, ending) in cases {
+            assert_eq!(Lexer::ending(input), ending, "{:?}", input);
+        }
+    }
+}
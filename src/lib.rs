@@ -0,0 +1,8 @@
+//! The parts of Delta that do not need LLVM: turning source text into
+//! tokens, the byte spans that point into it, and the diagnostics reported
+//! about it. The `delta` binary builds the rest of the compiler on them, and
+//! the lexer benchmark uses them on their own.
+
+pub mod diagnostics;
+pub mod lexer;
+pub mod span;
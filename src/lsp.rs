@@ -0,0 +1,788 @@
+//! `delta lsp`: a language server for editors, speaking the Language Server
+//! Protocol over stdin and stdout.
+//!
+//! Documents are synced whole. Every change lexes and parses the document
+//! again, publishes the lexer's and parser's diagnostics, and rebuilds the
+//! symbol index that definitions, references, completion and document
+//! symbols are answered from. Hover needs types, which only the compiler
+//! works out, so the first hover after a change compiles the document with
+//! type recording on.
+//!
+//! `serve` works on any reader and writer, so the tests drive it in memory,
+//! and `scripts/lsp-client.py` runs a scripted session through pipes to
+//! show what an editor would receive.
+
+mod analysis;
+mod protocol;
+
+use crate::ast::Program;
+use crate::codegen::CodeGenerator;
+use crate::formatter;
+use crate::parser::Parser;
+use analysis::{Index, SymbolKind};
+use delta::diagnostics::{Diagnostic, Label, Severity};
+use delta::lexer::{self, Comment, Lexer};
+use delta::span::Span;
+use inkwell::context::Context;
+use protocol::{Encoding, LineIndex};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+/// Offered by completion wherever the cursor is.
+const KEYWORDS: &[&str] = &[
+    "let",
+    "be",
+    "when",
+    "then",
+    "otherwise",
+    "show",
+    "define",
+    "with",
+    "end",
+    "return",
+    "repeat",
+    "while",
+    "for each",
+    "from",
+    "to",
+    "by",
+    "in",
+    "add",
+    "remove",
+    "break",
+    "continue",
+    "and",
+    "or",
+    "not",
+    "true",
+    "false",
+    "nothing",
+    "is",
+    "is not",
+    "is equal",
+    "is not equal",
+    "is greater than",
+    "is less than",
+    "is greater than or equal",
+    "is less than or equal",
+    "length of",
+    "first of",
+    "last of",
+];
+
+// JSON-RPC and LSP error codes
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const SERVER_NOT_INITIALIZED: i64 = -32002;
+const REQUEST_FAILED: i64 = -32803;
+
+// LSP's numbers for kinds of symbols and completions
+const SYMBOL_FUNCTION: u32 = 12;
+const SYMBOL_VARIABLE: u32 = 13;
+const COMPLETION_FUNCTION: u32 = 3;
+const COMPLETION_VARIABLE: u32 = 6;
+const COMPLETION_KEYWORD: u32 = 14;
+
+/// The result of a request, or its error code and message.
+type Response = Result<Value, (i64, String)>;
+
+/// An open document and what is known about its last version.
+struct Document {
+    text: String,
+    lines: LineIndex,
+    // `None` when the lexer failed, as there is nothing to parse then
+    program: Option<Program>,
+    comments: Vec<Comment>,
+    diagnostics: Vec<Diagnostic>,
+    index: Index,
+    // Filled in by the first hover
+    types: Option<Vec<(Span, String)>>,
+}
+
+impl Document {
+    fn new(text: String, encoding: Encoding) -> Self {
+        let (program, comments, diagnostics) = match Lexer::new(&text).tokenize() {
+            Ok(tokens) => {
+                let comments = lexer::comments(&tokens);
+                let mut parser = Parser::new(tokens);
+                let program = parser.parse();
+                (Some(program), comments, parser.take_errors())
+            }
+            Err(err) => (None, Vec::new(), vec![err]),
+        };
+        let index = program
+            .as_ref()
+            .map(|program| Index::build(program, &text))
+            .unwrap_or_default();
+        Document {
+            lines: LineIndex::new(&text, encoding),
+            text,
+            program,
+            comments,
+            diagnostics,
+            index,
+            types: None,
+        }
+    }
+
+    fn range(&self, span: Span) -> Value {
+        self.lines.range(&self.text, span)
+    }
+
+    fn location(&self, uri: &str, span: Span) -> Value {
+        json!({ "uri": uri, "range": self.range(span) })
+    }
+
+    /// The types the compiler found for the smallest recorded span that
+    /// covers `span`, and that span.
+    fn types_at(&mut self, span: Span) -> Option<(Span, Vec<String>)> {
+        let program = self.program.as_ref()?;
+        let types = self.types.get_or_insert_with(|| compile_types(program));
+
+        let covering = types
+            .iter()
+            .map(|(recorded, _)| *recorded)
+            .filter(|recorded| recorded.start <= span.start && span.end <= recorded.end)
+            .min_by_key(|recorded| recorded.end - recorded.start)?;
+        let mut found = Vec::new();
+        for (recorded, description) in types.iter() {
+            if *recorded == covering && !found.contains(description) {
+                found.push(description.clone());
+            }
+        }
+        Some((covering, found))
+    }
+
+    fn to_lsp(&self, uri: &str, diagnostic: &Diagnostic) -> Value {
+        let primary = diagnostic.labels.iter().find(|label| label.primary);
+        let mut message = diagnostic.message.clone();
+        // The label often repeats the message, as in "Expected an expression,
+        // found end of line" over "expected an expression"
+        let repeats = |label: &&Label| {
+            message
+                .to_lowercase()
+                .contains(&label.message.to_lowercase())
+        };
+        if let Some(label) = primary.filter(|label| !repeats(label)) {
+            message += &format!(": {}", label.message);
+        }
+        for note in &diagnostic.notes {
+            message += &format!("\nnote: {}", note);
+        }
+        if let Some(help) = &diagnostic.help {
+            message += &format!("\nhelp: {}", help);
+        }
+
+        let related: Vec<Value> = diagnostic
+            .labels
+            .iter()
+            .filter(|label| !label.primary)
+            .map(|label| json!({ "location": self.location(uri, label.span), "message": label.message }))
+            .collect();
+        json!({
+            "range": self.range(primary.map_or(Span::default(), |label| label.span)),
+            "severity": match diagnostic.severity {
+                Severity::Error => 1,
+                Severity::Warning => 2,
+            },
+            "code": diagnostic.code.id(),
+            "source": "delta",
+            "message": message,
+            "relatedInformation": related,
+        })
+    }
+}
+
+/// Compiles `program` only to learn the types of its expressions and
+/// bindings. Code after an error is not compiled, so has no types.
+fn compile_types(program: &Program) -> Vec<(Span, String)> {
+    let context = Context::create();
+    let Ok(mut codegen) = CodeGenerator::new(&context, "delta_lsp") else {
+        return Vec::new();
+    };
+    codegen.record_types();
+    let _ = codegen.compile(program);
+    codegen.take_types()
+}
+
+struct Server<W> {
+    output: W,
+    documents: HashMap<String, Document>,
+    encoding: Encoding,
+    initialized: bool,
+    shut_down: bool,
+}
+
+/// Answers messages from `input` on `output` until the client sends `exit`
+/// or closes the input. Returns whether the client asked the server to shut
+/// down first, as it should.
+pub fn serve(mut input: impl BufRead, output: impl Write) -> io::Result<bool> {
+    let mut server = Server {
+        output,
+        documents: HashMap::new(),
+        encoding: Encoding::Utf16,
+        initialized: false,
+        shut_down: false,
+    };
+
+    while let Some(body) = protocol::read_message(&mut input)? {
+        let message: Value = match serde_json::from_str(&body) {
+            Ok(message) => message,
+            Err(err) => {
+                let error = format!("Invalid JSON: {}", err);
+                server.respond(Value::Null, Err((PARSE_ERROR, error)))?;
+                continue;
+            }
+        };
+        if message["method"] == "exit" {
+            return Ok(server.shut_down);
+        }
+        server.handle(&message)?;
+    }
+    Ok(false)
+}
+
+impl<W: Write> Server<W> {
+    fn send(&mut self, message: Value) -> io::Result<()> {
+        protocol::write_message(&mut self.output, &message)
+    }
+
+    fn respond(&mut self, id: Value, response: Response) -> io::Result<()> {
+        let message = match response {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        self.send(message)
+    }
+
+    fn handle(&mut self, message: &Value) -> io::Result<()> {
+        let Some(method) = message["method"].as_str() else {
+            // A response, but the server never sends requests
+            return Ok(());
+        };
+        let params = &message["params"];
+
+        let Some(id) = message.get("id").cloned() else {
+            return self.notification(method, params);
+        };
+        let response = if !self.initialized && method != "initialize" {
+            Err((SERVER_NOT_INITIALIZED, "Not initialized yet".to_string()))
+        } else if self.shut_down {
+            Err((INVALID_REQUEST, "The server has shut down".to_string()))
+        } else {
+            self.request(method, params)
+        };
+        self.respond(id, response)
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> io::Result<()> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        match method {
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.update(uri, text.to_string())
+            }
+            "textDocument/didChange" => {
+                // Full sync: the last change holds the whole text
+                let changes = params["contentChanges"].as_array();
+                match changes.and_then(|changes| changes.last()) {
+                    Some(change) => {
+                        let text = change["text"].as_str().unwrap_or_default();
+                        self.update(uri, text.to_string())
+                    }
+                    None => Ok(()),
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish(&uri, Vec::new())
+            }
+            // `initialized`, `$/cancelRequest`, `textDocument/didSave` and
+            // the rest need nothing done
+            _ => Ok(()),
+        }
+    }
+
+    fn update(&mut self, uri: String, text: String) -> io::Result<()> {
+        let document = Document::new(text, self.encoding);
+        let diagnostics = document
+            .diagnostics
+            .iter()
+            .map(|diagnostic| document.to_lsp(&uri, diagnostic))
+            .collect();
+        self.documents.insert(uri.clone(), document);
+        self.publish(&uri, diagnostics)
+    }
+
+    fn publish(&mut self, uri: &str, diagnostics: Vec<Value>) -> io::Result<()> {
+        self.send(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": { "uri": uri, "diagnostics": diagnostics },
+        }))
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Response {
+        match method {
+            "initialize" => Ok(self.initialize(params)),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/definition" => self.definition(params),
+            "textDocument/references" => self.references(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/completion" => self.completion(params),
+            "textDocument/documentSymbol" => self.document_symbols(params),
+            "textDocument/formatting" => self.formatting(params),
+            _ => Err((METHOD_NOT_FOUND, format!("Unsupported method '{}'", method))),
+        }
+    }
+
+    fn initialize(&mut self, params: &Value) -> Value {
+        // UTF-8 positions are exact byte offsets, so they are preferred when
+        // the client offers them
+        let offered = params["capabilities"]["general"]["positionEncodings"].as_array();
+        if offered.is_some_and(|encodings| encodings.iter().any(|encoding| encoding == "utf-8")) {
+            self.encoding = Encoding::Utf8;
+        }
+        self.initialized = true;
+
+        json!({
+            "capabilities": {
+                "positionEncoding": self.encoding.name(),
+                "textDocumentSync": { "openClose": true, "change": 1 },
+                "definitionProvider": true,
+                "referencesProvider": true,
+                "hoverProvider": true,
+                "completionProvider": {},
+                "documentSymbolProvider": true,
+                "documentFormattingProvider": true,
+            },
+            "serverInfo": { "name": "delta", "version": env!("CARGO_PKG_VERSION") },
+        })
+    }
+
+    /// The open document that `params` names, and its URI.
+    fn document(&self, params: &Value) -> Result<(String, &Document), (i64, String)> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match self.documents.get(uri) {
+            Some(document) => Ok((uri.to_string(), document)),
+            None => Err((INVALID_PARAMS, format!("'{}' is not open", uri))),
+        }
+    }
+
+    /// The byte offset of the position in `params`.
+    fn offset(document: &Document, params: &Value) -> usize {
+        document.lines.offset(&document.text, &params["position"])
+    }
+
+    fn definition(&self, params: &Value) -> Response {
+        let (uri, document) = self.document(params)?;
+        let offset = Self::offset(document, params);
+        Ok(match document.index.symbol_at(offset) {
+            Some((id, _)) => document.location(&uri, document.index.symbols[id].definition),
+            None => Value::Null,
+        })
+    }
+
+    fn references(&self, params: &Value) -> Response {
+        let (uri, document) = self.document(params)?;
+        let offset = Self::offset(document, params);
+        let Some((id, _)) = document.index.symbol_at(offset) else {
+            return Ok(Value::Null);
+        };
+
+        let symbol = &document.index.symbols[id];
+        let include_declaration = params["context"]["includeDeclaration"].as_bool() == Some(true);
+        let locations: Vec<Value> = symbol
+            .occurrences()
+            .into_iter()
+            .filter(|span| include_declaration || *span != symbol.definition)
+            .map(|span| document.location(&uri, span))
+            .collect();
+        Ok(json!(locations))
+    }
+
+    fn hover(&mut self, params: &Value) -> Response {
+        let (uri, document) = self.document(params)?;
+        let offset = Self::offset(document, params);
+        let document = self
+            .documents
+            .get_mut(&uri)
+            .expect("the document was just found");
+
+        let (contents, span) = match document.index.symbol_at(offset) {
+            Some((id, span)) => {
+                let symbol = document.index.symbols[id].clone();
+                match symbol.kind {
+                    SymbolKind::Function => {
+                        let parameters: Vec<&str> = document
+                            .index
+                            .symbols
+                            .iter()
+                            .filter(|other| {
+                                other.function == Some(id) && other.kind == SymbolKind::Parameter
+                            })
+                            .map(|parameter| parameter.name.as_str())
+                            .collect();
+                        let mut contents = code_block(&function_header(&symbol.name, &parameters));
+                        // Signatures are noted on the name in the `define`
+                        match document.types_at(symbol.definition) {
+                            Some((recorded, signatures)) if recorded == symbol.definition => {
+                                contents += "\nCompiled as:\n";
+                                for signature in signatures {
+                                    contents += &format!("- `{}`\n", signature);
+                                }
+                            }
+                            _ => {
+                                contents += "\nIts types are not known until the program compiles."
+                            }
+                        }
+                        (contents, span)
+                    }
+                    SymbolKind::Variable | SymbolKind::Parameter => {
+                        let text = match document.types_at(span) {
+                            Some((_, types)) => format!("{}: {}", symbol.name, types.join(" or ")),
+                            None => symbol.name.clone(),
+                        };
+                        (code_block(&text), span)
+                    }
+                }
+            }
+            // Anything else shows the type of the innermost expression
+            None => match document.types_at(Span::new(offset, offset)) {
+                Some((recorded, types)) => (code_block(&types.join(" or ")), recorded),
+                None => return Ok(Value::Null),
+            },
+        };
+
+        Ok(json!({
+            "contents": { "kind": "markdown", "value": contents },
+            "range": document.range(span),
+        }))
+    }
+
+    fn completion(&self, params: &Value) -> Response {
+        let (_, document) = self.document(params)?;
+        let offset = Self::offset(document, params);
+
+        let mut items: Vec<Value> = KEYWORDS
+            .iter()
+            .map(|keyword| json!({ "label": keyword, "kind": COMPLETION_KEYWORD }))
+            .collect();
+        let mut seen = Vec::new();
+        for symbol in document.index.visible_at(offset) {
+            if seen.contains(&&symbol.name) {
+                continue;
+            }
+            seen.push(&symbol.name);
+            let (kind, detail) = match symbol.kind {
+                SymbolKind::Function => (COMPLETION_FUNCTION, "function"),
+                SymbolKind::Parameter => (COMPLETION_VARIABLE, "parameter"),
+                SymbolKind::Variable => (COMPLETION_VARIABLE, "variable"),
+            };
+            items.push(json!({ "label": symbol.name, "kind": kind, "detail": detail }));
+        }
+        Ok(json!(items))
+    }
+
+    fn document_symbols(&self, params: &Value) -> Response {
+        let (_, document) = self.document(params)?;
+        let symbols = &document.index.symbols;
+        let variable = |symbol: &analysis::Symbol| {
+            json!({
+                "name": symbol.name,
+                "kind": SYMBOL_VARIABLE,
+                "range": document.range(symbol.definition),
+                "selectionRange": document.range(symbol.definition),
+            })
+        };
+
+        let mut outline: Vec<(usize, Value)> = Vec::new();
+        for (id, symbol) in symbols.iter().enumerate() {
+            match symbol.kind {
+                SymbolKind::Function => {
+                    let locals: Vec<&analysis::Symbol> = symbols
+                        .iter()
+                        .filter(|other| other.function == Some(id))
+                        .collect();
+                    let parameters: Vec<&str> = locals
+                        .iter()
+                        .filter(|local| local.kind == SymbolKind::Parameter)
+                        .map(|parameter| parameter.name.as_str())
+                        .collect();
+                    let entry = json!({
+                        "name": symbol.name,
+                        "detail": function_header(&symbol.name, &parameters),
+                        "kind": SYMBOL_FUNCTION,
+                        "range": document.range(symbol.extent),
+                        "selectionRange": document.range(symbol.definition),
+                        "children": locals.iter().map(|local| variable(local)).collect::<Vec<_>>(),
+                    });
+                    outline.push((symbol.extent.start, entry));
+                }
+                _ if symbol.function.is_none() => {
+                    outline.push((symbol.definition.start, variable(symbol)));
+                }
+                _ => {}
+            }
+        }
+        outline.sort_by_key(|(start, _)| *start);
+        Ok(json!(
+            outline
+                .into_iter()
+                .map(|(_, entry)| entry)
+                .collect::<Vec<_>>()
+        ))
+    }
+
+    fn formatting(&self, params: &Value) -> Response {
+        let (_, document) = self.document(params)?;
+        let program = match &document.program {
+            Some(program) if document.diagnostics.is_empty() => program,
+            _ => {
+                return Err((
+                    REQUEST_FAILED,
+                    "Cannot format a document with syntax errors".to_string(),
+                ));
+            }
+        };
+
+        let formatted = formatter::format_program(program, &document.comments, &document.text);
+        if formatted == document.text {
+            return Ok(json!([]));
+        }
+        let whole = Span::new(0, document.text.len());
+        Ok(json!([{ "range": document.range(whole), "newText": formatted }]))
+    }
+}
+
+/// `define name with a, b`, as a function's header is written.
+fn function_header(name: &str, parameters: &[&str]) -> String {
+    if parameters.is_empty() {
+        format!("define {}", name)
+    } else {
+        format!("define {} with {}", name, parameters.join(", "))
+    }
+}
+
+fn code_block(code: &str) -> String {
+    format!("```delta\n{}\n```\n", code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const URI: &str = "file:///tmp/session.de";
+
+    const SOURCE: &str = "\
+define square with n
+    return n * n
+end
+
+let total be 0
+repeat for each i from 1 to 3
+    let total be total + square i
+show total
+";
+
+    /// Sends `messages` to a server, then `shutdown` and `exit`, and
+    /// returns every message it sent back.
+    fn session(messages: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        let initialize =
+            json!({ "id": 0, "method": "initialize", "params": { "capabilities": {} } });
+        let shutdown = json!({ "id": "shutdown", "method": "shutdown" });
+        let exit = json!({ "method": "exit" });
+        let all = std::iter::once(&initialize)
+            .chain(messages)
+            .chain([&shutdown, &exit]);
+        for message in all {
+            protocol::write_message(&mut input, message).unwrap();
+        }
+
+        let mut output = Vec::new();
+        assert!(serve(Cursor::new(input), &mut output).unwrap());
+        let mut output = Cursor::new(output);
+        let mut sent = Vec::new();
+        while let Some(body) = protocol::read_message(&mut output).unwrap() {
+            sent.push(serde_json::from_str(&body).unwrap());
+        }
+        sent
+    }
+
+    fn open(text: &str) -> Value {
+        json!({
+            "method": "textDocument/didOpen",
+            "params": { "textDocument": { "uri": URI, "languageId": "delta", "version": 1, "text": text } },
+        })
+    }
+
+    /// A request about the document at `line` and `character`.
+    fn request(id: u32, method: &str, line: u32, character: u32) -> Value {
+        json!({
+            "id": id,
+            "method": method,
+            "params": {
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character },
+                "context": { "includeDeclaration": true },
+            },
+        })
+    }
+
+    /// The result of the request with `id`.
+    fn result(sent: &[Value], id: u32) -> &Value {
+        let response = sent.iter().find(|message| message["id"] == id).unwrap();
+        assert!(response.get("error").is_none(), "{}", response);
+        &response["result"]
+    }
+
+    fn range(start: (u32, u32), end: (u32, u32)) -> Value {
+        json!({
+            "start": { "line": start.0, "character": start.1 },
+            "end": { "line": end.0, "character": end.1 },
+        })
+    }
+
+    #[test]
+    fn navigation_follows_the_symbols_in_the_document() {
+        let sent = session(&[
+            open(SOURCE),
+            request(1, "textDocument/definition", 6, 26),
+            request(2, "textDocument/references", 4, 5),
+            request(3, "textDocument/completion", 7, 5),
+            request(4, "textDocument/documentSymbol", 0, 0),
+        ]);
+
+        // `square` in the loop leads to its `define`
+        assert_eq!(
+            *result(&sent, 1),
+            json!({ "uri": URI, "range": range((0, 7), (0, 13)) })
+        );
+
+        // `total` is set twice and read twice
+        let references: Vec<&Value> = result(&sent, 2)
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|location| &location["range"])
+            .collect();
+        assert_eq!(
+            references,
+            [
+                &range((4, 4), (4, 9)),
+                &range((6, 8), (6, 13)),
+                &range((6, 17), (6, 22)),
+                &range((7, 5), (7, 10)),
+            ]
+        );
+
+        // Keywords, then the names in scope; the parameter `n` is not
+        let completions = result(&sent, 3).as_array().unwrap();
+        let labels: Vec<&str> = completions
+            .iter()
+            .map(|item| item["label"].as_str().unwrap())
+            .collect();
+        assert_eq!(labels.len(), KEYWORDS.len() + 2);
+        assert_eq!(&labels[..KEYWORDS.len()], KEYWORDS);
+        assert_eq!(
+            completions[KEYWORDS.len()..],
+            [
+                json!({ "label": "square", "kind": COMPLETION_FUNCTION, "detail": "function" }),
+                json!({ "label": "total", "kind": COMPLETION_VARIABLE, "detail": "variable" }),
+            ]
+        );
+
+        let outline = result(&sent, 4).as_array().unwrap();
+        let names: Vec<(&str, &Value)> = outline
+            .iter()
+            .map(|symbol| (symbol["name"].as_str().unwrap(), &symbol["kind"]))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("square", &json!(SYMBOL_FUNCTION)),
+                ("total", &json!(SYMBOL_VARIABLE)),
+                ("i", &json!(SYMBOL_VARIABLE)),
+            ]
+        );
+        assert_eq!(outline[0]["range"], range((0, 0), (2, 3)));
+        assert_eq!(outline[0]["detail"], "define square with n");
+        assert_eq!(outline[0]["children"][0]["name"], "n");
+    }
+
+    #[test]
+    fn hover_shows_what_the_compiler_inferred() {
+        let sent = session(&[
+            open(SOURCE),
+            request(1, "textDocument/hover", 0, 8),
+            request(2, "textDocument/hover", 7, 7),
+        ]);
+        assert_eq!(
+            result(&sent, 1)["contents"]["value"],
+            "```delta\ndefine square with n\n```\n\nCompiled as:\n- `square with n: number returns number`\n"
+        );
+        assert_eq!(result(&sent, 1)["range"], range((0, 7), (0, 13)));
+        assert_eq!(
+            result(&sent, 2)["contents"]["value"],
+            "```delta\ntotal: number\n```\n"
+        );
+    }
+
+    #[test]
+    fn syntax_errors_are_published_and_block_formatting() {
+        let change = |text: &str| {
+            json!({
+                "method": "textDocument/didChange",
+                "params": {
+                    "textDocument": { "uri": URI, "version": 2 },
+                    "contentChanges": [{ "text": text }],
+                },
+            })
+        };
+        let messy = "define square with n\n  return n*n\nlet total be square 3\n";
+        let sent = session(&[
+            open("let broken be\nshow 1\n"),
+            request(1, "textDocument/formatting", 0, 0),
+            change(messy),
+            request(2, "textDocument/formatting", 0, 0),
+        ]);
+
+        let published: Vec<&Value> = sent
+            .iter()
+            .filter(|message| message["method"] == "textDocument/publishDiagnostics")
+            .map(|message| &message["params"]["diagnostics"])
+            .collect();
+        assert_eq!(published.len(), 2);
+        let diagnostics = published[0].as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0]["code"], "E0004");
+        assert_eq!(diagnostics[0]["severity"], 1);
+        assert_eq!(diagnostics[0]["range"], range((0, 13), (1, 0)));
+        assert_eq!(*published[1], json!([]));
+
+        let refused = sent.iter().find(|message| message["id"] == 1).unwrap();
+        assert_eq!(refused["error"]["code"], REQUEST_FAILED);
+        assert_eq!(
+            *result(&sent, 2),
+            json!([{
+                "range": range((0, 0), (3, 0)),
+                "newText": "define square with n\n    return n * n\nend\n\nlet total be square 3\n",
+            }])
+        );
+    }
+}
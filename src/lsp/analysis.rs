@@ -0,0 +1,350 @@
+//! Name resolution for the editor features: which `let`, parameter, loop
+//! variable or `define` each name refers to.
+//!
+//! The rules are the interpreter's. Every block opens a scope, and `let`
+//! rebinds a variable that is already visible or declares one in the
+//! current block. A function body sees only its parameters and its own
+//! variables. Functions are global, and a call can come before the
+//! `define`. A name that is both a variable and a function means the
+//! variable.
+
+use crate::ast::*;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Parameter,
+    Variable,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Where the name is first bound, or the function's name in its `define`
+    pub definition: Span,
+    /// Every other place the name is written: uses and later `let`s
+    pub references: Vec<Span>,
+    /// Where the name can be used: to the end of the block it is declared
+    /// in. For a function, the whole `define` statement.
+    pub extent: Span,
+    /// The function whose body declares the name, if any
+    pub function: Option<usize>,
+}
+
+impl Symbol {
+    /// The definition and every reference, in source order.
+    pub fn occurrences(&self) -> Vec<Span> {
+        let mut spans = self.references.clone();
+        spans.push(self.definition);
+        spans.sort_by_key(|span| span.start);
+        spans
+    }
+}
+
+/// Every symbol in a program.
+#[derive(Default)]
+pub struct Index {
+    pub symbols: Vec<Symbol>,
+}
+
+impl Index {
+    pub fn build(program: &Program, source: &str) -> Self {
+        let mut resolver = Resolver {
+            source,
+            symbols: Vec::new(),
+            functions: HashMap::new(),
+            scopes: vec![Scope::new(usize::MAX)],
+            function: None,
+        };
+        resolver.collect_functions(&program.statements);
+        resolver.statements(&program.statements);
+        Index {
+            symbols: resolver.symbols,
+        }
+    }
+
+    /// The symbol written at `offset`, and the span of that occurrence. An
+    /// offset just past the end of a name still counts, as that is where the
+    /// cursor sits after typing it.
+    pub fn symbol_at(&self, offset: usize) -> Option<(usize, Span)> {
+        self.symbols.iter().enumerate().find_map(|(id, symbol)| {
+            symbol
+                .occurrences()
+                .into_iter()
+                .find(|span| span.start <= offset && offset <= span.end)
+                .map(|span| (id, span))
+        })
+    }
+
+    /// The function whose body contains `offset`, the innermost if
+    /// definitions are nested.
+    pub fn function_at(&self, offset: usize) -> Option<usize> {
+        self.symbols
+            .iter()
+            .enumerate()
+            .filter(|(_, symbol)| {
+                symbol.kind == SymbolKind::Function
+                    && symbol.definition.end < offset
+                    && offset <= symbol.extent.end
+            })
+            .min_by_key(|(_, symbol)| symbol.extent.end - symbol.extent.start)
+            .map(|(id, _)| id)
+    }
+
+    /// The functions, and the variables and parameters that can be used at
+    /// `offset`.
+    pub fn visible_at(&self, offset: usize) -> impl Iterator<Item = &Symbol> {
+        let function = self.function_at(offset);
+        self.symbols.iter().filter(move |symbol| {
+            symbol.kind == SymbolKind::Function
+                || (symbol.function == function
+                    && symbol.definition.end < offset
+                    && offset <= symbol.extent.end)
+        })
+    }
+}
+
+struct Scope {
+    names: HashMap<String, usize>,
+    // Where the block ends, which is where its variables stop being visible
+    end: usize,
+}
+
+impl Scope {
+    fn new(end: usize) -> Self {
+        Scope {
+            names: HashMap::new(),
+            end,
+        }
+    }
+}
+
+struct Resolver<'a> {
+    source: &'a str,
+    symbols: Vec<Symbol>,
+    functions: HashMap<String, usize>,
+    // The blocks of the function being resolved, or of the top level,
+    // innermost last
+    scopes: Vec<Scope>,
+    function: Option<usize>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Adds a symbol for every `define`, however deeply nested. The first
+    /// definition of a name is the one calls use.
+    fn collect_functions(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            match &statement.kind {
+                StatementKind::FunctionDef(function) => {
+                    if !self.functions.contains_key(&function.name) {
+                        self.functions
+                            .insert(function.name.clone(), self.symbols.len());
+                        self.symbols.push(Symbol {
+                            name: function.name.clone(),
+                            kind: SymbolKind::Function,
+                            definition: function.name_span,
+                            references: Vec::new(),
+                            extent: statement.span,
+                            function: None,
+                        });
+                    }
+                    self.collect_functions(&function.body);
+                }
+                StatementKind::When(when) => {
+                    self.collect_functions(&when.then_block);
+                    if let Some(otherwise_block) = &when.otherwise_block {
+                        self.collect_functions(otherwise_block);
+                    }
+                }
+                StatementKind::RepeatWhile(repeat) => self.collect_functions(&repeat.body),
+                StatementKind::RepeatRange(repeat) => self.collect_functions(&repeat.body),
+                StatementKind::RepeatEach(repeat) => self.collect_functions(&repeat.body),
+                _ => {}
+            }
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.names.get(name).copied())
+    }
+
+    fn declare(&mut self, name: &str, span: Span, kind: SymbolKind) {
+        let scope = self.scopes.last_mut().expect("there is always a scope");
+        scope.names.insert(name.to_string(), self.symbols.len());
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            kind,
+            definition: span,
+            references: Vec::new(),
+            extent: Span::new(span.start, scope.end),
+            function: self.function,
+        });
+    }
+
+    /// A `let` of `name`: a new value for a visible variable, or a new one.
+    fn bind(&mut self, name: &str, span: Span) {
+        match self.lookup(name) {
+            Some(id) => self.symbols[id].references.push(span),
+            None => self.declare(name, span, SymbolKind::Variable),
+        }
+    }
+
+    /// Where `name` is written in an expression spanning `span`. The span
+    /// includes any parentheses around the expression.
+    fn name_span(&self, span: Span, name: &str) -> Span {
+        let text = self.source.get(span.start..span.end).unwrap_or_default();
+        let skipped = text.len()
+            - text
+                .trim_start_matches(|ch: char| ch == '(' || ch.is_whitespace())
+                .len();
+        Span::new(span.start + skipped, span.start + skipped + name.len())
+    }
+
+    fn statements(&mut self, statements: &[Statement]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    /// Resolves a block in a scope of its own that lasts until `end`,
+    /// optionally starting with a loop variable.
+    fn block(&mut self, statements: &[Statement], end: usize, variable: Option<(&str, Span)>) {
+        self.scopes.push(Scope::new(end));
+        if let Some((name, span)) = variable {
+            self.declare(name, span, SymbolKind::Variable);
+        }
+        self.statements(statements);
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        let end = statement.span.end;
+        match &statement.kind {
+            StatementKind::Let(let_statement) => {
+                self.expression(&let_statement.value);
+                for (name, span) in let_statement
+                    .identifiers
+                    .iter()
+                    .zip(&let_statement.name_spans)
+                {
+                    self.bind(name, *span);
+                }
+            }
+            StatementKind::SetField(set_field) => {
+                self.expression(&set_field.object);
+                self.expression(&set_field.value);
+            }
+            StatementKind::Show(show) => self.expression(&show.value),
+            StatementKind::When(when) => {
+                self.expression(&when.condition);
+                self.block(&when.then_block, end, None);
+                if let Some(otherwise_block) = &when.otherwise_block {
+                    self.block(otherwise_block, end, None);
+                }
+            }
+            StatementKind::FunctionDef(function) => {
+                let id = self.functions[&function.name];
+                if self.symbols[id].definition != function.name_span {
+                    // A second definition of the name, which is an error
+                    // the compiler reports
+                    self.symbols[id].references.push(function.name_span);
+                }
+
+                let enclosing_scopes = std::mem::replace(&mut self.scopes, vec![Scope::new(end)]);
+                let enclosing_function = self.function.replace(id);
+                for (name, span) in function.parameters.iter().zip(&function.parameter_spans) {
+                    self.declare(name, *span, SymbolKind::Parameter);
+                }
+                self.statements(&function.body);
+                self.scopes = enclosing_scopes;
+                self.function = enclosing_function;
+            }
+            StatementKind::Return(return_statement) => {
+                for value in &return_statement.values {
+                    self.expression(value);
+                }
+            }
+            StatementKind::RepeatWhile(repeat) => {
+                self.expression(&repeat.condition);
+                self.block(&repeat.body, end, None);
+            }
+            StatementKind::RepeatRange(repeat) => {
+                self.expression(&repeat.start);
+                self.expression(&repeat.end);
+                if let Some(step) = &repeat.step {
+                    self.expression(step);
+                }
+                let variable = (repeat.variable.as_str(), repeat.variable_span);
+                self.block(&repeat.body, end, Some(variable));
+            }
+            StatementKind::RepeatEach(repeat) => {
+                self.expression(&repeat.list);
+                let variable = (repeat.variable.as_str(), repeat.variable_span);
+                self.block(&repeat.body, end, Some(variable));
+            }
+            StatementKind::Add(add) => {
+                self.expression(&add.item);
+                self.expression(&add.list);
+            }
+            StatementKind::Remove(remove) => {
+                self.expression(&remove.item);
+                self.expression(&remove.list);
+            }
+            StatementKind::Expression(expr) => self.expression(expr),
+            StatementKind::Break | StatementKind::Continue | StatementKind::Error => {}
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) => {
+                let span = self.name_span(expr.span, name);
+                if let Some(id) = self
+                    .lookup(name)
+                    .or_else(|| self.functions.get(name).copied())
+                {
+                    self.symbols[id].references.push(span);
+                }
+            }
+            ExpressionKind::FunctionCall(call) => {
+                let span = self.name_span(expr.span, &call.name);
+                if let Some(&id) = self.functions.get(&call.name) {
+                    self.symbols[id].references.push(span);
+                }
+                for argument in &call.arguments {
+                    self.expression(argument);
+                }
+            }
+            ExpressionKind::BinaryOp(operation) => {
+                self.expression(&operation.left);
+                self.expression(&operation.right);
+            }
+            ExpressionKind::Not(operand) => self.expression(operand),
+            ExpressionKind::List(items) => {
+                for item in items {
+                    self.expression(item);
+                }
+            }
+            ExpressionKind::Index(index) => {
+                self.expression(&index.list);
+                self.expression(&index.index);
+            }
+            ExpressionKind::ListQuery(query) => self.expression(&query.list),
+            ExpressionKind::Object(fields) => {
+                for field in fields {
+                    self.expression(&field.value);
+                }
+            }
+            ExpressionKind::Member(member) => self.expression(&member.object),
+            ExpressionKind::Number(_)
+            | ExpressionKind::String(_)
+            | ExpressionKind::Boolean(_)
+            | ExpressionKind::Nothing => {}
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! The wire format: JSON-RPC messages framed by a `Content-Length` header,
+//! and conversion between byte offsets and LSP positions.
+
+use delta::span::Span;
+use serde_json::{Value, json};
+use std::io::{self, BufRead, Write};
+
+/// Reads the body of the next message, or `None` at the end of the input.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        // Content-Type is the only other header, and it has one value
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("Content-Length")
+        {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message without a valid Content-Length header",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+/// How the `character` of a position counts, as agreed in `initialize`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+    /// Bytes of UTF-8
+    Utf8,
+    /// UTF-16 code units, which every client understands
+    Utf16,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16 => "utf-16",
+        }
+    }
+}
+
+/// Where each line of a document starts, for converting positions.
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+    encoding: Encoding,
+}
+
+impl LineIndex {
+    pub fn new(text: &str, encoding: Encoding) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(index, _)| index + 1))
+            .collect();
+        LineIndex {
+            line_starts,
+            encoding,
+        }
+    }
+
+    /// The position of the byte `offset` in `text`.
+    pub fn position(&self, text: &str, offset: usize) -> Value {
+        let offset = offset.min(text.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset) - 1;
+        let before = text.get(self.line_starts[line]..offset).unwrap_or_default();
+        let character = match self.encoding {
+            Encoding::Utf8 => before.len(),
+            Encoding::Utf16 => before.encode_utf16().count(),
+        };
+        json!({ "line": line, "character": character })
+    }
+
+    pub fn range(&self, text: &str, span: Span) -> Value {
+        json!({
+            "start": self.position(text, span.start),
+            "end": self.position(text, span.end),
+        })
+    }
+
+    /// The byte offset of an LSP position in `text`. Positions past the end
+    /// of a line or of the text are clamped to it.
+    pub fn offset(&self, text: &str, position: &Value) -> usize {
+        let field = |name: &str| position[name].as_u64().unwrap_or(0) as usize;
+        let Some(&line_start) = self.line_starts.get(field("line")) else {
+            return text.len();
+        };
+        let line = text[line_start..].split('\n').next().unwrap_or_default();
+
+        let mut remaining = field("character");
+        for (index, ch) in line.char_indices() {
+            let width = match self.encoding {
+                Encoding::Utf8 => ch.len_utf8(),
+                Encoding::Utf16 => ch.len_utf16(),
+            };
+            if remaining < width {
+                return line_start + index;
+            }
+            remaining -= width;
+        }
+        line_start + line.len()
+    }
+}
@@ -1,138 +1,451 @@
 use std::env;
+use std::fmt;
 use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::process;
 
-mod lexer;
-mod parser;
 mod ast;
+mod cli;
 mod codegen;
+mod formatter;
+mod interpreter;
+mod lsp;
+mod parser;
+mod repl;
 
-use lexer::Lexer;
-use parser::Parser;
-use codegen::CodeGenerator;
+use ast::Program;
+use cli::{Command, Emit, Invocation, MessageFormat, Options};
+use codegen::{CodeGenerator, NativeFormat, OptLevel};
+use delta::diagnostics::{Diagnostic, codes};
+use delta::lexer::{self, Lexer, SpannedToken};
 use inkwell::context::Context;
+use inkwell::targets::TargetMachine;
+use interpreter::Interpreter;
+use parser::Parser;
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() < 2 || args.len() > 3 {
-        eprintln!("Usage: {} <source_file.de> [--compile|--interpret]", args[0]);
-        eprintln!("  --compile   : Generate LLVM IR and compile (default)");
-        eprintln!("  --interpret : Run in interpreter mode");
-        process::exit(1);
-    }
-    
-    let filename = &args[1];
-    let mode = if args.len() == 3 {
-        &args[2]
-    } else {
-        "--compile"
-    };
-    
-    let source = match fs::read_to_string(filename) {
-        Ok(content) => content,
-        Err(err) => {
-            eprintln!("Error reading file '{}': {}", filename, err);
-            process::exit(1);
+/// Whether human diagnostics on stderr get colour: only on a terminal, and
+/// not when `NO_COLOR` is set.
+fn use_color() -> bool {
+    std::io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none()
+}
+
+/// Why a command failed, which decides the exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Failure {
+    /// The program has errors, which have been reported
+    Errors = 1,
+    /// The command line is invalid
+    Usage = 2,
+    /// A file could not be read or written, or an external tool failed
+    Tool = 3,
+}
+
+/// One input file on its way through the pipeline.
+struct Unit<'a> {
+    filename: &'a str,
+    source: String,
+    options: &'a Options,
+}
+
+impl<'a> Unit<'a> {
+    fn load(filename: &'a str, options: &'a Options) -> Result<Self, Failure> {
+        match fs::read_to_string(filename) {
+            Ok(source) => Ok(Unit {
+                filename,
+                source,
+                options,
+            }),
+            Err(err) => {
+                eprintln!("Error reading file '{}': {}", filename, err);
+                Err(Failure::Tool)
+            }
         }
-    };
-    
-    // Step 1: Tokenize
-    let mut lexer = Lexer::new(&source);
-    let tokens = match lexer.tokenize() {
-        Ok(tokens) => tokens,
-        Err(err) => {
-            eprintln!("Lexer error: {}", err);
-            process::exit(1);
+    }
+
+    /// Prints a diagnostic to stderr.
+    fn report(&self, diagnostic: &Diagnostic) {
+        match self.options.message_format {
+            MessageFormat::Human => {
+                eprint!(
+                    "{}",
+                    diagnostic.render(self.filename, &self.source, use_color())
+                );
+            }
+            MessageFormat::Json => eprintln!("{}", diagnostic.to_json(self.filename, &self.source)),
         }
-    };
-    
-    if mode == "--debug" {
-        println!("Tokens: {:?}", tokens);
-    }
-    
-    // Step 2: Parse into AST
-    let mut parser = Parser::new(tokens);
-    let ast = match parser.parse() {
-        Ok(ast) => ast,
-        Err(err) => {
-            eprintln!("Parser error: {}", err);
-            process::exit(1);
+    }
+
+    fn tokenize(&self) -> Result<Vec<SpannedToken>, Failure> {
+        Lexer::new(&self.source).tokenize().map_err(|err| {
+            self.report(&err);
+            Failure::Errors
+        })
+    }
+
+    fn parse(&self) -> Result<Program, Failure> {
+        self.parse_tokens(self.tokenize()?)
+    }
+
+    fn parse_tokens(&self, tokens: Vec<SpannedToken>) -> Result<Program, Failure> {
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse();
+        let errors = parser.take_errors();
+        if !errors.is_empty() {
+            for err in &errors {
+                self.report(err);
+            }
+            return Err(Failure::Errors);
         }
-    };
-    
-    if mode == "--debug" {
-        println!("AST: {:#?}", ast);
-    }
-    
-    // Step 3: Execute based on mode
-    match mode {
-        "--interpret" => {
-            println!("Running in interpreter mode...");
-            let context = Context::create();
-            let mut codegen = match CodeGenerator::new(&context, "delta_module") {
-                Ok(cg) => cg,
-                Err(err) => {
-                    eprintln!("Failed to create code generator: {}", err);
-                    process::exit(1);
-                }
+        Ok(program)
+    }
+
+    fn compile<'ctx>(&self, context: &'ctx Context) -> Result<CodeGenerator<'ctx>, Failure> {
+        let program = self.parse()?;
+        let mut codegen = match CodeGenerator::new(context, "delta_module") {
+            Ok(cg) => cg,
+            Err(err) => {
+                eprintln!("Failed to create code generator: {}", err);
+                return Err(Failure::Tool);
+            }
+        };
+
+        if self.options.debug_info {
+            let optimized = self.options.opt_level != OptLevel::O0;
+            codegen.enable_debug_info(Path::new(self.filename), &self.source, optimized);
+        }
+
+        self.progress(format_args!("Compiling to LLVM IR"));
+        let result = codegen.compile(&program);
+        for warning in codegen.take_warnings() {
+            self.report(&warning);
+        }
+        if let Err(err) = result {
+            let diagnostic = match err.downcast::<Diagnostic>() {
+                Ok(diagnostic) => *diagnostic,
+                Err(err) => Diagnostic::error(codes::INTERNAL, err.to_string()),
             };
-            
-            if let Err(err) = codegen.interpret(&ast) {
-                eprintln!("Interpreter error: {}", err);
-                process::exit(1);
+            self.report(&diagnostic);
+            return Err(Failure::Errors);
+        }
+        Ok(codegen)
+    }
+
+    /// Rewrites the file in the standard layout, or with `--check` reports
+    /// where it first differs from it.
+    fn format(&self) -> Result<(), Failure> {
+        let tokens = self.tokenize()?;
+        let comments = lexer::comments(&tokens);
+        let program = self.parse_tokens(tokens)?;
+        let formatted = formatter::format_program(&program, &comments, &self.source);
+
+        if self.options.check {
+            if formatted == self.source {
+                return Ok(());
             }
+            // The first line that differs, or the first one past the shorter text
+            let line = formatted
+                .lines()
+                .zip(self.source.lines())
+                .position(|(formatted, original)| formatted != original)
+                .unwrap_or_else(|| formatted.lines().count().min(self.source.lines().count()));
+            eprintln!(
+                "{}:{}: not formatted; run 'delta fmt {}'",
+                self.filename,
+                line + 1,
+                self.filename
+            );
+            return Err(Failure::Errors);
         }
-        "--compile" | _ => {
-            println!("Compiling to LLVM IR...");
-            let context = Context::create();
-            let mut codegen = match CodeGenerator::new(&context, "delta_module") {
-                Ok(cg) => cg,
-                Err(err) => {
-                    eprintln!("Failed to create code generator: {}", err);
-                    process::exit(1);
-                }
+        if self.options.output.is_some() {
+            return self.write_text(&formatted);
+        }
+        if formatted != self.source {
+            self.progress(format_args!("Reformatting"));
+            fs::write(self.filename, formatted).map_err(|err| {
+                eprintln!("Failed to write '{}': {}", self.filename, err);
+                Failure::Tool
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Where to write output of the given kind: `-o` if given, otherwise
+    /// next to the source with `extension` in place of `.de`.
+    fn output_path(&self, extension: &str) -> PathBuf {
+        match &self.options.output {
+            Some(output) => PathBuf::from(output),
+            None => Path::new(self.filename).with_extension(extension),
+        }
+    }
+
+    fn run(&self) -> Result<(), Failure> {
+        if self.options.jit {
+            return self.run_jit();
+        }
+
+        let program = self.parse()?;
+        Interpreter::new().run(&program).map_err(|err| {
+            self.report(&err);
+            Failure::Errors
+        })
+    }
+
+    fn run_jit(&self) -> Result<(), Failure> {
+        let context = Context::create();
+        let codegen = self.compile(&context)?;
+        self.lower(&codegen)?;
+        match codegen.run_jit(self.options.opt_level) {
+            Ok(0) => Ok(()),
+            Ok(status) => process::exit(status),
+            Err(err) => {
+                eprintln!("{}", err);
+                Err(Failure::Tool)
+            }
+        }
+    }
+
+    fn check(&self) -> Result<(), Failure> {
+        let context = Context::create();
+        self.compile(&context).map(|_| ())
+    }
+
+    fn build(&self) -> Result<(), Failure> {
+        let context = Context::create();
+        let codegen = self.compile(&context)?;
+        let machine = self.lower(&codegen)?;
+        let exe_path = self.output_path(env::consts::EXE_EXTENSION);
+
+        // Requested files go next to the executable unless given a path
+        let mut kept_object = None;
+        for (emit, path) in &self.options.artifacts {
+            let path = match path {
+                Some(path) => PathBuf::from(path),
+                None => exe_path.with_extension(emit.extension().unwrap_or_default()),
             };
-            
-            if let Err(err) = codegen.compile(&ast) {
-                eprintln!("Compilation error: {}", err);
-                process::exit(1);
+            self.write_artifact(&codegen, &machine, *emit, &path)?;
+            if *emit == Emit::Obj {
+                kept_object = Some(path);
             }
-            
-            // Save LLVM IR to file
-            let ir_filename = filename.replace(".de", ".ll");
-            if let Err(err) = codegen.save_to_file(&ir_filename) {
-                eprintln!("Failed to save LLVM IR: {}", err);
-                process::exit(1);
+        }
+
+        let object_path = match kept_object {
+            Some(path) => path,
+            None => {
+                let path = temp_path(self.filename, "o");
+                self.write_artifact(&codegen, &machine, Emit::Obj, &path)?;
+                path
             }
-            
-            println!("LLVM IR saved to: {}", ir_filename);
-
-            // auto conversion (using clang)
-            let exe_filename = filename.replace(".de", "");
-            println!("Compiling to executable: {}", exe_filename);
-
-            let clang_status = process::Command::new("clang")
-                .arg(&ir_filename)
-                .arg("-o")
-                .arg(&exe_filename)
-                .status();
-
-            match clang_status {
-                Ok(status) => {
-                    if status.success() {
-                        println!("Compilation Successful! Run with: {}", exe_filename);
-                    } else {
-                        eprintln!("Failed to compile with clang. Exit Code: {}", status);
-                        process::exit(1)
-                    }
-                }
-                Err(error) => {
-                    eprintln!("Error executing clang: {}", error);
-                    eprintln!("You can manually compile with: clang {} -o {}", ir_filename, exe_filename);
-                    process::exit(1);
+        };
+
+        self.progress(format_args!("Linking {}", exe_path.display()));
+        let triple = machine.get_triple();
+        let result = codegen::link(
+            self.options.linker.as_deref(),
+            &triple.as_str().to_string_lossy(),
+            &[&object_path],
+            &exe_path,
+        );
+        if !self
+            .options
+            .artifacts
+            .iter()
+            .any(|(emit, _)| *emit == Emit::Obj)
+        {
+            let _ = fs::remove_file(&object_path);
+        }
+        if let Err(message) = result {
+            eprintln!("{}", message);
+            return Err(Failure::Tool);
+        }
+        Ok(())
+    }
+
+    fn emit(&self, emit: Emit) -> Result<(), Failure> {
+        match emit {
+            Emit::Tokens => {
+                let tokens = self.tokenize()?;
+                let mut text = String::new();
+                for token in &tokens {
+                    let (line, column) = token.span.line_col(&self.source);
+                    text += &format!("{}:{}\t{:?}\n", line, column, token.token);
                 }
+                return self.write_text(&text);
+            }
+            Emit::Ast => {
+                let program = self.parse()?;
+                return self.write_text(&format!("{:#?}\n", program));
+            }
+            Emit::Ir | Emit::Bc | Emit::Asm | Emit::Obj => {}
+        }
+
+        let context = Context::create();
+        let codegen = self.compile(&context)?;
+        let machine = self.lower(&codegen)?;
+
+        if emit == Emit::Ir && self.options.output.as_deref() == Some("-") {
+            return self.write_text(&codegen.ir());
+        }
+        let path = self.output_path(emit.extension().unwrap_or_default());
+        self.write_artifact(&codegen, &machine, emit, &path)
+    }
+
+    /// Writes the module as IR, bitcode, assembly or an object file.
+    fn write_artifact(
+        &self,
+        codegen: &CodeGenerator,
+        machine: &TargetMachine,
+        emit: Emit,
+        path: &Path,
+    ) -> Result<(), Failure> {
+        self.progress(format_args!("Writing {}", path.display()));
+        let format = match emit {
+            Emit::Bc => NativeFormat::Bitcode,
+            Emit::Asm => NativeFormat::Assembly,
+            Emit::Obj => NativeFormat::Object,
+            _ => return save_ir(codegen, path),
+        };
+        codegen.write_native(machine, format, path).map_err(|err| {
+            eprintln!("{}", err);
+            Failure::Tool
+        })
+    }
+
+    /// Reports a step on stderr with `-v`. Without it, a successful command
+    /// prints nothing but what it was asked to produce.
+    fn progress(&self, message: fmt::Arguments) {
+        if self.options.verbose {
+            eprintln!("{}: {}", self.filename, message);
+        }
+    }
+
+    /// Writes text output to `-o`, or to stdout when there is no `-o` or it
+    /// is `-`.
+    fn write_text(&self, text: &str) -> Result<(), Failure> {
+        match self.options.output.as_deref() {
+            None | Some("-") => {
+                print!("{}", text);
+                Ok(())
+            }
+            Some(path) => fs::write(path, text).map_err(|err| {
+                eprintln!("Failed to write '{}': {}", path, err);
+                Failure::Tool
+            }),
+        }
+    }
+
+    /// A machine for `--target` at the selected optimisation level.
+    fn target_machine(&self) -> Result<TargetMachine, Failure> {
+        let level = self.options.opt_level.machine_level();
+        codegen::target_machine(self.options.target.as_deref(), level).map_err(|err| {
+            eprintln!("{}", err);
+            Failure::Tool
+        })
+    }
+
+    /// Targets and optimises a compiled module, ready to be written out.
+    /// With `--dump-ir`, the IR on either side of the optimiser is saved
+    /// next to the source.
+    fn lower(&self, codegen: &CodeGenerator) -> Result<TargetMachine, Failure> {
+        let machine = self.target_machine()?;
+        codegen.set_target(&machine);
+
+        if self.options.dump_ir {
+            save_ir(
+                codegen,
+                &Path::new(self.filename).with_extension("unopt.ll"),
+            )?;
+        }
+        self.progress(format_args!(
+            "Optimising with -{}",
+            self.options.opt_level.name()
+        ));
+        if let Err(err) = codegen.optimize(&machine, self.options.opt_level) {
+            eprintln!("{}", err);
+            return Err(Failure::Tool);
+        }
+        if self.options.dump_ir {
+            save_ir(codegen, &Path::new(self.filename).with_extension("opt.ll"))?;
+        }
+        Ok(machine)
+    }
+}
+
+fn save_ir(codegen: &CodeGenerator, path: &Path) -> Result<(), Failure> {
+    codegen.save_to_file(path).map_err(|err| {
+        eprintln!("Failed to save LLVM IR to '{}': {}", path.display(), err);
+        Failure::Tool
+    })
+}
+
+/// A scratch file for intermediate output, unique to this process.
+fn temp_path(filename: &str, extension: &str) -> PathBuf {
+    let stem = Path::new(filename)
+        .file_stem()
+        .map_or_else(|| "delta".into(), |stem| stem.to_string_lossy());
+    env::temp_dir().join(format!("{}-{}.{}", stem, process::id(), extension))
+}
+
+fn main() {
+    let options = match cli::parse(env::args().skip(1)) {
+        Ok(Invocation::Compile(options)) => options,
+        Ok(Invocation::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Invocation::Version) => {
+            println!("delta {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, cli::USAGE);
+            process::exit(Failure::Usage as i32);
+        }
+    };
+
+    if options.command == Command::Repl {
+        let backend = if options.jit {
+            repl::Backend::Jit
+        } else {
+            repl::Backend::Interpreter
+        };
+        if let Err(message) = repl::run(backend) {
+            eprintln!("{}", message);
+            process::exit(Failure::Tool as i32);
+        }
+        return;
+    }
+    if options.command == Command::Lsp {
+        let stdin = std::io::stdin();
+        match lsp::serve(stdin.lock(), std::io::stdout()) {
+            Ok(true) => return,
+            // The protocol asks for status 1 when the client exits without
+            // shutting the server down first
+            Ok(false) => process::exit(1),
+            Err(err) => {
+                eprintln!("Language server stopped: {}", err);
+                process::exit(Failure::Tool as i32);
             }
         }
     }
+
+    // Every input is its own program; one failing does not stop the rest
+    let mut worst = None;
+    for filename in &options.inputs {
+        let result = Unit::load(filename, &options).and_then(|unit| match options.command {
+            Command::Run => unit.run(),
+            Command::Build => unit.build(),
+            Command::Check => unit.check(),
+            Command::Fmt => unit.format(),
+            Command::Repl | Command::Lsp => unreachable!("handled before the inputs"),
+            Command::Emit(emit) => unit.emit(emit),
+        });
+        if let Err(failure) = result {
+            worst = worst.max(Some(failure));
+        }
+    }
+
+    if let Some(failure) = worst {
+        process::exit(failure as i32);
+    }
 }
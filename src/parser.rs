@@ -1,33 +1,72 @@
 use crate::ast::*;
-use crate::lexer::Token;
+use delta::diagnostics::{codes, Diagnostic};
+use delta::lexer::{SpannedToken, Token};
 
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<SpannedToken>,
     current: usize,
+    // How many `define` bodies enclose the current token
+    function_depth: usize,
+    // How many loops enclose the current token within the current function
+    loop_depth: usize,
+    errors: Vec<Diagnostic>,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
+        Parser {
+            tokens,
+            current: 0,
+            function_depth: 0,
+            loop_depth: 0,
+            errors: Vec::new(),
+        }
     }
 
     fn current_token(&self) -> &Token {
-        self.tokens.get(self.current).unwrap_or(&Token::Eof)
+        self.tokens.get(self.current).map_or(&Token::Eof, |spanned| &spanned.token)
+    }
+
+    fn current_span(&self) -> Span {
+        match self.tokens.get(self.current).or(self.tokens.last()) {
+            Some(spanned) => spanned.span,
+            None => Span::default(),
+        }
+    }
+
+    /// The span from `start` to the end of the last token consumed, not
+    /// counting the newlines and dedents that close a block.
+    fn span_from(&self, start: usize) -> Span {
+        let end = self.tokens[..self.current]
+            .iter()
+            .rev()
+            .find(|spanned| !matches!(spanned.token, Token::Newline | Token::Indent | Token::Dedent))
+            .map_or(start, |spanned| spanned.span.end);
+        Span::new(start, end.max(start))
+    }
+
+    /// An error for a token that is not what the grammar needs here.
+    fn expected(&self, what: &str) -> Diagnostic {
+        Diagnostic::error(
+            codes::UNEXPECTED_TOKEN,
+            format!("Expected {}, found {}", what, self.current_token()),
+        )
+        .with_primary(self.current_span(), format!("expected {}", what))
     }
 
     fn advance(&mut self) -> &Token {
         if self.current < self.tokens.len() {
-            self.current = self.current + 1;
+            self.current += 1;
         }
         self.current_token()
     }
 
-    fn expect(&mut self, expected: Token) -> Result<(), String> {
+    fn expect(&mut self, expected: Token) -> Result<(), Diagnostic> {
         if std::mem::discriminant(self.current_token()) == std::mem::discriminant(&expected) {
             self.advance();
             Ok(())
         } else {
-            Err(format!("Expected {:?}, found {:?}", expected, self.current_token()))
+            Err(self.expected(&expected.to_string()))
         }
     }
 
@@ -37,57 +76,243 @@ impl Parser {
         }
     }
 
-    pub fn parse(&mut self) -> Result<Program, String> {
+    /// Parses the whole program. Syntax errors do not stop the parse: each
+    /// broken statement becomes a `StatementKind::Error` node, and the
+    /// errors are collected for `take_errors`.
+    pub fn parse(&mut self) -> Program {
         let mut statements = Vec::new();
         self.skip_newlines();
 
         while !matches!(self.current_token(), Token::Eof) {
-            statements.push(self.parse_statement()?);
+            statements.push(self.parse_statement_or_recover());
             self.skip_newlines();
         }
 
-        Ok(Program { statements })
+        Program { statements }
     }
 
-    fn parse_statement(&mut self) -> Result<Statement, String> {
-        match self.current_token() {
-            Token::Let => self.parse_let_statement(),
-            Token::Show => self.parse_show_statement(),
-            Token::When => self.parse_when_statement(),
-            Token::Define => self.parse_function_def(),
+    /// The syntax errors found so far, in source order.
+    pub fn take_errors(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Parses one statement, or records its error and skips to where the
+    /// next statement probably starts.
+    fn parse_statement_or_recover(&mut self) -> Statement {
+        let start_index = self.current;
+        let start = self.current_span().start;
+
+        // A block with no header to belong to
+        if matches!(self.current_token(), Token::Indent) {
+            self.errors.push(self.expected("a statement"));
+            self.parse_block();
+            return Statement {
+                kind: StatementKind::Error,
+                span: self.span_from(start),
+            };
+        }
+
+        match self.parse_statement() {
+            Ok(statement) => statement,
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize(start_index);
+                Statement {
+                    kind: StatementKind::Error,
+                    span: self.span_from(start),
+                }
+            }
+        }
+    }
+
+    /// Skips the rest of a broken statement: up to the end of its line, or
+    /// to a keyword that starts a new statement, whichever comes first.
+    /// A block under the broken line is still parsed so the errors inside
+    /// it are reported, but it is thrown away.
+    fn synchronize(&mut self, start_index: usize) {
+        let header = self.tokens.get(start_index).map(|spanned| spanned.token.clone());
+
+        // Errors raised at the first token, like a stray 'end', must still
+        // move forward or the same token would fail again
+        if self.current == start_index
+            && !matches!(self.current_token(), Token::Newline | Token::Dedent | Token::Eof)
+        {
+            self.advance();
+        }
+
+        loop {
+            match self.current_token() {
+                Token::Eof | Token::Dedent | Token::Otherwise | Token::End => return,
+                Token::Newline => {
+                    self.skip_newlines();
+                    if matches!(self.current_token(), Token::Indent) {
+                        self.parse_orphan_block(header.as_ref());
+                    }
+                    return;
+                }
+                Token::Let | Token::Show | Token::When | Token::Define | Token::Return
+                | Token::Repeat | Token::Add | Token::Remove | Token::Break | Token::Continue => {
+                    return
+                }
+                _ => {
+                    self.advance();
+                }
+            }
+        }
+    }
+
+    /// Parses the block under a header that failed to parse, as the body of
+    /// whatever the header started, so `break` and `return` inside it are
+    /// not reported as misplaced. A closing 'end' or 'otherwise' block is
+    /// taken along with it.
+    fn parse_orphan_block(&mut self, header: Option<&Token>) {
+        let enclosing = (self.function_depth, self.loop_depth);
+        match header {
+            Some(Token::Define) => {
+                self.function_depth += 1;
+                self.loop_depth = 0;
+            }
+            Some(Token::Repeat) => self.loop_depth += 1,
+            _ => {}
+        }
+        self.parse_block();
+        (self.function_depth, self.loop_depth) = enclosing;
+
+        match (header, self.current_token()) {
+            (Some(Token::Define), Token::End) => {
+                self.advance();
+            }
+            (Some(Token::When), Token::Otherwise) => {
+                self.advance();
+                self.parse_block();
+            }
+            _ => {}
+        }
+    }
+
+    fn parse_statement(&mut self) -> Result<Statement, Diagnostic> {
+        let start = self.current_span().start;
+        let kind = match self.current_token() {
+            Token::Let => self.parse_let_statement()?,
+            Token::Show => self.parse_show_statement()?,
+            Token::When => self.parse_when_statement()?,
+            Token::Define => self.parse_function_def()?,
+            Token::Return => self.parse_return_statement()?,
+            Token::Repeat => self.parse_repeat_statement()?,
+            Token::Break | Token::Continue => self.parse_loop_control()?,
+            Token::Add => self.parse_add_statement()?,
+            Token::Remove => self.parse_remove_statement()?,
             _ => {
                 let expr = self.parse_expression()?;
-                Ok(Statement::Expression(expr))
+                StatementKind::Expression(expr)
             }
-        }
+        };
+
+        Ok(Statement {
+            kind,
+            span: self.span_from(start),
+        })
     }
 
-    fn parse_let_statement(&mut self) -> Result<Statement, String> {
+    fn parse_let_statement(&mut self) -> Result<StatementKind, Diagnostic> {
         self.expect(Token::Let)?;
-        
-        let identifier = match self.current_token() {
+
+        let mut identifiers = Vec::new();
+        let mut name_spans = Vec::new();
+        loop {
+            match self.current_token() {
+                Token::Identifier(name) => {
+                    let name = name.clone();
+                    let name_span = self.current_span();
+                    self.advance();
+                    if identifiers.is_empty() && matches!(self.current_token(), Token::Dot) {
+                        return self.parse_set_field_statement(name, name_span);
+                    }
+                    identifiers.push(name);
+                    name_spans.push(name_span);
+                }
+                _ => return Err(self.expected("a variable name after 'let'")),
+            }
+
+            if !matches!(self.current_token(), Token::Comma) {
+                break;
+            }
+            self.advance();
+        }
+
+        self.expect(Token::Be)?;
+
+        let value = self.parse_expression()?;
+
+        Ok(StatementKind::Let(LetStatement { identifiers, name_spans, value }))
+    }
+
+    /// Parses the rest of `let user.address.city be ...` once `user` has
+    /// been read.
+    fn parse_set_field_statement(&mut self, name: String, name_span: Span) -> Result<StatementKind, Diagnostic> {
+        let mut object = Expression::new(ExpressionKind::Identifier(name), name_span);
+        let mut field = self.parse_field_name()?;
+        while matches!(self.current_token(), Token::Dot) {
+            let span = self.span_from(object.span.start);
+            object = Expression::new(
+                ExpressionKind::Member(MemberAccess {
+                    object: Box::new(object),
+                    field,
+                }),
+                span,
+            );
+            field = self.parse_field_name()?;
+        }
+
+        self.expect(Token::Be)?;
+        let value = self.parse_expression()?;
+
+        Ok(StatementKind::SetField(SetFieldStatement { object, field, value }))
+    }
+
+    /// Parses `.name`.
+    fn parse_field_name(&mut self) -> Result<String, Diagnostic> {
+        self.expect(Token::Dot)?;
+        match self.current_token() {
             Token::Identifier(name) => {
                 let name = name.clone();
                 self.advance();
-                name
+                Ok(name)
             }
-            _ => return Err("Expected identifier after 'let'".to_string()),
-        };
-        
-        self.expect(Token::Be)?;
-        
-        let value = self.parse_expression()?;
-        
-        Ok(Statement::Let(LetStatement { identifier, value }))
+            _ => Err(self.expected("a field name after '.'")),
+        }
     }
 
-    fn parse_show_statement(&mut self) -> Result<Statement, String> {
+    fn parse_return_statement(&mut self) -> Result<StatementKind, Diagnostic> {
+        if self.function_depth == 0 {
+            return Err(Diagnostic::error(
+                codes::RETURN_OUTSIDE_FUNCTION,
+                "'return' can only be used inside a function",
+            )
+            .with_primary(self.current_span(), "not inside a 'define' block"));
+        }
+
+        self.expect(Token::Return)?;
+
+        let mut values = Vec::new();
+        if !matches!(self.current_token(), Token::Newline | Token::Dedent | Token::Eof) {
+            values.push(self.parse_expression()?);
+            while matches!(self.current_token(), Token::Comma) {
+                self.advance();
+                values.push(self.parse_expression()?);
+            }
+        }
+
+        Ok(StatementKind::Return(ReturnStatement { values }))
+    }
+
+    fn parse_show_statement(&mut self) -> Result<StatementKind, Diagnostic> {
         self.expect(Token::Show)?;
         let value = self.parse_expression()?;
-        Ok(Statement::Show(ShowStatement { value }))
+        Ok(StatementKind::Show(ShowStatement { value }))
     }
-    
-    fn parse_when_statement(&mut self) -> Result<Statement, String> {
+
+    fn parse_when_statement(&mut self) -> Result<StatementKind, Diagnostic> {
         self.expect(Token::When)?;
 
         let condition = self.parse_expression()?;
@@ -101,7 +326,7 @@ impl Parser {
             self.advance(); // Go Over Indent
 
             while !matches!(self.current_token(), Token::Dedent | Token::Otherwise | Token::Eof) {
-                then_block.push(self.parse_statement()?);
+                then_block.push(self.parse_statement_or_recover());
                 self.skip_newlines();
             }
 
@@ -119,7 +344,7 @@ impl Parser {
                 self.advance(); // Go Over Indent
 
                 while !matches!(self.current_token(), Token::Dedent | Token::Eof) {
-                    otherwise_statements.push(self.parse_statement()?);
+                    otherwise_statements.push(self.parse_statement_or_recover());
                     self.skip_newlines();
                 }
 
@@ -133,97 +358,290 @@ impl Parser {
             None
         };
 
-                
-        Ok(Statement::When(WhenStatement {
+
+        Ok(StatementKind::When(WhenStatement {
             condition,
             then_block,
             otherwise_block,
         }))
     }
 
-    fn parse_function_def(&mut self) -> Result<Statement, String> {
+    fn parse_repeat_statement(&mut self) -> Result<StatementKind, Diagnostic> {
+        self.expect(Token::Repeat)?;
+
+        match self.current_token() {
+            Token::While => {
+                self.advance();
+                let condition = self.parse_expression()?;
+                let body = self.parse_loop_body();
+
+                Ok(StatementKind::RepeatWhile(RepeatWhileStatement { condition, body }))
+            }
+            Token::For => {
+                self.advance();
+                self.expect(Token::Each)?;
+
+                let variable_span = self.current_span();
+                let variable = match self.current_token() {
+                    Token::Identifier(name) => {
+                        let name = name.clone();
+                        self.advance();
+                        name
+                    }
+                    _ => return Err(self.expected("a loop variable after 'for each'")),
+                };
+
+                if matches!(self.current_token(), Token::In) {
+                    self.advance();
+                    let list = self.parse_expression()?;
+                    let body = self.parse_loop_body();
+
+                    return Ok(StatementKind::RepeatEach(RepeatEachStatement {
+                        variable,
+                        variable_span,
+                        list,
+                        body,
+                    }));
+                }
+
+                self.expect(Token::From)?;
+                let start = self.parse_expression()?;
+                self.expect(Token::To)?;
+                let end = self.parse_expression()?;
+
+                let step = if matches!(self.current_token(), Token::By) {
+                    self.advance();
+                    Some(self.parse_expression()?)
+                } else {
+                    None
+                };
+
+                let body = self.parse_loop_body();
+
+                Ok(StatementKind::RepeatRange(RepeatRangeStatement {
+                    variable,
+                    variable_span,
+                    start,
+                    end,
+                    step,
+                    body,
+                }))
+            }
+            _ => Err(self.expected("'while' or 'for each' after 'repeat'")),
+        }
+    }
+
+    fn parse_add_statement(&mut self) -> Result<StatementKind, Diagnostic> {
+        self.expect(Token::Add)?;
+        let item = self.parse_expression()?;
+        self.expect(Token::To)?;
+        let list = self.parse_expression()?;
+        Ok(StatementKind::Add(AddStatement { item, list }))
+    }
+
+    fn parse_remove_statement(&mut self) -> Result<StatementKind, Diagnostic> {
+        self.expect(Token::Remove)?;
+        let item = self.parse_expression()?;
+        self.expect(Token::From)?;
+        let list = self.parse_expression()?;
+        Ok(StatementKind::Remove(RemoveStatement { item, list }))
+    }
+
+    fn parse_loop_body(&mut self) -> Vec<Statement> {
+        self.loop_depth += 1;
+        let body = self.parse_block();
+        self.loop_depth -= 1;
+        body
+    }
+
+    fn parse_loop_control(&mut self) -> Result<StatementKind, Diagnostic> {
+        let (statement, keyword) = match self.current_token() {
+            Token::Break => (StatementKind::Break, "break"),
+            _ => (StatementKind::Continue, "continue"),
+        };
+
+        if self.loop_depth == 0 {
+            return Err(Diagnostic::error(
+                codes::CONTROL_OUTSIDE_LOOP,
+                format!("'{}' can only be used inside a loop", keyword),
+            )
+            .with_primary(self.current_span(), "not inside a loop"));
+        }
+        self.advance();
+
+        Ok(statement)
+    }
+
+    /// Parses an indented block following a header line. A missing indent
+    /// gives an empty block.
+    fn parse_block(&mut self) -> Vec<Statement> {
+        self.skip_newlines();
+
+        let mut statements = Vec::new();
+        if matches!(self.current_token(), Token::Indent) {
+            self.advance(); // Go Over Indent
+
+            while !matches!(self.current_token(), Token::Dedent | Token::Eof) {
+                statements.push(self.parse_statement_or_recover());
+                self.skip_newlines();
+            }
+
+            if matches!(self.current_token(), Token::Dedent) {
+                self.advance(); // Go Over Dedent
+            }
+        }
+
+        statements
+    }
+
+    fn parse_function_def(&mut self) -> Result<StatementKind, Diagnostic> {
         self.expect(Token::Define)?;
-        
+
+        let name_span = self.current_span();
         let name = match self.current_token() {
             Token::Identifier(name) => {
                 let name = name.clone();
                 self.advance();
                 name
             }
-            _ => return Err("Expected function name after 'define'".to_string()),
+            _ => return Err(self.expected("a function name after 'define'")),
         };
-        
+
         let mut parameters = Vec::new();
-        
+        let mut parameter_spans = Vec::new();
+
         if matches!(self.current_token(), Token::With) {
             self.advance(); // consume 'with'
-            
+
+            // Parameters are separated by commas: `define power with base, exponent`
             loop {
+                let Token::Identifier(param) = self.current_token() else {
+                    let what = if parameters.is_empty() {
+                        "a parameter name after 'with'"
+                    } else {
+                        "a parameter name after ','"
+                    };
+                    return Err(self.expected(what));
+                };
+                parameters.push(param.clone());
+                parameter_spans.push(self.current_span());
+                self.advance();
+
                 match self.current_token() {
-                    Token::Identifier(param) => {
-                        parameters.push(param.clone());
+                    Token::Comma => {
                         self.advance();
                     }
-                    _ => break,
-                }
-                
-                // Check for more parameters (basic implementation)
-                if !matches!(self.current_token(), Token::Identifier(_)) {
-                    break;
+                    Token::Newline | Token::Eof => break,
+                    _ => return Err(self.expected("',' or the end of the line after a parameter")),
                 }
             }
         }
-        
+
         self.skip_newlines();
-        
+
         let mut body = Vec::new();
         if matches!(self.current_token(), Token::Indent) {
             self.advance(); // Go Over Indent
-            
+
+            // Loops around a definition do not extend into its body
+            let enclosing_loops = std::mem::replace(&mut self.loop_depth, 0);
+            self.function_depth += 1;
             while !matches!(self.current_token(), Token::End | Token::Dedent | Token::Eof) {
-                body.push(self.parse_statement()?);
+                body.push(self.parse_statement_or_recover());
                 self.skip_newlines();
             }
-            
+            self.function_depth -= 1;
+            self.loop_depth = enclosing_loops;
+
             // Handle either 'end' keyword or dedent
             if matches!(self.current_token(), Token::Dedent) {
                 self.advance();
             }
         }
-        
+
         if matches!(self.current_token(), Token::End) {
             self.advance();
         }
-        
-        Ok(Statement::FunctionDef(FunctionDef {
+
+        Ok(StatementKind::FunctionDef(FunctionDef {
             name,
+            name_span,
             parameters,
+            parameter_spans,
             body,
         }))
     }
-    
-    fn parse_expression(&mut self) -> Result<Expression, String> {
+
+    fn parse_expression(&mut self) -> Result<Expression, Diagnostic> {
+        self.parse_or()
+    }
+
+    /// Joins two operands; the result covers both.
+    fn binary(left: Expression, operator: BinaryOperator, right: Expression) -> Expression {
+        let span = left.span.to(right.span);
+        Expression::new(
+            ExpressionKind::BinaryOp(BinaryOperation {
+                left: Box::new(left),
+                operator,
+                right: Box::new(right),
+            }),
+            span,
+        )
+    }
+
+    fn parse_or(&mut self) -> Result<Expression, Diagnostic> {
+        let mut left = self.parse_and()?;
+
+        while matches!(self.current_token(), Token::Or) {
+            self.advance();
+            let right = self.parse_and()?;
+            left = Self::binary(left, BinaryOperator::Or, right);
+        }
+
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expression, Diagnostic> {
+        let mut left = self.parse_not()?;
+
+        while matches!(self.current_token(), Token::And) {
+            self.advance();
+            let right = self.parse_not()?;
+            left = Self::binary(left, BinaryOperator::And, right);
+        }
+
+        Ok(left)
+    }
+
+    // `not a is equal b` negates the whole comparison
+    fn parse_not(&mut self) -> Result<Expression, Diagnostic> {
+        if matches!(self.current_token(), Token::Not) {
+            let start = self.current_span().start;
+            self.advance();
+            let operand = self.parse_not()?;
+            return Ok(Expression::new(
+                ExpressionKind::Not(Box::new(operand)),
+                self.span_from(start),
+            ));
+        }
+
         self.parse_comparison()
     }
-    
-    fn parse_comparison(&mut self) -> Result<Expression, String> {
+
+    fn parse_comparison(&mut self) -> Result<Expression, Diagnostic> {
         let mut left = self.parse_arithmetic()?;
-        
+
         while let Some(op) = self.parse_comparison_operator() {
             let right = self.parse_arithmetic()?;
-            left = Expression::BinaryOp(BinaryOperation {
-                left: Box::new(left),
-                operator: op,
-                right: Box::new(right),
-            });
+            left = Self::binary(left, op, right);
         }
-        
+
         Ok(left)
     }
 
-    fn parse_arithmetic(&mut self) -> Result<Expression, String> {
+    fn parse_arithmetic(&mut self) -> Result<Expression, Diagnostic> {
         let mut left = self.parse_term()?;
-        
+
         while matches!(self.current_token(), Token::Plus | Token::Minus) {
             let op = match self.current_token() {
                 Token::Plus => {
@@ -236,21 +654,17 @@ impl Parser {
                 }
                 _ => break,
             };
-            
+
             let right = self.parse_term()?;
-            left = Expression::BinaryOp(BinaryOperation {
-                left: Box::new(left),
-                operator: op,
-                right: Box::new(right),
-            });
+            left = Self::binary(left, op, right);
         }
-        
+
         Ok(left)
     }
-    
-    fn parse_term(&mut self) -> Result<Expression, String> {
-        let mut left = self.parse_primary()?;
-        
+
+    fn parse_term(&mut self) -> Result<Expression, Diagnostic> {
+        let mut left = self.parse_postfix()?;
+
         while matches!(self.current_token(), Token::Multiply | Token::Divide) {
             let op = match self.current_token() {
                 Token::Multiply => {
@@ -263,15 +677,11 @@ impl Parser {
                 }
                 _ => break,
             };
-            
-            let right = self.parse_primary()?;
-            left = Expression::BinaryOp(BinaryOperation {
-                left: Box::new(left),
-                operator: op,
-                right: Box::new(right),
-            });
+
+            let right = self.parse_postfix()?;
+            left = Self::binary(left, op, right);
         }
-        
+
         Ok(left)
     }
 
@@ -305,23 +715,282 @@ impl Parser {
         }
     }
 
+    // `[` is left out so that `items[0]` stays an index rather than a call
+    // with a list argument; write `f ([1, 2])` to pass a list literal.
+    fn starts_argument(&self) -> bool {
+        matches!(
+            self.current_token(),
+            Token::Number(_)
+                | Token::String(_)
+                | Token::Identifier(_)
+                | Token::True
+                | Token::False
+                | Token::Nothing
+                | Token::LeftParen
+                | Token::LeftBrace
+                | Token::LengthOf
+                | Token::FirstOf
+                | Token::LastOf
+        )
+    }
+
+    /// Parses a primary expression followed by any number of `[index]`s
+    /// and `.field`s.
+    fn parse_postfix(&mut self) -> Result<Expression, Diagnostic> {
+        let mut expr = self.parse_primary()?;
+
+        loop {
+            let start = expr.span.start;
+            let kind = match self.current_token() {
+                Token::LeftBracket => {
+                    self.advance();
+                    let index = self.parse_expression()?;
+                    self.expect(Token::RightBracket)?;
+                    ExpressionKind::Index(IndexExpression {
+                        list: Box::new(expr),
+                        index: Box::new(index),
+                    })
+                }
+                Token::Dot => {
+                    let field = self.parse_field_name()?;
+                    ExpressionKind::Member(MemberAccess {
+                        object: Box::new(expr),
+                        field,
+                    })
+                }
+                _ => break,
+            };
+            expr = Expression::new(kind, self.span_from(start));
+        }
+
+        Ok(expr)
+    }
+
     // The `parse_primary` function is Generated by AI.
-    fn parse_primary(&mut self) -> Result<Expression, String> {
-        match self.current_token().clone() {
+    fn parse_primary(&mut self) -> Result<Expression, Diagnostic> {
+        let start = self.current_span().start;
+        let kind = match self.current_token().clone() {
             Token::Number(n) => {
                 self.advance();
-                Ok(Expression::Number(n))
+                ExpressionKind::Number(n)
             }
             Token::String(s) => {
                 self.advance();
-                Ok(Expression::String(s))
+                ExpressionKind::String(s)
+            }
+            Token::True | Token::False => {
+                let value = matches!(self.current_token(), Token::True);
+                self.advance();
+                ExpressionKind::Boolean(value)
+            }
+            Token::Nothing => {
+                self.advance();
+                ExpressionKind::Nothing
             }
             Token::Identifier(name) => {
                 self.advance();
-                // Check if this is a function call (basic implementation)
-                Ok(Expression::Identifier(name))
+
+                // An identifier directly followed by another operand is a call
+                // with comma-separated arguments: `greet "Bob"`, `power 2, 3`.
+                // A bare identifier may still name a zero-argument function;
+                // that is resolved once we know which names are variables.
+                if !self.starts_argument() {
+                    return Ok(Expression::new(ExpressionKind::Identifier(name), self.span_from(start)));
+                }
+
+                let mut arguments = vec![self.parse_arithmetic()?];
+                while matches!(self.current_token(), Token::Comma) {
+                    self.advance();
+                    arguments.push(self.parse_arithmetic()?);
+                }
+
+                ExpressionKind::FunctionCall(FunctionCall { name, arguments })
             }
-            _ => Err(format!("Unexpected token in expression: {:?}", self.current_token())),
-        }
+            Token::LeftParen => {
+                self.advance();
+                let expr = self.parse_expression()?;
+                self.expect(Token::RightParen)?;
+                // The span keeps the parentheses so errors point at what was written
+                return Ok(Expression::new(expr.kind, self.span_from(start)));
+            }
+            Token::LeftBracket => {
+                self.advance();
+                let mut items = Vec::new();
+                if !matches!(self.current_token(), Token::RightBracket) {
+                    items.push(self.parse_expression()?);
+                    while matches!(self.current_token(), Token::Comma) {
+                        self.advance();
+                        items.push(self.parse_expression()?);
+                    }
+                }
+                self.expect(Token::RightBracket)?;
+                ExpressionKind::List(items)
+            }
+            Token::LeftBrace => {
+                self.advance();
+                let mut fields: Vec<ObjectField> = Vec::new();
+                while !matches!(self.current_token(), Token::RightBrace) {
+                    if !fields.is_empty() {
+                        self.expect(Token::Comma)?;
+                    }
+                    let name = match self.current_token() {
+                        Token::Identifier(name) => name.clone(),
+                        _ => return Err(self.expected("a field name")),
+                    };
+                    if let Some(first) = fields.iter().find(|field| field.name == name) {
+                        return Err(Diagnostic::error(
+                            codes::DUPLICATE_FIELD,
+                            format!("Field '{}' appears more than once", name),
+                        )
+                        .with_primary(self.current_span(), "set again here")
+                        .with_secondary(first.name_span, "first set here"));
+                    }
+                    let name_span = self.current_span();
+                    self.advance();
+                    self.expect(Token::Colon)?;
+                    let value = self.parse_expression()?;
+                    fields.push(ObjectField { name, name_span, value });
+                }
+                self.expect(Token::RightBrace)?;
+                ExpressionKind::Object(fields)
+            }
+            Token::LengthOf | Token::FirstOf | Token::LastOf => {
+                let query = match self.current_token() {
+                    Token::LengthOf => ListQueryKind::Length,
+                    Token::FirstOf => ListQueryKind::First,
+                    _ => ListQueryKind::Last,
+                };
+                self.advance();
+                let list = self.parse_postfix()?;
+                ExpressionKind::ListQuery(ListQuery {
+                    query,
+                    list: Box::new(list),
+                })
+            }
+            _ => return Err(self.expected("an expression")),
+        };
+
+        Ok(Expression::new(kind, self.span_from(start)))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use delta::lexer::Lexer;
+
+    fn parse(source: &str) -> (Program, Vec<Diagnostic>) {
+        let tokens = Lexer::new(source).tokenize().unwrap();
+        let mut parser = Parser::new(tokens);
+        let program = parser.parse();
+        (program, parser.take_errors())
+    }
+
+    /// The code of each error, with the line and text its primary label
+    /// points at.
+    fn summarize<'a>(
+        errors: &[Diagnostic],
+        source: &'a str,
+    ) -> Vec<(&'static str, usize, &'a str)> {
+        errors
+            .iter()
+            .map(|error| {
+                let span = error.labels[0].span;
+                (
+                    error.code.id(),
+                    span.line_col(source).0,
+                    &source[span.start..span.end],
+                )
+            })
+            .collect()
+    }
+
+    fn is_error(statement: &Statement) -> bool {
+        matches!(statement.kind, StatementKind::Error)
+    }
+
+    #[test]
+    fn every_broken_statement_is_reported() {
+        let source = "\
+let x be
+show 1 +
+let y be 2
+end
+return y
+show y )
+";
+        let (program, errors) = parse(source);
+        assert_eq!(
+            summarize(&errors, source),
+            [
+                ("E0004", 1, "\n"),
+                ("E0004", 2, "\n"),
+                ("E0004", 4, "end"),
+                ("E0005", 5, "return"),
+                ("E0004", 6, ")"),
+            ]
+        );
+        let broken: Vec<bool> = program.statements.iter().map(is_error).collect();
+        assert_eq!(broken, [true, true, false, true, true, false, true]);
+    }
+
+    #[test]
+    fn a_block_under_a_broken_header_is_checked_on_its_own() {
+        let source = "\
+repeat while x is
+    break
+    show 1 +
+define broken with a,
+    return a
+end
+show 2
+";
+        let (program, errors) = parse(source);
+        // Neither the 'break' nor the 'return' is out of place
+        assert_eq!(
+            summarize(&errors, source),
+            [("E0004", 1, "\n"), ("E0004", 3, "\n"), ("E0004", 4, "\n")]
+        );
+        let broken: Vec<bool> = program.statements.iter().map(is_error).collect();
+        assert_eq!(broken, [true, true, false]);
+    }
+
+    #[test]
+    fn a_block_with_no_header_is_checked_and_dropped() {
+        let source = "\
+show 1
+    show 2
+    show 2 +
+show 3
+";
+        let (program, errors) = parse(source);
+        assert_eq!(
+            summarize(&errors, source),
+            [("E0004", 2, "    "), ("E0004", 3, "\n")]
+        );
+        let broken: Vec<bool> = program.statements.iter().map(is_error).collect();
+        assert_eq!(broken, [false, true, false]);
+    }
+
+    #[test]
+    fn parameters_must_be_separated_by_commas() {
+        let source = "\
+define spaced with a b
+    return a
+define bare with
+    return 1
+define sum with a, b
+    return a + b
+show 1
+";
+        let (program, errors) = parse(source);
+        assert_eq!(
+            summarize(&errors, source),
+            [("E0004", 1, "b"), ("E0004", 3, "\n")]
+        );
+        let StatementKind::FunctionDef(def) = &program.statements[2].kind else {
+            panic!("expected a definition, got {:?}", program.statements[2]);
+        };
+        assert_eq!(def.parameters, ["a", "b"]);
+    }
+}
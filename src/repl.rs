@@ -0,0 +1,314 @@
+//! `delta repl`: an interactive session where variables and functions
+//! persist from one entry to the next.
+//!
+//! An entry is one line, and the lines after it for as long as the lexer
+//! finds it unfinished: inside brackets, or in or just after the header of
+//! an indented block, which a blank line closes. The block's indentation
+//! is lexed exactly as in a file. Accepted entries are appended to one
+//! session source, and each entry's spans are shifted to point into it, so
+//! errors inside a function defined earlier still show the right line.
+//!
+//! Both backends keep their state between entries in this process. The
+//! JIT compiles only the new entry and links it to the earlier ones, as
+//! described in `codegen::jit`.
+
+use crate::ast::Program;
+use crate::codegen::{CodeGenerator, JitSession};
+use crate::interpreter::{Interpreter, Value};
+use crate::parser::Parser;
+use delta::diagnostics::Diagnostic;
+use delta::lexer::{Ending, Lexer, SpannedToken};
+use delta::span::Span;
+use inkwell::context::Context;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+use std::env;
+use std::error::Error;
+use std::path::PathBuf;
+
+const PROMPT: &str = ">>> ";
+const CONTINUATION_PROMPT: &str = "... ";
+
+/// The name diagnostics give for the session's source.
+const FILENAME: &str = "<repl>";
+
+const HELP: &str = "\
+Enter Delta statements to run them. A line that opens a block or a bracket
+continues on the next lines; finish a block with a blank line.
+
+Commands:
+  :tokens [code]  Show the tokens of the code, or of the last entry
+  :ast [code]     Show the syntax tree of the code, or of the last entry
+  :ir             Show the LLVM IR for the session so far
+  :help           Show this message
+  :quit           Leave (Ctrl-D works too)";
+
+/// Where entries run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Interpreter,
+    Jit,
+}
+
+struct Session<'ctx> {
+    interpreter: Interpreter,
+    // Set when entries run on the JIT
+    jit: Option<JitSession<'ctx>>,
+    // Every accepted entry, each ending in a newline
+    source: String,
+    last_entry: Option<String>,
+}
+
+/// Runs the REPL until the user leaves. The error is a message for the
+/// user.
+pub fn run(backend: Backend) -> Result<(), String> {
+    let mut editor =
+        DefaultEditor::new().map_err(|err| format!("Could not start the line editor: {}", err))?;
+    let history = history_path();
+    if let Some(history) = &history {
+        // There is no history yet on the first run
+        let _ = editor.load_history(history);
+    }
+
+    let name = match backend {
+        Backend::Interpreter => "interpreter",
+        Backend::Jit => "JIT",
+    };
+    println!(
+        "Delta {} ({}). Type :help for help, Ctrl-D to leave.",
+        env!("CARGO_PKG_VERSION"),
+        name
+    );
+
+    let context = Context::create();
+    let jit = match backend {
+        Backend::Interpreter => None,
+        Backend::Jit => Some(
+            JitSession::new(&context).map_err(|err| format!("Could not start the JIT: {}", err))?,
+        ),
+    };
+    let mut session = Session {
+        interpreter: Interpreter::new(),
+        jit,
+        source: String::new(),
+        last_entry: None,
+    };
+
+    loop {
+        let entry = match read_entry(&mut editor) {
+            Ok(entry) => entry,
+            // Ctrl-C drops the entry being typed
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => return Err(format!("Could not read input: {}", err)),
+        };
+        if entry.trim().is_empty() {
+            continue;
+        }
+        let _ = editor.add_history_entry(entry.as_str());
+
+        match entry.trim().strip_prefix(':') {
+            Some(command) => {
+                if !session.command(command) {
+                    break;
+                }
+            }
+            None => session.enter(&entry),
+        }
+    }
+
+    if let Some(history) = &history {
+        let _ = editor.save_history(history);
+    }
+    Ok(())
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".delta_history"))
+}
+
+/// Reads one entry: a line, and more lines while the entry is unfinished.
+/// A blank line ends an open block, but not an open bracket.
+fn read_entry(editor: &mut DefaultEditor) -> Result<String, ReadlineError> {
+    let mut entry = editor.readline(PROMPT)?;
+    if entry.trim_start().starts_with(':') {
+        return Ok(entry);
+    }
+    loop {
+        let ending = Lexer::ending(&entry);
+        if ending == Ending::Complete {
+            break;
+        }
+        let line = editor.readline(CONTINUATION_PROMPT)?;
+        if ending == Ending::InBlock && line.trim().is_empty() {
+            break;
+        }
+        entry.push('\n');
+        entry.push_str(&line);
+    }
+    Ok(entry)
+}
+
+fn report(diagnostic: &Diagnostic, source: &str) {
+    eprint!(
+        "{}",
+        diagnostic.render(FILENAME, source, crate::use_color())
+    );
+}
+
+impl Session<'_> {
+    /// Runs a `:` command. Returns false when the session should end.
+    fn command(&mut self, command: &str) -> bool {
+        let (name, argument) = match command.split_once(char::is_whitespace) {
+            Some((name, argument)) => (name, Some(argument.trim())),
+            None => (command, None),
+        };
+        let code = argument
+            .filter(|argument| !argument.is_empty())
+            .map(str::to_string)
+            .or_else(|| self.last_entry.clone());
+
+        match name {
+            "tokens" | "ast" => {
+                let Some(code) = code else {
+                    eprintln!(
+                        "Nothing entered yet; give some code, as in ':{} let x be 1'",
+                        name
+                    );
+                    return true;
+                };
+                let code = format!("{}\n", code);
+                let Some(tokens) = tokenize(&code, 0) else {
+                    return true;
+                };
+                if name == "tokens" {
+                    for token in &tokens {
+                        let (line, column) = token.span.line_col(&code);
+                        println!("{}:{}\t{:?}", line, column, token.token);
+                    }
+                } else if let Some(program) = parse(tokens, &code) {
+                    println!("{:#?}", program);
+                }
+            }
+            "ir" => match &self.jit {
+                Some(jit) => print!("{}", jit.ir()),
+                None => {
+                    let context = Context::create();
+                    if let Some(codegen) = compile(&context, &self.source) {
+                        print!("{}", codegen.ir());
+                    }
+                }
+            },
+            "help" | "h" => println!("{}", HELP),
+            "quit" | "q" | "exit" => return false,
+            other => eprintln!("Unknown command ':{}'; try :help", other),
+        }
+        true
+    }
+
+    /// Runs one entry of Delta code.
+    fn enter(&mut self, entry: &str) {
+        self.last_entry = Some(entry.to_string());
+        let text = format!("{}\n", entry);
+        let base = self.source.len();
+
+        let Some(tokens) = tokenize(&text, base) else {
+            return;
+        };
+        let combined = format!("{}{}", self.source, text);
+        let Some(program) = parse(tokens, &combined) else {
+            return;
+        };
+
+        match &mut self.jit {
+            None => {
+                let result = self.interpreter.run_more(&program);
+                // Kept even after a runtime error, as the entry may have
+                // defined functions or set variables before failing
+                self.source = combined;
+                match result {
+                    Ok(values) => print_values(&values),
+                    Err(err) => report(&err, &self.source),
+                }
+            }
+            Some(jit) => {
+                let result = jit.run(&program);
+                for warning in jit.take_warnings() {
+                    report(&warning, &combined);
+                }
+                match result {
+                    // Kept after a runtime error too, as with the interpreter
+                    Ok(_) => self.source = combined,
+                    Err(err) => report_error(err, &combined),
+                }
+            }
+        }
+    }
+}
+
+/// Lexes `text`, which starts `base` bytes into the session.
+fn tokenize(text: &str, base: usize) -> Option<Vec<SpannedToken>> {
+    match Lexer::new(text).tokenize() {
+        Ok(mut tokens) => {
+            let shift = |span: Span| Span::new(span.start + base, span.end + base);
+            for token in &mut tokens {
+                token.span = shift(token.span);
+                for comment in &mut token.comments {
+                    comment.span = shift(comment.span);
+                }
+            }
+            Some(tokens)
+        }
+        Err(err) => {
+            // Lexer errors are found before the shift, so they point into `text`
+            report(&err, text);
+            None
+        }
+    }
+}
+
+fn parse(tokens: Vec<SpannedToken>, source: &str) -> Option<Program> {
+    let mut parser = Parser::new(tokens);
+    let program = parser.parse();
+    let errors = parser.take_errors();
+    for err in &errors {
+        report(err, source);
+    }
+    errors.is_empty().then_some(program)
+}
+
+/// Compiles the whole of `source` for `:ir`, reporting any errors.
+fn compile<'ctx>(context: &'ctx Context, source: &str) -> Option<CodeGenerator<'ctx>> {
+    let program = parse(tokenize(source, 0)?, source)?;
+    let mut codegen = match CodeGenerator::new(context, "delta_repl") {
+        Ok(codegen) => codegen,
+        Err(err) => {
+            eprintln!("Failed to create code generator: {}", err);
+            return None;
+        }
+    };
+
+    match codegen.compile(&program) {
+        Ok(()) => Some(codegen),
+        Err(err) => {
+            report_error(err, source);
+            None
+        }
+    }
+}
+
+fn report_error(err: Box<dyn Error>, source: &str) {
+    match err.downcast::<Diagnostic>() {
+        Ok(diagnostic) => report(&diagnostic, source),
+        Err(err) => eprintln!("{}", err),
+    }
+}
+
+/// Echoes the values of an expression entry, like `show` would.
+fn print_values(values: &[Value]) {
+    if values.is_empty() || matches!(values, [Value::Nothing]) {
+        return;
+    }
+    let shown: Vec<String> = values.iter().map(Value::to_string).collect();
+    println!("{}", shown.join(", "));
+}
@@ -0,0 +1,305 @@
+//! Runs programs with the interpreter, the JIT and as built executables,
+//! which all have to agree on what they print.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Writes `source` to a file named after `name` and runs it with
+/// `delta run`, adding `--jit` when `jit` is set. Returns whether it
+/// succeeded, with its output and errors.
+fn run(name: &str, source: &str, jit: bool) -> (bool, String, String) {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(format!("{}.de", name));
+    fs::write(&path, source).unwrap();
+
+    let mut command = Command::new(env!("CARGO_BIN_EXE_delta"));
+    command.arg("run");
+    if jit {
+        command.arg("--jit");
+    }
+    let output = command.arg(&path).output().unwrap();
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+/// Builds `source` into an executable with `delta build` and runs it.
+/// Returns whether it succeeded, with its output and errors.
+fn build_and_run(name: &str, source: &str) -> (bool, String, String) {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(format!("{}.de", name));
+    fs::write(&path, source).unwrap();
+    let executable = path.with_extension(format!("built{}", std::env::consts::EXE_SUFFIX));
+
+    let build = Command::new(env!("CARGO_BIN_EXE_delta"))
+        .arg("build")
+        .arg("-o")
+        .arg(&executable)
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(
+        build.status.success(),
+        "the build failed:\n{}",
+        String::from_utf8_lossy(&build.stderr)
+    );
+
+    let output = Command::new(&executable).output().unwrap();
+    (
+        output.status.success(),
+        String::from_utf8_lossy(&output.stdout).into_owned(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+/// Runs `source` both ways and returns what it printed.
+fn same_output(name: &str, source: &str) -> String {
+    let (interpreted, expected, errors) = run(name, source, false);
+    assert!(interpreted, "the interpreter failed:\n{}", errors);
+    let (compiled, output, errors) = run(name, source, true);
+    assert!(compiled, "the JIT failed:\n{}", errors);
+    assert_eq!(output, expected, "the JIT printed something else");
+    output
+}
+
+/// Checks that the JIT refuses `source` with an error mentioning `message`.
+fn rejected(name: &str, source: &str, message: &str) {
+    let (compiled, _, errors) = run(name, source, true);
+    assert!(!compiled, "the JIT accepted the program");
+    assert!(errors.contains(message), "unexpected errors:\n{}", errors);
+}
+
+#[test]
+fn let_in_a_block_rebinds_a_visible_variable() {
+    let source = "\
+let total be 0
+when total is 0 then
+    let total be 10
+otherwise
+    let total be 20
+show total
+";
+    assert_eq!(same_output("rebinds", source), "10\n");
+}
+
+#[test]
+fn variables_declared_in_a_block_end_with_it() {
+    let source = "\
+when true then
+    let note be 1
+show note
+";
+    let (interpreted, _, errors) = run("block_scope", source, false);
+    assert!(!interpreted && errors.contains("note"), "{}", errors);
+    rejected("block_scope", source, "Undefined variable: note");
+}
+
+#[test]
+fn a_type_has_to_hold_on_every_path() {
+    let source = "\
+let x be 1
+when true then
+    let x be \"two\"
+show x
+";
+    rejected("branch_types", source, "'x' holds a string on one path");
+
+    let source = "\
+let x be 1
+repeat while x is 1
+    let x be \"two\"
+";
+    rejected(
+        "loop_types",
+        source,
+        "'x' holds a number when the loop starts",
+    );
+}
+
+#[test]
+fn a_loop_variable_only_shadows_inside_the_loop() {
+    let source = "\
+let n be 5
+let total be 0
+repeat for each n from 1 to 4
+    let total be total + n
+    let n be \"shadow\"
+show total
+show n
+";
+    assert_eq!(same_output("shadowing", source), "10\n5\n");
+}
+
+#[test]
+fn lists_filled_in_a_loop_are_typed_from_the_first_pass() {
+    let source = "\
+let items be []
+repeat for each n from 1 to 3
+    show items
+    add n to items
+let found be []
+repeat for each n in items
+    when n is 2 then
+        add n to found
+        break
+show found
+";
+    assert_eq!(same_output("filled", source), "[]\n[1]\n[1, 2]\n[2]\n");
+}
+
+#[test]
+fn an_empty_list_learns_its_item_type_only_through_its_own_variable() {
+    let source = "\
+let a be []
+add 1 to a
+let b be a
+add 2 to b
+let c be []
+show [c]
+show a
+";
+    assert_eq!(same_output("list_owner", source), "[[]]\n[1, 2]\n");
+
+    let source = "\
+let a be []
+let b be a
+add 1 to b
+show a
+";
+    rejected("list_alias", source, "other names hold this list");
+
+    let source = "\
+let a be []
+define fill with l
+    add 1 to l
+fill a
+show a
+";
+    rejected("list_argument", source, "other names hold this list");
+}
+
+#[test]
+fn fields_are_added_only_through_the_variable_the_object_was_made_in() {
+    let source = "\
+let o be {a: 1}
+let o.b be 2
+let p be o
+let p.a be 5
+show o
+";
+    assert_eq!(same_output("object_owner", source), "{a: 5, b: 2}\n");
+
+    let source = "\
+let o be {a: 1}
+let p be o
+let o.b be 2
+show p
+";
+    rejected("object_alias", source, "other names hold this object");
+
+    let source = "\
+let o be {a: 1}
+let o.me be o
+show o
+";
+    rejected("object_itself", source, "other names hold this object");
+}
+
+#[test]
+fn functions_that_are_never_called_are_still_compiled() {
+    let source = "\
+define sum_of with items
+    let total be 0
+    repeat for each n in items
+        let total be total + n
+    return total + items[0]
+define first_name with person
+    return person.name
+define greet with name
+    when name is equal \"\" then
+        show \"hello\"
+show \"done\"
+";
+    assert_eq!(same_output("uncalled", source), "done\n");
+
+    let source = "\
+define broken with items
+    return items[0] * \"two\"
+show \"done\"
+";
+    rejected(
+        "uncalled_error",
+        source,
+        "Expected a number for the right operand, found string",
+    );
+}
+
+#[test]
+fn a_range_too_long_to_count_is_an_error() {
+    let source = "\
+repeat for each i from 1 to 1000000000000000000000
+    show i
+    break
+";
+    let (interpreted, _, errors) = run("too_long", source, false);
+    assert!(
+        !interpreted && errors.contains("too many steps"),
+        "{}",
+        errors
+    );
+    let (compiled, _, errors) = run("too_long", source, true);
+    assert!(!compiled && errors.contains("too many steps"), "{}", errors);
+}
+
+#[test]
+fn dividing_by_zero_is_an_error() {
+    let source = "\
+let zero be 0
+show 1 / 4
+show 1 / zero
+show \"not reached\"
+";
+    let (interpreted, output, errors) = run("divide_by_zero", source, false);
+    assert!(
+        !interpreted && errors.contains("Division by zero"),
+        "{}",
+        errors
+    );
+    assert_eq!(output, "0.25\n");
+    rejected("divide_by_zero", source, "division by zero");
+    let (_, output, _) = run("divide_by_zero", source, true);
+    assert_eq!(output, "0.25\n");
+}
+
+#[test]
+fn the_jit_runs_programs_like_the_built_executable() {
+    let source = "\
+define describe with n
+    when n is greater than 2 then
+        return \"big\"
+    return \"small\"
+let items be [1.5, 2, 3]
+let total be 0
+repeat for each n in items
+    let total be total + n
+    show describe n
+show total / 3
+show items
+show {name: \"delta\", size: total}
+show items[3]
+show \"not reached\"
+";
+    let jit = run("jit_and_built", source, true);
+    let built = build_and_run("jit_and_built", source);
+    assert_eq!(jit, built);
+    let (succeeded, output, errors) = jit;
+    assert!(!succeeded);
+    assert_eq!(
+        output,
+        "small\nsmall\nbig\n2.166666666666667\n[1.5, 2, 3]\n{name: delta, size: 6.5}\n"
+    );
+    assert!(errors.contains("list index out of range"), "{}", errors);
+}
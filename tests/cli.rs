@@ -0,0 +1,156 @@
+//! Checks what `delta` reports for each kind of failure: its exit codes and
+//! the diagnostics it writes.
+
+use serde_json::{Value, json};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Writes `source` to a file named after `name` and returns its path.
+fn source_file(name: &str, source: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(format!("{}.de", name));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+/// Runs `delta` with `args` and returns its exit code and stderr.
+fn delta(args: &[&str]) -> (i32, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_delta"))
+        .args(args)
+        .output()
+        .unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn exit_codes_tell_failures_apart() {
+    let good = source_file("exit_good", "show 1\n");
+    let good = good.to_str().unwrap();
+    let bad = source_file("exit_bad", "show 1 +\n");
+    let bad = bad.to_str().unwrap();
+    let missing = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("exit_missing.de");
+    let missing = missing.to_str().unwrap();
+
+    let cases: &[(&[&str], i32)] = &[
+        (&["check", good], 0),
+        (&["check", bad], 1),
+        (&["check", "--frobnicate", good], 2),
+        (&["build", "-o", "out", good, bad], 2),
+        (&["check", missing], 3),
+        // The worst failure decides
+        (&["check", bad, missing, good], 3),
+    ];
+    for (args, expected) in cases {
+        let (code, errors) = delta(args);
+        assert_eq!(code, *expected, "{:?}:\n{}", args, errors);
+    }
+}
+
+#[test]
+fn fmt_check_reports_the_first_unformatted_line() {
+    let formatted = source_file("check_formatted", "let x be 1\nshow x + 1\n");
+    let (code, errors) = delta(&["fmt", "--check", formatted.to_str().unwrap()]);
+    assert_eq!(code, 0, "{}", errors);
+
+    let source = "let x be 1\nshow x+1\nshow x\n";
+    let path = source_file("check_unformatted", source);
+    let path = path.to_str().unwrap();
+    let (code, errors) = delta(&["fmt", "--check", path]);
+    assert_eq!(code, 1);
+    assert_eq!(
+        errors,
+        format!("{}:2: not formatted; run 'delta fmt {}'\n", path, path)
+    );
+    assert_eq!(fs::read_to_string(path).unwrap(), source);
+}
+
+#[test]
+fn json_diagnostics_give_one_object_per_line() {
+    let broken = source_file("json_broken", "show 1 +\nshow 2 )\n");
+    let broken = broken.to_str().unwrap();
+    let shared = source_file("json_shared", "let a be []\nlet b be a\nadd 1 to b\n");
+    let shared = shared.to_str().unwrap();
+    let (code, errors) = delta(&["check", "--message-format=json", broken, shared]);
+    assert_eq!(code, 1, "{}", errors);
+
+    let diagnostics: Vec<Value> = errors
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let summary: Vec<(&str, &str, &str, u64, u64)> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            (
+                diagnostic["file"].as_str().unwrap(),
+                diagnostic["severity"].as_str().unwrap(),
+                diagnostic["code"].as_str().unwrap(),
+                diagnostic["span"]["line"].as_u64().unwrap(),
+                diagnostic["span"]["column"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (broken, "error", "E0004", 1, 9),
+            (broken, "error", "E0004", 2, 8),
+            (shared, "error", "E0016", 3, 10),
+        ]
+    );
+
+    assert_eq!(
+        diagnostics[1]["message"],
+        "Expected an expression, found ')'"
+    );
+    assert_eq!(diagnostics[1]["help"], Value::Null);
+    assert_eq!(
+        diagnostics[2]["span"],
+        json!({
+            "start": 32,
+            "end": 33,
+            "line": 3,
+            "column": 10,
+            "end_line": 3,
+            "end_column": 11,
+        })
+    );
+    assert_eq!(
+        diagnostics[2]["help"],
+        "add the first item before passing the list on"
+    );
+}
+
+#[test]
+fn json_warnings_keep_every_label() {
+    let source = "define f\n    return 1\n    show 2\nshow f\n";
+    let path = source_file("json_warning", source);
+    let (code, errors) = delta(&["check", "--message-format=json", path.to_str().unwrap()]);
+    assert_eq!(code, 0, "{}", errors);
+
+    let warning: Value = serde_json::from_str(errors.trim_end()).unwrap();
+    assert_eq!(warning["severity"], "warning");
+    assert_eq!(warning["code"], "W0001");
+    assert_eq!(warning["message"], "Unreachable code");
+    let labels: Vec<(&str, bool)> = warning["labels"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|label| {
+            (
+                label["message"].as_str().unwrap(),
+                label["primary"].as_bool().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        labels,
+        [
+            ("this can never run", true),
+            ("control never gets past this", false),
+        ]
+    );
+}
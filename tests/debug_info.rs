@@ -0,0 +1,96 @@
+//! Checks the DWARF debug information `delta emit --ir -g` puts in the
+//! module.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Compiles `source` with `-g` and returns the textual IR.
+fn debug_ir(name: &str, source: &str) -> String {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(format!("{}.de", name));
+    fs::write(&path, source).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_delta"))
+        .args(["emit", "--ir", "-g", "-o", "-"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "the compile failed:\n{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// The instructions in the body of the function named `symbol`, leaving out
+/// the stack slots, which belong to no statement.
+fn instructions<'a>(ir: &'a str, symbol: &str) -> Vec<&'a str> {
+    let header = format!("@{}(", symbol);
+    ir.lines()
+        .skip_while(|line| !(line.starts_with("define") && line.contains(&header)))
+        .skip(1)
+        .take_while(|line| *line != "}")
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.ends_with(':') && !line.contains(" = alloca "))
+        .collect()
+}
+
+#[test]
+fn every_statement_gets_a_location() {
+    let source = "\
+define double with n
+    let twice be n * 2
+    return twice
+let x be double 4
+show x
+";
+    let ir = debug_ir("debug_info", source);
+
+    assert!(ir.contains("distinct !DICompileUnit("), "{}", ir);
+    assert!(ir.contains("!DIFile(filename: \"debug_info.de\""), "{}", ir);
+    for subprogram in [
+        "!DISubprogram(name: \"main\", linkageName: \"main\"",
+        "!DISubprogram(name: \"double\", linkageName: \"delta.double.number\"",
+    ] {
+        assert!(ir.contains(subprogram), "no {} in:\n{}", subprogram, ir);
+    }
+    for (name, line) in [("n", 1), ("twice", 2), ("x", 4)] {
+        let descriptor = format!("!DILocalVariable(name: \"{}\",", name);
+        let Some(found) = ir.lines().find(|text| text.contains(&descriptor)) else {
+            panic!("no descriptor for '{}' in:\n{}", name, ir);
+        };
+        assert!(found.contains(&format!(" line: {},", line)), "{}", found);
+    }
+
+    // The line of each location, by its metadata id
+    let lines: HashMap<&str, &str> = ir
+        .lines()
+        .filter_map(|line| {
+            let (id, location) = line.split_once(" = !DILocation(line: ")?;
+            Some((id, location.split(',').next()?))
+        })
+        .collect();
+    for (symbol, expected) in [
+        ("main", ["4", "5"].as_slice()),
+        ("delta.double.number", &["1", "2", "3"]),
+    ] {
+        let body = instructions(&ir, symbol);
+        assert!(!body.is_empty(), "no body for {} in:\n{}", symbol, ir);
+        let mut seen = BTreeSet::new();
+        for instruction in body {
+            let Some((_, id)) = instruction.rsplit_once("!dbg ") else {
+                panic!("no location on `{}` in {}", instruction, symbol);
+            };
+            seen.insert(lines[id]);
+        }
+        assert_eq!(
+            seen,
+            expected.iter().copied().collect(),
+            "lines of {}",
+            symbol
+        );
+    }
+}
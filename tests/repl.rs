@@ -0,0 +1,119 @@
+//! Drives `delta repl` through its standard input.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Feeds `input` to `delta repl`, with `--jit` when `jit` is set, and
+/// returns what it printed after the banner.
+fn session(input: &str, jit: bool) -> String {
+    session_with_errors(input, jit).0
+}
+
+/// Like `session`, also returning what went to stderr.
+fn session_with_errors(input: &str, jit: bool) -> (String, String) {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_delta"));
+    command.arg("repl");
+    if jit {
+        command.arg("--jit");
+    }
+    let mut child = command
+        .env_remove("HOME")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    (
+        stdout.split_once('\n').unwrap().1.to_string(),
+        String::from_utf8_lossy(&output.stderr).into_owned(),
+    )
+}
+
+#[test]
+fn entries_keep_their_effects_and_print_once() {
+    let input = "\
+let items be [1]
+define push with list
+    add 2 to list
+
+push items
+when true then
+    show \"shown once\"
+
+show items
+items
+";
+    let expected = "shown once\n[1, 2]\n[1, 2]\n";
+    assert_eq!(session(input, false), expected);
+    assert_eq!(session(input, true), expected);
+}
+
+#[test]
+fn a_runtime_error_ends_only_the_entry() {
+    let input = "\
+let count be 1
+let items be [count]
+let count be 2
+show items[5]
+show count
+";
+    let (output, errors) = session_with_errors(input, true);
+    assert_eq!(output, "2\n");
+    assert!(
+        errors.contains("Runtime error: list index out of range"),
+        "{}",
+        errors
+    );
+}
+
+#[test]
+fn a_define_replaces_the_earlier_function() {
+    let input = "\
+define greeting
+    return \"hello\"
+
+define greet
+    show greeting
+
+greet
+define greeting
+    return \"hi\"
+
+greet
+";
+    assert_eq!(session(input, false), "hello\nhi\n");
+    assert_eq!(session(input, true), "hello\nhi\n");
+}
+
+#[test]
+fn open_brackets_and_blocks_continue_the_entry() {
+    let input = "\
+let xs be [
+    1,
+
+    2
+]
+show xs
+when false then
+    show \"no\"
+otherwise
+    show \"yes\"
+
+define double with n
+    return n * 2
+end
+show double 4
+";
+    let expected = "[1, 2]\nyes\n8\n";
+    assert_eq!(session(input, false), expected);
+    assert_eq!(session(input, true), expected);
+}